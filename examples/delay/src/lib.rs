@@ -54,6 +54,10 @@ impl Plugin for Delay {
         self.buf_idx += sample_count;
         self.buf_idx %= buf_len;
     }
+
+    fn tail_samples(&self) -> usize {
+        (self.sample_rate * MAX_DELAY) as usize + 1
+    }
 }
 
 #[no_mangle]
@@ -67,6 +71,7 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                 name: "Stereo Delay",
                 maker: "Noah Weninger",
                 copyright: "None",
+                category: Some(ladspa::Category::Delay),
                 ports: vec![
                     Port {
                         name: "Left Audio In",
@@ -95,6 +100,7 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Value1),
                         lower_bound: Some(0.0),
                         upper_bound: Some(MAX_DELAY),
+                        optional: false,
                     },
                     Port {
                         name: "Right Delay (seconds)",
@@ -103,6 +109,7 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Value1),
                         lower_bound: Some(0.0),
                         upper_bound: Some(MAX_DELAY),
+                        optional: false,
                     },
                     Port {
                         name: "Left Dry/Wet",
@@ -111,6 +118,7 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Middle),
                         lower_bound: Some(0.0),
                         upper_bound: Some(1.0),
+                        optional: false,
                     },
                     Port {
                         name: "Right Dry/Wet",
@@ -119,6 +127,7 @@ pub fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                         default: Some(DefaultValue::Middle),
                         lower_bound: Some(0.0),
                         upper_bound: Some(1.0),
+                        optional: false,
                     },
                 ],
                 new: new_delay,
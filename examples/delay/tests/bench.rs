@@ -0,0 +1,54 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::{own_plugins, ControlError};
+use ladspa::testing::bench::{self, Bench, Measurement};
+use ladspa::PortDescriptor;
+
+#[test]
+fn baseline_round_trip() {
+    let results = Bench::new(own_plugins()[0])
+        .block_sizes(&[64, 1000])
+        .preset("wet \"and\" short\n", &[(4, 0.01), (6, 1.0)])
+        .seconds(0.05)
+        .run();
+    assert_eq!(results.len(), 2);
+    let json = bench::to_json(&results);
+    assert_eq!(bench::from_json(&json).unwrap(), results);
+    assert!(bench::compare(&results, &results, 0.0).is_empty());
+}
+
+#[test]
+fn baseline_format() {
+    let json = "[\n  {\"block_size\": 64, \"sample_rate\": 48000, \"preset\": \"default\", \"samples\": 480000, \
+                \"ns_per_sample\": 12.5, \"realtime_factor\": 1666.6, \"cycles_per_sample\": null}\n]\n";
+    let expected = Measurement {
+        block_size: 64,
+        sample_rate: 48000,
+        preset: "default".to_string(),
+        samples: 480000,
+        ns_per_sample: 12.5,
+        realtime_factor: 1666.6,
+        cycles_per_sample: None,
+    };
+    assert_eq!(bench::from_json(json).unwrap(), vec![expected.clone()]);
+    assert_eq!(bench::to_json(&[expected]), json);
+    assert_eq!(bench::from_json(" [ ] ").unwrap(), vec![]);
+
+    assert!(bench::from_json("").is_err());
+    assert!(bench::from_json("[{\"block_size\": \"64\"}]").is_err());
+    assert!(bench::from_json("[{\"block_size\": null}]").is_err());
+    assert!(bench::from_json("[{\"speed\": 1.0}]").is_err());
+    assert!(bench::from_json("[{\"preset\": \"default}]").is_err());
+    assert!(bench::from_json("[] []").is_err());
+}
+
+#[test]
+fn set_control_rejects_other_ports() {
+    let mut instance = own_plugins()[0].instantiate(48000).unwrap();
+    assert_eq!(instance.set_control(8, 1.0), Err(ControlError::OutOfRange { port: 8, ports: 8 }));
+    assert_eq!(instance.set_control(2, 1.0),
+               Err(ControlError::NotAControlInput { port: 2, desc: PortDescriptor::AudioOutput }));
+    assert_eq!(instance.set_control(6, 1.0), Ok(()));
+    assert_eq!(instance.control(6), 1.0);
+}
@@ -0,0 +1,67 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::{own_plugins, PluginRef};
+use ladspa::testing::blocksize::{self, random_block_sizes, MAX_RANDOM_BLOCK_SIZE};
+use ladspa::testing::{plugin_ref, signal};
+use ladspa::{Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+
+// Fades every block in from silence, so its output depends on the block sizes.
+struct Fade;
+
+impl Plugin for Fade {
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        for i in 0..sample_count {
+            output[i] = input[i] * i as f32 / sample_count as f32;
+        }
+    }
+}
+
+fn new_fade(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Fade)
+}
+
+fn fade() -> PluginRef {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9003,
+        label: "fade",
+        properties: Properties::PROP_HARD_REALTIME_CAPABLE,
+        name: "Fade",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() }],
+        new: new_fade,
+    })
+}
+
+#[test]
+fn delay_is_independent_of_block_sizes() {
+    // At 1 kHz the default delay of a second shows up in the render.
+    blocksize::check_noise(own_plugins()[0], 1000, 5000, 0.0).unwrap();
+}
+
+#[test]
+fn divergence_is_reported() {
+    let divergence = blocksize::check(fade(), 48000, &[signal::dc(100, 1.0)], 100, 0.0).unwrap_err();
+    assert_eq!(divergence.block_sizes, "1-sample");
+    assert_eq!((divergence.difference.output, divergence.difference.sample), (0, 1));
+    assert_eq!((divergence.difference.expected, divergence.difference.actual), (0.01, 0.0));
+    assert_eq!(divergence.to_string(),
+               "with 1-sample blocks, output 0 differs at sample 1: expected 0.01, got 0.0");
+}
+
+#[test]
+fn random_block_sizes_cover_the_length() {
+    for seed in 1..8 {
+        let sizes = random_block_sizes(seed, 5000);
+        assert!(sizes.iter().sum::<usize>() >= 5000);
+        assert!(sizes.iter().sum::<usize>() - sizes.last().unwrap() < 5000);
+        assert!(sizes.iter().all(|&x| x >= 1 && x <= MAX_RANDOM_BLOCK_SIZE));
+        assert_eq!(random_block_sizes(seed, 5000), sizes);
+    }
+    assert_ne!(random_block_sizes(1, 5000), random_block_sizes(2, 5000));
+}
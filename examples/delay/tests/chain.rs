@@ -0,0 +1,88 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::chain::{find_control, Chain, Error, Stage};
+use ladspa::host::{own_plugins, PluginRef};
+use ladspa::{Data, Properties};
+use std::env;
+use std::ptr;
+
+// Fully wet with both channels delayed by 48 samples at 48 kHz.
+fn short_delay(plugin: PluginRef) -> Stage {
+    Stage { plugin, controls: vec![(4, 0.001), (5, 0.001), (6, 1.0), (7, 1.0)] }
+}
+
+fn impulse(length: usize) -> Vec<Data> {
+    let mut x = vec![0.0; length];
+    x[0] = 1.0;
+    x
+}
+
+fn peak(x: &[Data]) -> usize {
+    x.iter().position(|&x| x == 1.0).unwrap()
+}
+
+#[test]
+fn serial_delay() {
+    let delay = own_plugins()[0];
+    let mut chain = Chain::new(&[short_delay(delay), short_delay(delay)], 48000).unwrap();
+    assert_eq!((chain.inputs(), chain.outputs()), (2, 2));
+    // Both stages process in place.
+    assert_eq!(chain.buffers(), 2);
+
+    let input = impulse(4096);
+    let outputs = chain.render(&[input.clone(), input], 4096, 100);
+    assert_eq!(peak(&outputs[0]), 96);
+    assert_eq!(peak(&outputs[1]), 96);
+}
+
+#[test]
+fn inplace_broken() {
+    let delay = own_plugins()[0];
+    let mut descriptor = unsafe { ptr::read(delay.0) };
+    descriptor.properties |= Properties::PROP_INPLACE_BROKEN.bits();
+    let broken = PluginRef(Box::leak(Box::new(descriptor)));
+
+    let stages = [short_delay(broken), short_delay(broken), short_delay(broken)];
+    let mut chain = Chain::new(&stages, 48000).unwrap();
+    // Each stage writes to a fresh pair of buffers, and the third reuses the first pair.
+    assert_eq!(chain.buffers(), 4);
+    let outputs = chain.render(&[impulse(4096)], 4096, 4096);
+    assert_eq!(peak(&outputs[0]), 144);
+    assert!(outputs[1].iter().all(|&x| x == 0.0));
+}
+
+#[test]
+fn controls() {
+    let delay = own_plugins()[0];
+    assert_eq!(find_control(delay, "left"), Some(4));
+    assert_eq!(find_control(delay, "Right Delay (seconds)"), Some(5));
+    assert_eq!(find_control(delay, "right_dry"), Some(7));
+    assert_eq!(find_control(delay, "6"), Some(6));
+    assert_eq!(find_control(delay, "0"), None);
+    assert_eq!(find_control(delay, "lef"), None);
+}
+
+#[test]
+fn parse() {
+    // Cargo builds the plugin library next to the deps directory holding this test.
+    let library = env::current_exe().unwrap().parent().unwrap().parent().unwrap().join("librustdelay.so");
+    let library = library.to_str().unwrap();
+    let spec = format!("{0}:stereo_delay left=0.001 right=0.001 left_dry=1 right_dry=1 ! {0}:stereo_delay 5=0.002 6=0.5 7=1", library);
+    let mut chain = Chain::parse(&spec, 48000).unwrap();
+    let input = impulse(4096);
+    let outputs = chain.render(&[input.clone(), input], 4096, 512);
+    assert_eq!(peak(&outputs[1]), 144);
+    assert!((outputs[0][48] - 0.5).abs() < 1e-6);
+
+    match Chain::parse(&format!("{}:stereo_delay feedback=1", library), 48000) {
+        Err(Error::UnknownControl { control, .. }) => assert_eq!(control, "feedback"),
+        _ => panic!("expected UnknownControl"),
+    }
+    match Chain::parse(&format!("{}:mono_delay", library), 48000) {
+        Err(Error::UnknownPlugin { label, .. }) => assert_eq!(label, "mono_delay"),
+        _ => panic!("expected UnknownPlugin"),
+    }
+    assert!(matches!(Chain::parse("stereo_delay", 48000), Err(Error::Syntax(_))));
+    assert!(matches!(Chain::new(&[], 48000), Err(Error::Empty)));
+}
@@ -0,0 +1,98 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::clap::clap_entry;
+use ladspa::clap::host::{Host, ParamEvent};
+
+#[test]
+fn round_trip() {
+    let mut host = Host::new(&clap_entry, "ladspa.400").expect("create_plugin failed");
+    // Listing the plugins inits and deinits the entry again while the instance is alive.
+    assert_eq!(Host::plugin_ids(&clap_entry), vec!["ladspa.400".to_string()]);
+
+    let params = host.params();
+    assert_eq!(params.iter().map(|x| x.id).collect::<Vec<_>>(), vec![4, 5, 6, 7]);
+    assert_eq!(params[2].name, "Left Dry/Wet");
+    assert_eq!(params[2].default_value, 0.5);
+    assert_eq!(host.audio_ports(true), vec![2]);
+    assert_eq!(host.audio_ports(false), vec![2]);
+
+    assert!(host.activate(48000.0, 64));
+    let input = [1.0; 64];
+    let mut left = [0.0; 64];
+    let mut right = [0.0; 64];
+    // Fully dry on the left from sample 32; both channels start half dry with an empty delay line.
+    host.process(64, &[&input, &input], &mut [&mut left, &mut right],
+                 &[ParamEvent { time: 32, param_id: 6, value: 0.0 }]);
+    assert!(left[..32].iter().all(|&x| x == 0.5), "{:?}", &left[..]);
+    assert!(left[32..].iter().all(|&x| x == 1.0), "{:?}", &left[..]);
+    assert!(right.iter().all(|&x| x == 0.5), "{:?}", &right[..]);
+    assert_eq!(host.param_value(6), Some(0.0));
+    host.deactivate();
+    drop(host);
+
+    // Every init has been matched by a deinit, so this starts from scratch.
+    assert!(Host::new(&clap_entry, "ladspa.400").is_some());
+}
+
+#[test]
+fn main_feature() {
+    use ladspa::clap::features;
+    use ladspa::{Category, PluginDescriptor, PortDescriptor};
+
+    let delay = rustdelay::get_ladspa_descriptor(0).unwrap();
+    assert_eq!(features(&delay), vec!["audio-effect", "stereo", "delay"]);
+
+    let ports = delay.ports.iter().filter(|x| x.desc != PortDescriptor::AudioInput).cloned().collect();
+    let generator = PluginDescriptor { ports, category: None, ..delay };
+    assert_eq!(features(&generator)[0], "instrument");
+    let oscillator = PluginDescriptor { category: Some(Category::Oscillator), ..generator };
+    assert_eq!(features(&oscillator)[0], "instrument");
+    let delay_line = PluginDescriptor { category: Some(Category::Delay), ..oscillator };
+    assert_eq!(features(&delay_line)[0], "audio-effect");
+    let meter = PluginDescriptor { category: Some(Category::FrequencyMeter), ..delay_line };
+    assert_eq!(features(&meter), vec!["analyzer", "stereo"]);
+}
+
+#[test]
+fn reset_asks_for_a_restart() {
+    let mut host = Host::new(&clap_entry, "ladspa.400").unwrap();
+    assert!(host.activate(48000.0, 64));
+    let mut impulse = [0.0; 32];
+    impulse[0] = 1.0;
+    let mut left = [0.0; 32];
+    let mut right = [0.0; 32];
+    // Fully wet with a 48 sample delay, so the echo falls into the next block.
+    let events = [ParamEvent { time: 0, param_id: 4, value: 0.001 },
+                  ParamEvent { time: 0, param_id: 6, value: 1.0 }];
+    host.process(32, &[&impulse, &impulse], &mut [&mut left, &mut right], &events);
+
+    // Plugin::activate may allocate, so reset leaves it to the host.
+    host.reset();
+    assert!(host.take_restart_request());
+    assert!(!host.take_restart_request());
+    host.deactivate();
+    assert!(host.activate(48000.0, 64));
+    let silence = [0.0; 32];
+    host.process(32, &[&silence, &silence], &mut [&mut left, &mut right], &[]);
+    assert!(left.iter().all(|&x| x == 0.0), "{:?}", &left[..]);
+    assert_eq!(host.param_value(6), Some(1.0));
+}
+
+#[test]
+fn entry_init_from_several_threads() {
+    let mut host = Host::new(&clap_entry, "ladspa.400").unwrap();
+    let threads: Vec<_> = (0..8)
+        .map(|_| std::thread::spawn(|| {
+            for _ in 0..50 {
+                assert_eq!(Host::plugin_ids(&clap_entry).len(), 1);
+            }
+        }))
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    // The instance still points into the registered plugins.
+    assert!(host.activate(48000.0, 64));
+    assert_eq!(host.params().len(), 4);
+}
@@ -0,0 +1,28 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::own_plugins;
+use ladspa::testing::compare::{Alignment, Comparison};
+
+#[test]
+fn nulls_against_itself() {
+    let plugin = own_plugins()[0];
+    let report = Comparison::new(plugin, plugin)
+        .length(8192)
+        .controls(&[(4, 0.01), (5, 0.02)])
+        .alignment(Alignment::CrossCorrelation { max_lag: 1024 })
+        .run();
+    assert_eq!(report.lag, 0);
+    assert_eq!(report.outputs.len(), 2);
+    assert!(report.outputs.iter().all(|x| x.peak_difference == 0.0), "{}", report);
+}
+
+#[test]
+fn empty_render_with_cross_correlation() {
+    let plugin = own_plugins()[0];
+    let report = Comparison::new(plugin, plugin)
+        .inputs(vec![Vec::new(), Vec::new()])
+        .alignment(Alignment::CrossCorrelation { max_lag: 1024 })
+        .run();
+    assert_eq!(report.lag, 0);
+}
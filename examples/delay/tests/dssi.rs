@@ -0,0 +1,165 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::dssi::{dssi_descriptor, dssi_h, MidiController, MidiEvent, MidiMessage, SynthPlugin};
+use ladspa::ffi::ladspa_h;
+use ladspa::{Plugin, PluginDescriptor, PortConnection};
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::Mutex;
+
+static RECEIVED: Mutex<Vec<(usize, Option<MidiMessage>)>> = Mutex::new(Vec::new());
+
+// Records the events it receives and maps the delay times onto a CC and an NRPN.
+struct Recorder;
+
+impl Plugin for Recorder {
+    fn run<'a>(&mut self, _: usize, _: &[&'a PortConnection<'a>]) {
+        panic!("run called instead of run_synth");
+    }
+
+    fn as_synth(&mut self) -> Option<&mut dyn SynthPlugin> {
+        Some(self)
+    }
+}
+
+impl SynthPlugin for Recorder {
+    fn run_synth<'a>(&mut self, _: usize, ports: &[&'a PortConnection<'a>], events: &[MidiEvent]) {
+        let mut out = ports[2].unwrap_audio_mut();
+        for event in events {
+            out[event.frame()] = 1.0;
+        }
+        RECEIVED.lock().unwrap().extend(events.iter().map(|e| (e.frame(), e.message())));
+    }
+
+    fn midi_controller(&self, port: usize) -> Option<MidiController> {
+        match port {
+            4 => Some(MidiController::Cc(7)),
+            5 => Some(MidiController::Nrpn(1000)),
+            _ => None,
+        }
+    }
+}
+
+fn new_recorder(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Recorder)
+}
+
+// The delay's LADSPA descriptor, creating the plugin with new instead.
+unsafe fn descriptor(new: fn(&PluginDescriptor, u64) -> Box<dyn Plugin + Send>) -> &'static ladspa_h::Descriptor {
+    let mut rust_desc = rustdelay::get_ladspa_descriptor(0).unwrap();
+    rust_desc.new = new;
+    let mut desc = ptr::read((*dssi_descriptor(0)).ladspa_plugin);
+    desc.implementation_data = Box::leak(Box::new(rust_desc)) as *mut PluginDescriptor as *mut c_void;
+    Box::leak(Box::new(desc))
+}
+
+fn note(type_: u8, tick: u32, channel: u8, note: u8, velocity: u8) -> dssi_h::SeqEvent {
+    let mut event: dssi_h::SeqEvent = unsafe { mem::zeroed() };
+    event.type_ = type_;
+    event.tick = tick;
+    event.data.note = dssi_h::Note { channel, note, velocity, off_velocity: 0, duration: 0 };
+    event
+}
+
+fn control(type_: u8, tick: u32, channel: u8, param: u32, value: i32) -> dssi_h::SeqEvent {
+    let mut event: dssi_h::SeqEvent = unsafe { mem::zeroed() };
+    event.type_ = type_;
+    event.tick = tick;
+    event.data.control = dssi_h::Ctrl { channel, unused: [0; 3], param, value };
+    event
+}
+
+#[test]
+fn midi_controller_of_null_instance() {
+    unsafe {
+        let descriptor = &*dssi_descriptor(0);
+        let get_midi_controller = descriptor.get_midi_controller_for_port.unwrap();
+        assert_eq!(get_midi_controller(ptr::null_mut(), 4), dssi_h::NONE);
+    }
+}
+
+#[test]
+fn run_synth_decodes_events() {
+    unsafe {
+        let dssi = &*dssi_descriptor(0);
+        let desc = descriptor(new_recorder);
+        let instance = desc.instantiate.unwrap()(desc, 44100);
+        assert!(!instance.is_null());
+
+        let get_midi_controller = dssi.get_midi_controller_for_port.unwrap();
+        assert_eq!(get_midi_controller(instance, 4), dssi_h::CC_BITS | 7);
+        assert_eq!(get_midi_controller(instance, 5), dssi_h::NRPN_BITS | (1000 << 7));
+        assert_eq!(get_midi_controller(instance, 6), dssi_h::NONE);
+
+        let mut audio = vec![vec![0.0; 16]; 4];
+        let mut controls = [1.0, 1.0, 0.5, 0.5];
+        for (i, buffer) in audio.iter_mut().enumerate() {
+            desc.connect_port.unwrap()(instance, i as _, buffer.as_mut_ptr());
+        }
+        for (i, value) in controls.iter_mut().enumerate() {
+            desc.connect_port.unwrap()(instance, (i + 4) as _, value);
+        }
+        desc.activate.unwrap()(instance);
+
+        let mut events = [note(dssi_h::EVENT_NOTEON, 0, 1, 60, 100),
+                          note(dssi_h::EVENT_NOTEON, 2, 1, 60, 0),
+                          note(dssi_h::EVENT_NOTEOFF, 3, 2, 64, 40),
+                          note(dssi_h::EVENT_KEYPRESS, 4, 0, 62, 90),
+                          control(dssi_h::EVENT_CONTROLLER, 5, 3, 74, 127),
+                          control(dssi_h::EVENT_PGMCHANGE, 6, 0, 0, 12),
+                          control(dssi_h::EVENT_CHANPRESS, 7, 15, 0, 55),
+                          control(dssi_h::EVENT_PITCHBEND, 8, 0, 0, -8192),
+                          note(0, 9, 0, 0, 0)];
+        dssi.run_synth.unwrap()(instance, 16, events.as_mut_ptr(), events.len() as _);
+
+        let received = RECEIVED.lock().unwrap().clone();
+        assert_eq!(received,
+                   vec![(0, Some(MidiMessage::NoteOn { channel: 1, note: 60, velocity: 100 })),
+                        (2, Some(MidiMessage::NoteOff { channel: 1, note: 60, velocity: 0 })),
+                        (3, Some(MidiMessage::NoteOff { channel: 2, note: 64, velocity: 40 })),
+                        (4, Some(MidiMessage::KeyPressure { channel: 0, note: 62, pressure: 90 })),
+                        (5, Some(MidiMessage::Controller { channel: 3, controller: 74, value: 127 })),
+                        (6, Some(MidiMessage::ProgramChange { channel: 0, program: 12 })),
+                        (7, Some(MidiMessage::ChannelPressure { channel: 15, pressure: 55 })),
+                        (8, Some(MidiMessage::PitchBend { channel: 0, value: -8192 })),
+                        (9, None)]);
+        let hits: Vec<usize> = (0..16).filter(|&i| audio[2][i] == 1.0).collect();
+        assert_eq!(hits, vec![0, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        desc.deactivate.unwrap()(instance);
+        desc.cleanup.unwrap()(instance);
+    }
+}
+
+#[test]
+fn run_synth_runs_effects() {
+    unsafe {
+        let dssi = &*dssi_descriptor(0);
+        let desc = &*dssi.ladspa_plugin;
+        let instance = desc.instantiate.unwrap()(desc, 44100);
+        assert_eq!(dssi.get_midi_controller_for_port.unwrap()(instance, 4), dssi_h::NONE);
+
+        let mut audio = vec![vec![0.0; 8]; 4];
+        audio[0][0] = 1.0;
+        audio[1][0] = 1.0;
+        let mut controls = [0.0, 0.0, 0.0, 0.0];
+        for (i, buffer) in audio.iter_mut().enumerate() {
+            desc.connect_port.unwrap()(instance, i as _, buffer.as_mut_ptr());
+        }
+        for (i, value) in controls.iter_mut().enumerate() {
+            desc.connect_port.unwrap()(instance, (i + 4) as _, value);
+        }
+        desc.activate.unwrap()(instance);
+        let mut events = [note(dssi_h::EVENT_NOTEON, 0, 0, 60, 100)];
+        dssi.run_synth.unwrap()(instance, 8, events.as_mut_ptr(), 1);
+
+        // With the dry/wet at fully dry, the delay passes its input through.
+        assert_eq!(audio[2], audio[0]);
+        assert_eq!(audio[3], audio[1]);
+
+        desc.deactivate.unwrap()(instance);
+        desc.cleanup.unwrap()(instance);
+    }
+}
@@ -0,0 +1,35 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::own_plugins;
+use ladspa::testing::fuzz::{call_sequence, call_sequence_seed, run, run_seed};
+
+#[test]
+fn seeds() {
+    let plugin = own_plugins()[0];
+    for seed in 0..20 {
+        if let Err(failure) = run_seed(plugin, 44100, seed) {
+            panic!("seed {}: {}", seed, failure);
+        }
+    }
+}
+
+#[test]
+fn call_sequences() {
+    let plugin = own_plugins()[0];
+    for seed in 0..200 {
+        if let Err(failure) = call_sequence_seed(plugin, 44100, seed) {
+            panic!("seed {}: {}", seed, failure);
+        }
+    }
+}
+
+#[test]
+fn bytes() {
+    let plugin = own_plugins()[0];
+    // Short and empty inputs read as zeros.
+    for data in [&[][..], &[1], &[0xff; 64]] {
+        assert_eq!(run(plugin, 44100, data), Ok(()));
+        assert_eq!(call_sequence(plugin, 44100, data), Ok(()));
+    }
+}
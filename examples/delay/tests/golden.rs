@@ -0,0 +1,56 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::own_plugins;
+use ladspa::host::wav::Wav;
+use ladspa::testing::golden::{Failure, Golden, Input, UPDATE_VAR};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ladspa-rs-golden-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn missing_references_are_only_written_on_update() {
+    let dir = temp_dir("update");
+    let golden = Golden::new(own_plugins()[0], &dir).length(4096).preset("short", &[(4, 0.01), (5, 0.02)]);
+    match golden.check() {
+        Err(Failure::Missing { reference }) => assert_eq!(reference, golden.reference(Input::Impulse)),
+        other => panic!("expected a missing reference, got {:?}", other),
+    }
+    assert!(!golden.reference(Input::Impulse).exists());
+
+    env::set_var(UPDATE_VAR, "1");
+    let written = golden.check();
+    env::remove_var(UPDATE_VAR);
+    written.unwrap();
+    for input in Input::ALL {
+        assert!(golden.reference(input).exists());
+    }
+    golden.check().unwrap();
+
+    let other = Golden::new(own_plugins()[0], &dir).length(4096).preset("short", &[(4, 0.02), (5, 0.02)]);
+    match other.check() {
+        Err(Failure::Mismatch(diff)) => assert!(diff.error_db.is_finite(), "{}", diff),
+        other => panic!("expected a mismatch, got {:?}", other),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn silent_reference() {
+    let dir = temp_dir("silent");
+    let golden = Golden::new(own_plugins()[0], &dir).length(1024);
+    let silence = Wav { sample_rate: 48000, channels: vec![vec![0.0; 1024]; 2] };
+    silence.write(&golden.reference(Input::Impulse)).unwrap();
+    match golden.check() {
+        Err(Failure::Mismatch(diff)) => assert_eq!(diff.error_db, f64::INFINITY),
+        other => panic!("expected a mismatch, got {:?}", other),
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,89 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::graph::{Error, Graph};
+use ladspa::host::own_plugins;
+use ladspa::{Data, PortDescriptor};
+
+// Fully wet, delaying both channels by the given number of samples at 48 kHz.
+fn delay(samples: usize) -> Vec<(usize, Data)> {
+    let seconds = samples as Data / 48000.0;
+    vec![(4, seconds), (5, seconds), (6, 1.0), (7, 1.0)]
+}
+
+fn impulse(length: usize) -> Vec<Data> {
+    let mut x = vec![0.0; length];
+    x[0] = 1.0;
+    x
+}
+
+// Half-scale white noise from a xorshift generator.
+fn noise(length: usize) -> Vec<Data> {
+    let mut state = 0x9e3779b97f4a7c15u64;
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as Data / (1u64 << 24) as Data - 0.5
+        })
+        .collect()
+}
+
+// input -> a -> b, c -> d -> output, with b and c summed into d.
+fn diamond() -> Graph {
+    let plugin = own_plugins()[0];
+    let mut graph = Graph::new(48000);
+    let a = graph.add(plugin, &delay(48)).unwrap();
+    let b = graph.add(plugin, &delay(48)).unwrap();
+    let c = graph.add(plugin, &delay(96)).unwrap();
+    let d = graph.add(plugin, &delay(48)).unwrap();
+    graph.connect_input(0, a, 0).unwrap();
+    graph.connect(a, 2, b, 0).unwrap();
+    graph.connect(a, 2, c, 0).unwrap();
+    graph.connect(b, 2, d, 0).unwrap();
+    graph.connect(c, 2, d, 0).unwrap();
+    graph.connect_output(d, 2, 0).unwrap();
+    graph.connect_output(a, 2, 1).unwrap();
+    graph
+}
+
+#[test]
+fn diamond_sums_branches() {
+    let mut processor = diamond().compile(4).unwrap();
+    assert_eq!(processor.schedule(), &[vec![0], vec![1, 2], vec![3]]);
+    // One buffer for the input, two per node and one for the sum would be ten; the rest are
+    // reused once their last reader has run.
+    assert_eq!(processor.buffers(), 6);
+
+    let outputs = processor.render(&[impulse(4096)], 4096, 256);
+    let peaks: Vec<usize> = outputs[0].iter().enumerate().filter(|x| *x.1 != 0.0).map(|x| x.0).collect();
+    assert_eq!(peaks, vec![144, 192]);
+    assert_eq!(outputs[1].iter().position(|&x| x != 0.0), Some(48));
+
+    let timings = processor.timings();
+    assert_eq!(timings.len(), 4);
+    assert!(timings.iter().all(|x| x.blocks == 16 && x.label == "stereo_delay"), "{:?}", timings);
+}
+
+#[test]
+fn threads_do_not_change_the_output() {
+    let input = noise(10000);
+    let single = diamond().compile(1).unwrap().render(&[input.clone()], 10000, 333);
+    let parallel = diamond().compile(3).unwrap().render(&[input], 10000, 333);
+    assert!(single == parallel);
+}
+
+#[test]
+fn errors() {
+    let plugin = own_plugins()[0];
+    let mut graph = Graph::new(48000);
+    let a = graph.add(plugin, &[]).unwrap();
+    let b = graph.add(plugin, &[]).unwrap();
+    assert_eq!(graph.connect(a, 0, b, 0), Err(Error::BadPort { node: a, port: 0, expected: PortDescriptor::AudioOutput }));
+    assert_eq!(graph.connect(a, 2, 5, 0), Err(Error::UnknownNode(5)));
+    assert_eq!(graph.add(plugin, &[(2, 1.0)]).err(), Some(Error::BadPort { node: 2, port: 2, expected: PortDescriptor::ControlInput }));
+    graph.connect(a, 2, b, 0).unwrap();
+    graph.connect(b, 3, a, 1).unwrap();
+    assert!(matches!(graph.compile(2), Err(Error::Cycle)));
+}
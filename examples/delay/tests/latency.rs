@@ -0,0 +1,145 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::chain::{Chain, Stage};
+use ladspa::host::graph::Graph;
+use ladspa::host::PluginRef;
+use ladspa::testing::{plugin_ref, signal};
+use ladspa::{Data, DefaultValue, Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+
+const HISTORY: usize = 1024;
+
+// Delays its input by the number of samples set on port 2 and reports that as its latency.
+struct Late {
+    history: Vec<Data>,
+    pos: usize,
+    samples: usize,
+}
+
+impl Plugin for Late {
+    fn activate(&mut self) {
+        self.history.iter_mut().for_each(|x| *x = 0.0);
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        self.samples = (*ports[2].unwrap_control() as usize).min(HISTORY - 1);
+        for i in 0..sample_count {
+            self.history[self.pos] = input[i];
+            output[i] = self.history[(self.pos + HISTORY - self.samples) % HISTORY];
+            self.pos = (self.pos + 1) % HISTORY;
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.samples
+    }
+}
+
+fn new_late(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Late { history: vec![0.0; HISTORY], pos: 0, samples: 0 })
+}
+
+fn late() -> PluginRef {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9000,
+        label: "late",
+        properties: Properties::PROP_HARD_REALTIME_CAPABLE,
+        name: "Late",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() },
+                    Port {
+                        name: "Samples",
+                        desc: PortDescriptor::ControlInput,
+                        default: Some(DefaultValue::Minimum),
+                        lower_bound: Some(0.0),
+                        upper_bound: Some((HISTORY - 1) as Data),
+                        ..Default::default()
+                    },
+                    Port::latency()],
+        new: new_late,
+    })
+}
+
+fn peaks(x: &[Data]) -> Vec<(usize, Data)> {
+    x.iter().enumerate().filter(|(_, &y)| y != 0.0).map(|(i, &y)| (i, y)).collect()
+}
+
+#[test]
+fn instance_reports_latency_after_run() {
+    let mut instance = late().instantiate(48000).unwrap();
+    instance.set_control(2, 12.0).unwrap();
+    assert_eq!(instance.latency(), 0);
+    instance.activate();
+    let output = instance.render(&[signal::impulse(64)], 64, [16]);
+    assert_eq!(instance.latency(), 12);
+    assert_eq!(peaks(&output[0]), vec![(12, 1.0)]);
+}
+
+#[test]
+fn chain_removes_latency() {
+    let stage = |samples: Data| Stage { plugin: late(), controls: vec![(2, samples)] };
+    let mut chain = Chain::new(&[stage(5.0), stage(7.0)], 48000).unwrap();
+    assert_eq!(chain.latency(), 12);
+    for block_size in [1, 5, 64] {
+        let outputs = chain.render(&[signal::impulse(100)], 100, block_size);
+        assert_eq!(outputs[0].len(), 100);
+        assert_eq!(peaks(&outputs[0]), vec![(0, 1.0)], "block size {}", block_size);
+    }
+}
+
+#[test]
+fn graph_aligns_summed_branches() {
+    let mut graph = Graph::new(48000);
+    let slow = graph.add(late(), &[(2, 10.0)]).unwrap();
+    let fast = graph.add(late(), &[(2, 3.0)]).unwrap();
+    let direct = graph.add(late(), &[]).unwrap();
+    let sum = graph.add(late(), &[(2, 1.0)]).unwrap();
+    for node in [slow, fast, direct] {
+        graph.connect_input(0, node, 0).unwrap();
+        graph.connect(node, 1, sum, 0).unwrap();
+    }
+    graph.connect_output(sum, 1, 0).unwrap();
+    let mut processor = graph.compile(2).unwrap();
+    assert_eq!(processor.latency(), 11);
+
+    let outputs = processor.render(&[signal::impulse(64)], 64, 16);
+    assert_eq!(outputs[0].len(), 64);
+    assert_eq!(peaks(&outputs[0]), vec![(0, 3.0)]);
+}
+
+#[test]
+fn graph_aligns_outputs() {
+    let mut graph = Graph::new(48000);
+    let slow = graph.add(late(), &[(2, 4.0)]).unwrap();
+    let fast = graph.add(late(), &[]).unwrap();
+    for (output, node) in vec![slow, fast].into_iter().enumerate() {
+        graph.connect_input(0, node, 0).unwrap();
+        graph.connect_output(node, 1, output).unwrap();
+    }
+    // Output 0 also takes the input directly.
+    let pass = graph.add(late(), &[]).unwrap();
+    graph.connect_input(0, pass, 0).unwrap();
+    graph.connect_output(pass, 1, 0).unwrap();
+    let mut processor = graph.compile(1).unwrap();
+    assert_eq!(processor.latency(), 4);
+
+    let outputs = processor.render(&[signal::impulse(32)], 32, 7);
+    assert_eq!(peaks(&outputs[0]), vec![(0, 2.0)]);
+    assert_eq!(peaks(&outputs[1]), vec![(0, 1.0)]);
+}
+
+#[test]
+fn plugins_without_latency_output() {
+    let mut graph = Graph::new(48000);
+    let delay = graph.add(ladspa::host::own_plugins()[0], &[(4, 0.0), (5, 0.0), (6, 0.0), (7, 0.0)]).unwrap();
+    graph.connect_input(0, delay, 0).unwrap();
+    graph.connect_output(delay, 2, 0).unwrap();
+    let mut processor = graph.compile(1).unwrap();
+    assert_eq!(processor.latency(), 0);
+    assert_eq!(peaks(&processor.render(&[signal::impulse(16)], 16, 16)[0]), vec![(0, 1.0)]);
+}
@@ -0,0 +1,194 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::ffi::ladspa_h;
+use ladspa::testing::plugin_ref;
+use ladspa::{Data, Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+use std::cell::RefCell;
+use std::env;
+use std::process::Command;
+
+// Set in the child process that misuse_is_reported_once runs, so that the reports on its
+// stderr can be checked.
+const CHILD: &str = "LADSPA_RS_LIFECYCLE_CHILD";
+
+thread_local! {
+    // The calls that reached the plugin on this thread; every test drives its own instance.
+    static CALLS: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+}
+
+fn calls() -> Vec<&'static str> {
+    CALLS.with(|calls| calls.borrow().clone())
+}
+
+// Copies its input to its output and records every call.
+struct Counted;
+
+impl Plugin for Counted {
+    fn activate(&mut self) {
+        CALLS.with(|calls| calls.borrow_mut().push("activate"));
+    }
+
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        CALLS.with(|calls| calls.borrow_mut().push("run"));
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        output[..sample_count].copy_from_slice(&input[..sample_count]);
+    }
+
+    fn deactivate(&mut self) {
+        CALLS.with(|calls| calls.borrow_mut().push("deactivate"));
+    }
+}
+
+fn new_counted(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Counted)
+}
+
+fn counted() -> &'static ladspa_h::Descriptor {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9005,
+        label: "counted",
+        properties: Properties::PROP_HARD_REALTIME_CAPABLE,
+        name: "Counted",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() }],
+        new: new_counted,
+    }).0
+}
+
+// An instance of counted with both ports connected.
+unsafe fn connected(desc: &ladspa_h::Descriptor, input: &mut [Data], output: &mut [Data]) -> ladspa_h::Handle {
+    let instance = desc.instantiate.unwrap()(desc, 48000);
+    assert!(!instance.is_null());
+    desc.connect_port.unwrap()(instance, 0, input.as_mut_ptr());
+    desc.connect_port.unwrap()(instance, 1, output.as_mut_ptr());
+    instance
+}
+
+#[test]
+fn run_before_activate_is_silent() {
+    let desc = counted();
+    let mut input = [0.5; 16];
+    let mut output = [1.0; 16];
+    unsafe {
+        let instance = connected(desc, &mut input, &mut output);
+        desc.run.unwrap()(instance, 16);
+        assert!(output.iter().all(|&x| x == 0.0));
+        assert!(calls().is_empty());
+
+        // Once activated, runs reach the plugin again.
+        desc.activate.unwrap()(instance);
+        desc.run.unwrap()(instance, 16);
+        assert!(output.iter().all(|&x| x == 0.5));
+        desc.deactivate.unwrap()(instance);
+        desc.cleanup.unwrap()(instance);
+    }
+    assert_eq!(calls(), vec!["activate", "run", "deactivate"]);
+}
+
+#[test]
+fn double_activate_is_ignored() {
+    let desc = counted();
+    let mut input = [0.0; 16];
+    let mut output = [0.0; 16];
+    unsafe {
+        let instance = connected(desc, &mut input, &mut output);
+        desc.activate.unwrap()(instance);
+        desc.activate.unwrap()(instance);
+        assert_eq!(calls(), vec!["activate"]);
+        desc.deactivate.unwrap()(instance);
+        desc.deactivate.unwrap()(instance);
+        assert_eq!(calls(), vec!["activate", "deactivate"]);
+
+        // The instance can still be activated again afterwards.
+        desc.activate.unwrap()(instance);
+        desc.deactivate.unwrap()(instance);
+        desc.cleanup.unwrap()(instance);
+    }
+    assert_eq!(calls(), vec!["activate", "deactivate", "activate", "deactivate"]);
+}
+
+#[test]
+fn cleanup_while_active_deactivates() {
+    let desc = counted();
+    let mut input = [0.0; 16];
+    let mut output = [0.0; 16];
+    unsafe {
+        let instance = connected(desc, &mut input, &mut output);
+        desc.activate.unwrap()(instance);
+        desc.run.unwrap()(instance, 16);
+        desc.cleanup.unwrap()(instance);
+    }
+    assert_eq!(calls(), vec!["activate", "run", "deactivate"]);
+}
+
+#[test]
+fn out_of_range_port_is_rejected() {
+    let desc = counted();
+    let mut input = [0.25; 16];
+    let mut output = [0.0; 16];
+    let mut stray = [1.0; 16];
+    unsafe {
+        let instance = connected(desc, &mut input, &mut output);
+        desc.connect_port.unwrap()(instance, 2, stray.as_mut_ptr());
+        desc.connect_port.unwrap()(instance, !0, stray.as_mut_ptr());
+        desc.activate.unwrap()(instance);
+        desc.run.unwrap()(instance, 16);
+        desc.deactivate.unwrap()(instance);
+        desc.cleanup.unwrap()(instance);
+    }
+    // The ports that exist keep their connections and the stray buffer is never touched.
+    assert!(output.iter().all(|&x| x == 0.25));
+    assert!(stray.iter().all(|&x| x == 1.0));
+}
+
+#[test]
+fn misuse_sequence() {
+    if env::var_os(CHILD).is_none() {
+        return;
+    }
+    let desc = counted();
+    let mut input = [0.0; 16];
+    let mut output = [0.0; 16];
+    unsafe {
+        let instance = connected(desc, &mut input, &mut output);
+        desc.run.unwrap()(instance, 16);
+        desc.run.unwrap()(instance, 16);
+        desc.activate.unwrap()(instance);
+        desc.activate.unwrap()(instance);
+        desc.connect_port.unwrap()(instance, 5, output.as_mut_ptr());
+        desc.deactivate.unwrap()(instance);
+        desc.deactivate.unwrap()(instance);
+        desc.activate.unwrap()(instance);
+        desc.cleanup.unwrap()(instance);
+    }
+    assert_eq!(calls(), vec!["activate", "deactivate", "activate", "deactivate"]);
+}
+
+#[test]
+fn misuse_is_reported_once() {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(&["misuse_sequence", "--exact", "--nocapture", "--test-threads=1"])
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let reports: Vec<&str> = stderr.lines().filter(|line| line.starts_with("LADSPA Host Error: ")).collect();
+    assert_eq!(reports,
+               vec!["LADSPA Host Error: counted: run called on an inactive instance \
+                     (calls: instantiate, connect_port x2, run)",
+                    "LADSPA Host Error: counted: activate called twice without deactivate \
+                     (calls: instantiate, connect_port x2, run x2, activate x2)",
+                    "LADSPA Host Error: counted: connect_port called for port 5, but there are 2 ports \
+                     (calls: instantiate, connect_port x2, run x2, activate x2, connect_port)",
+                    "LADSPA Host Error: counted: deactivate called on an inactive instance \
+                     (calls: instantiate, connect_port x2, run x2, activate x2, connect_port, deactivate x2)",
+                    "LADSPA Host Error: counted: cleanup called without deactivate \
+                     (calls: instantiate, connect_port x2, run x2, activate x2, connect_port, deactivate x2, \
+                     activate, cleanup)"]);
+}
@@ -0,0 +1,46 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::lv2;
+use ladspa::PluginDescriptor;
+use std::ffi::CStr;
+
+#[test]
+fn plugin_ttl() {
+    let desc = rustdelay::get_ladspa_descriptor(0).unwrap();
+    let ttl = lv2::plugin_ttl(&desc);
+    assert!(ttl.contains("<urn:ladspa:400>\n    a lv2:Plugin, lv2:DelayPlugin ;"), "{}", ttl);
+    assert!(ttl.contains("doap:name \"Stereo Delay\" ;"), "{}", ttl);
+    assert!(ttl.contains("dc:rights \"None\" ;"), "{}", ttl);
+    assert!(!ttl.contains("doap:license"), "{}", ttl);
+    assert!(ttl.contains("lv2:symbol \"left_delay__seconds_\" ;"), "{}", ttl);
+    assert!(ttl.contains("lv2:minimum 0.0 ;\n        lv2:maximum 5.0 ;\n        lv2:default 1.0"), "{}", ttl);
+    assert!(ttl.trim_end().ends_with("] ."), "{}", ttl);
+}
+
+#[test]
+fn license() {
+    let spdx = PluginDescriptor { copyright: "gpl-3.0-or-later", ..rustdelay::get_ladspa_descriptor(0).unwrap() };
+    assert!(lv2::plugin_ttl(&spdx).contains("doap:license <http://spdx.org/licenses/GPL-3.0-or-later> ;"));
+
+    let uri = PluginDescriptor { copyright: "https://example.com/license", ..spdx };
+    assert_eq!(lv2::license_uri(&uri), Some("https://example.com/license".to_string()));
+
+    let text = PluginDescriptor { copyright: "Copyright 2024 Someone, MIT", ..uri };
+    assert_eq!(lv2::license_uri(&text), None);
+}
+
+#[test]
+fn manifest_and_descriptor() {
+    let desc = rustdelay::get_ladspa_descriptor(0).unwrap();
+    let manifest = lv2::manifest_ttl(&[desc], "librustdelay.so");
+    assert!(manifest.contains("lv2:binary <librustdelay.so> ;"), "{}", manifest);
+    assert!(manifest.contains("rdfs:seeAlso <stereo_delay.ttl> ."), "{}", manifest);
+
+    unsafe {
+        let descriptor = lv2::lv2_descriptor(0);
+        assert!(!descriptor.is_null());
+        assert_eq!(CStr::from_ptr((*descriptor).uri).to_str().unwrap(), "urn:ladspa:400");
+        assert!(lv2::lv2_descriptor(1).is_null());
+    }
+}
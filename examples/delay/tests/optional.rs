@@ -0,0 +1,80 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::{own_plugins, PluginRef};
+use ladspa::testing::realtime::{self, CheckedAlloc};
+use ladspa::testing::signal;
+use ladspa::Data;
+use std::ptr;
+
+#[global_allocator]
+static ALLOC: CheckedAlloc = CheckedAlloc::new();
+
+// The delay with every port optional.
+fn optional_delay() -> PluginRef {
+    let mut plugin = rustdelay::get_ladspa_descriptor(0).unwrap();
+    for port in plugin.ports.iter_mut() {
+        port.optional = true;
+    }
+    let mut descriptor = unsafe { ptr::read(own_plugins()[0].0) };
+    descriptor.implementation_data = Box::into_raw(Box::new(plugin)) as *mut _;
+    PluginRef(Box::leak(Box::new(descriptor)))
+}
+
+// Renders an impulse through the left channel with the controls and the right channel connected
+// to NULL, in blocks longer than the control dummies once were.
+fn render_with_null_ports(block_sizes: &[usize]) -> Vec<Data> {
+    let mut instance = optional_delay().instantiate(48000).unwrap();
+    let length: usize = block_sizes.iter().sum();
+    let input = signal::impulse(length);
+    let mut output = vec![0.0; length];
+    unsafe {
+        for port in [1, 3, 4, 5, 6, 7] {
+            instance.connect(port, ptr::null_mut());
+        }
+        instance.activate();
+        let mut pos = 0;
+        for &size in block_sizes {
+            instance.connect(0, input[pos..].as_ptr() as *mut Data);
+            instance.connect(2, output[pos..].as_mut_ptr());
+            instance.run_raw(size);
+            pos += size;
+        }
+    }
+    output
+}
+
+#[test]
+fn null_ports_read_defaults() {
+    let output = render_with_null_ports(&[64, 4096, 16, 8000]);
+    // Half dry; the wet half comes after the default delay of a second.
+    assert_eq!(output[0], 0.5);
+    assert!(output[1..].iter().all(|&x| x == 0.0));
+}
+
+#[test]
+fn block_sizes_do_not_change_null_ports() {
+    let expected = render_with_null_ports(&[12176]);
+    for _ in 0..4 {
+        assert_eq!(render_with_null_ports(&[5, 4000, 7, 8164]), expected);
+    }
+}
+
+#[test]
+fn null_ports_do_not_allocate_in_run() {
+    let mut instance = optional_delay().instantiate(48000).unwrap();
+    let input = signal::impulse(48000);
+    let mut output = vec![0.0; 48000];
+    unsafe {
+        instance.connect(0, input.as_ptr() as *mut Data);
+        instance.connect(2, output.as_mut_ptr());
+        instance.activate();
+        // The first run connects the ports that were left unconnected.
+        instance.run_raw(16);
+        realtime::assert_no_allocations(|| {
+            for &size in [64, 4096, 8000, 48000].iter() {
+                instance.run_raw(size);
+            }
+        });
+    }
+}
@@ -0,0 +1,96 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::pipewire::{Error, FilterChain};
+use ladspa::{PluginDescriptor, PortDescriptor};
+use rustdelay::get_ladspa_descriptor;
+use std::f32;
+
+const LIBRARY: &str = "/usr/lib/ladspa/librustdelay.so";
+
+// The delay with only its left channel, so that it has a single audio output.
+fn mono_delay() -> PluginDescriptor {
+    let mut desc = get_ladspa_descriptor(0).unwrap();
+    desc.label = "mono_delay";
+    desc.ports.retain(|p| !p.name.starts_with("Right"));
+    desc
+}
+
+#[test]
+fn mono_output_feeds_every_input() {
+    let conf = FilterChain::new("Delays")
+        .plugin(LIBRARY, &mono_delay())
+        .plugin(LIBRARY, &get_ladspa_descriptor(0).unwrap())
+        .to_conf()
+        .unwrap();
+    assert!(conf.contains(r#"{ output = "mono_delay:Left Audio Out" input = "stereo_delay:Left Audio In" }"#));
+    assert!(conf.contains(r#"{ output = "mono_delay:Left Audio Out" input = "stereo_delay:Right Audio In" }"#));
+    assert_eq!(conf.matches("{ output =").count(), 2);
+    assert!(conf.contains(r#"inputs = [ "mono_delay:Left Audio In" ]"#));
+    assert!(conf.contains(r#"outputs = [ "stereo_delay:Left Audio Out" "stereo_delay:Right Audio Out" ]"#));
+    assert!(conf.contains("audio.channels = 2"));
+}
+
+#[test]
+fn stereo_outputs_do_not_fit_a_mono_input() {
+    let result = FilterChain::new("Delays")
+        .plugin(LIBRARY, &get_ladspa_descriptor(0).unwrap())
+        .plugin(LIBRARY, &mono_delay())
+        .to_conf();
+    assert_eq!(result,
+               Err(Error::ChannelMismatch {
+                   from: "stereo_delay".to_string(),
+                   to: "mono_delay".to_string(),
+                   outputs: 2,
+                   inputs: 1,
+               }));
+}
+
+#[test]
+fn controls_override_defaults() {
+    let conf = FilterChain::new("Delay")
+        .plugin(LIBRARY, &get_ladspa_descriptor(0).unwrap())
+        .control("Left Delay (seconds)", 0.25)
+        .to_conf()
+        .unwrap();
+    assert!(conf.contains(r#""Left Delay (seconds)" = 0.25"#));
+    assert!(conf.contains(r#""Right Delay (seconds)" = 1.0"#));
+    assert_eq!(conf.matches("Left Delay (seconds)").count(), 1);
+}
+
+#[test]
+fn unknown_control() {
+    for port in ["Left Delay", "Left Audio In"].iter() {
+        let result = FilterChain::new("Delay")
+            .plugin(LIBRARY, &get_ladspa_descriptor(0).unwrap())
+            .control(port, 0.5)
+            .to_conf();
+        assert_eq!(result, Err(Error::UnknownControl { node: "stereo_delay".to_string(), port: port.to_string() }));
+    }
+}
+
+#[test]
+fn non_finite_control() {
+    for &value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY].iter() {
+        let result = FilterChain::new("Delay")
+            .plugin(LIBRARY, &get_ladspa_descriptor(0).unwrap())
+            .control("Left Dry/Wet", value)
+            .to_conf();
+        match result {
+            Err(Error::NonFiniteControl { ref node, ref port, value: v }) => {
+                assert_eq!((node.as_str(), port.as_str()), ("stereo_delay", "Left Dry/Wet"));
+                assert!(v.is_nan() == value.is_nan() && (v.is_nan() || v == value));
+            }
+            ref other => panic!("expected NonFiniteControl, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn every_audio_port_is_named() {
+    let desc = get_ladspa_descriptor(0).unwrap();
+    let conf = FilterChain::new("Delay").plugin(LIBRARY, &desc).to_conf().unwrap();
+    for port in desc.ports.iter().filter(|p| p.desc == PortDescriptor::AudioInput || p.desc == PortDescriptor::AudioOutput) {
+        assert!(conf.contains(&format!("\"stereo_delay:{}\"", port.name)), "{}", port.name);
+    }
+}
@@ -0,0 +1,147 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::{Instance, PluginRef};
+use ladspa::profile::{self, PROFILE_VAR};
+use ladspa::testing::{plugin_ref, signal};
+use ladspa::{Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+// Set in the child processes that profile_is_dumped runs, whose instance is dumped on cleanup.
+const CHILD: &str = "LADSPA_RS_PROFILE_CHILD";
+
+// Copies its input after sleeping for the milliseconds set on port 2, and panics if that is
+// negative.
+struct Sleepy;
+
+impl Plugin for Sleepy {
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        let millis = *ports[2].unwrap_control();
+        assert!(millis >= 0.0, "negative sleep");
+        thread::sleep(Duration::from_micros((millis * 1000.0) as u64));
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        output[..sample_count].copy_from_slice(&input[..sample_count]);
+    }
+}
+
+fn new_sleepy(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Sleepy)
+}
+
+fn sleepy() -> PluginRef {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9007,
+        label: "sleepy",
+        properties: Properties::PROP_NONE,
+        name: "Sleepy",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() },
+                    Port { name: "Sleep (ms)", desc: PortDescriptor::ControlInput, ..Default::default() }],
+        new: new_sleepy,
+    })
+}
+
+fn profiled() -> Instance {
+    profile::enable();
+    let mut instance = sleepy().instantiate(48000).unwrap();
+    instance.set_control(2, 0.0).unwrap();
+    instance.activate();
+    instance
+}
+
+#[test]
+fn counters() {
+    let mut instance = profiled();
+    instance.render(&[signal::impulse(1000)], 1000, [100, 300, 600]);
+    unsafe { instance.run_raw(0) };
+    instance.set_control(2, -1.0).unwrap();
+    instance.render(&[signal::impulse(50)], 50, [50]);
+
+    let profile = instance.profile().unwrap();
+    assert_eq!(profile.label, "sleepy");
+    // The zero-length run counts as a run without samples; the panicking one counts fully.
+    assert_eq!((profile.runs, profile.samples, profile.panics), (5, 1050, 1));
+    // Percentiles are kept in single precision.
+    let (min, p50, p99, max) = (profile.min as f32, profile.p50 as f32, profile.p99 as f32, profile.max as f32);
+    assert!(min <= p50 && p50 <= p99 && p99 <= max, "{:?}", profile);
+    assert!(profile.min <= profile.mean && profile.mean <= profile.max, "{:?}", profile);
+}
+
+#[test]
+fn percentiles() {
+    let mut instance = profiled();
+    // Ninety fast blocks of 100 samples, then ten taking at least 2 ms, or 20000 ns per sample.
+    instance.render(&[signal::impulse(9000)], 9000, [100; 90]);
+    instance.set_control(2, 2.0).unwrap();
+    instance.render(&[signal::impulse(1000)], 1000, [100; 10]);
+
+    let profile = instance.profile().unwrap();
+    assert_eq!((profile.runs, profile.samples), (100, 10000));
+    assert!(profile.p50 < 20000.0, "{:?}", profile);
+    assert!(profile.p99 >= 20000.0, "{:?}", profile);
+    assert!(profile.max >= 20000.0 && profile.min < 20000.0, "{:?}", profile);
+    // At least a tenth of the samples took 20000 ns each.
+    assert!(profile.mean >= 2000.0, "{:?}", profile);
+}
+
+#[test]
+fn empty_profile() {
+    let instance = profiled();
+    let profile = instance.profile().unwrap();
+    assert_eq!((profile.runs, profile.samples, profile.panics), (0, 0, 0));
+    assert_eq!((profile.min, profile.mean, profile.max, profile.p50, profile.p99), (0.0, 0.0, 0.0, 0.0, 0.0));
+}
+
+#[test]
+fn dump_child() {
+    if env::var_os(CHILD).is_none() {
+        return;
+    }
+    // Counting is on through the environment alone.
+    let mut instance = sleepy().instantiate(48000).unwrap();
+    instance.set_control(2, 0.0).unwrap();
+    instance.activate();
+    instance.render(&[signal::impulse(300)], 300, [100; 3]);
+    assert!(instance.profile().is_some());
+}
+
+// Runs dump_child with LADSPA_RS_PROFILE set to target and returns its standard error.
+fn run_child(target: &str) -> String {
+    let output = Command::new(env::current_exe().unwrap())
+        .args(&["dump_child", "--exact", "--nocapture", "--test-threads=1"])
+        .env(CHILD, "1")
+        .env(PROFILE_VAR, target)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn profile_is_dumped() {
+    let stderr = run_child("1");
+    let lines: Vec<&str> = stderr.lines().filter(|line| line.starts_with("LADSPA profile: ")).collect();
+    assert_eq!(lines.len(), 1, "{}", stderr);
+    assert!(lines[0].starts_with("LADSPA profile: sleepy: 3 runs, 300 samples, 0 panics, ns/sample min "),
+            "{}", lines[0]);
+
+    // A path is appended to, one line per instance.
+    let path = env::temp_dir().join(format!("ladspa-rs-profile-{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+    for _ in 0..2 {
+        run_child(path.to_str().unwrap());
+    }
+    let dump = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(dump.lines().count(), 2, "{}", dump);
+    assert!(dump.lines().all(|line| line.starts_with("sleepy: 3 runs, 300 samples, 0 panics, ns/sample min ")),
+            "{}", dump);
+}
@@ -0,0 +1,14 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::rdf;
+
+#[test]
+fn category_reaches_rdf() {
+    let desc = rustdelay::get_ladspa_descriptor(0).unwrap();
+    let rdf = rdf::rdf(&[desc]);
+    assert!(rdf.contains("<ladspa:DelayPlugin rdf:about=\"&ladspa;400\">"), "{}", rdf);
+    assert!(rdf.contains("<dc:title>Stereo Delay</dc:title>"), "{}", rdf);
+    assert!(rdf.contains("<ladspa:InputControlPort rdf:about=\"&ladspa;400.4\">"), "{}", rdf);
+    assert!(rdf.trim_end().ends_with("</rdf:RDF>"));
+}
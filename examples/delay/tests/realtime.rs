@@ -0,0 +1,101 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::{own_plugins, PluginRef};
+use ladspa::testing::realtime::{self, CheckedAlloc, Operation};
+use ladspa::testing::{plugin_ref, signal, validate};
+use ladspa::{Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+use std::panic;
+
+#[global_allocator]
+static ALLOC: CheckedAlloc = CheckedAlloc::new();
+
+// Copies its input through a buffer allocated in every run, despite claiming to be hard realtime
+// capable.
+struct Allocating;
+
+impl Plugin for Allocating {
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        let copy = input[..sample_count].to_vec();
+        output[..sample_count].copy_from_slice(&copy);
+    }
+}
+
+fn new_allocating(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Allocating)
+}
+
+fn allocating() -> PluginRef {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9006,
+        label: "allocating",
+        properties: Properties::PROP_HARD_REALTIME_CAPABLE,
+        name: "Allocating",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() }],
+        new: new_allocating,
+    })
+}
+
+#[test]
+fn allocator_is_installed() {
+    assert!(realtime::installed());
+}
+
+#[test]
+fn delay_does_not_allocate() {
+    let mut instance = own_plugins()[0].instantiate(44100).unwrap();
+    instance.activate();
+    let inputs = vec![signal::noise(4096, 1, 0.5), signal::noise(4096, 2, 0.5)];
+    realtime::assert_no_allocations(|| instance.render(&inputs, 4096, [256; 16]));
+}
+
+#[test]
+fn allocations_outside_callbacks_are_allowed() {
+    let buffer = realtime::assert_no_allocations(|| vec![0.0f32; 4096]);
+    assert_eq!(buffer.len(), 4096);
+    drop(buffer);
+    assert!(realtime::take_violations().is_empty());
+}
+
+#[test]
+fn allocation_in_run_is_recorded() {
+    let mut instance = allocating().instantiate(44100).unwrap();
+    instance.activate();
+    realtime::take_violations();
+    instance.render(&[signal::impulse(512)], 512, [256, 256]);
+    let violations = realtime::take_violations();
+    let operations: Vec<(Operation, usize)> = violations.iter().map(|v| (v.operation, v.size)).collect();
+    // An allocation and a deallocation of 256 samples per run.
+    assert_eq!(operations,
+               vec![(Operation::Alloc, 1024), (Operation::Dealloc, 1024),
+                    (Operation::Alloc, 1024), (Operation::Dealloc, 1024)]);
+    assert!(violations[0].to_string().starts_with("allocation of 1024 bytes in a realtime callback\n"));
+    assert!(realtime::take_violations().is_empty());
+}
+
+#[test]
+fn assert_no_allocations_panics() {
+    let mut instance = allocating().instantiate(44100).unwrap();
+    instance.activate();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        realtime::assert_no_allocations(|| instance.render(&[signal::impulse(16)], 16, [16]))
+    }));
+    let message = result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.starts_with("allocation of 64 bytes in a realtime callback\n"), "{}", message);
+}
+
+#[test]
+fn validate_checks_hard_realtime() {
+    let check = |plugin: PluginRef| {
+        validate::validate(plugin, 44100).checks.into_iter().find(|c| c.name == "hard realtime").unwrap().result
+    };
+    assert_eq!(check(own_plugins()[0]), Ok(()));
+    let msg = check(allocating()).unwrap_err();
+    assert!(msg.starts_with("PROP_HARD_REALTIME_CAPABLE is declared, but found allocation of 1024 bytes"), "{}", msg);
+}
@@ -0,0 +1,55 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::{own_plugins, PluginRef};
+use ladspa::testing::{plugin_ref, reset, signal};
+use ladspa::{Data, Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+
+// Delays its input by one sample, keeping the last sample across activate.
+struct Stale {
+    last: Data,
+}
+
+impl Plugin for Stale {
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        for i in 0..sample_count {
+            output[i] = self.last;
+            self.last = input[i];
+        }
+    }
+}
+
+fn new_stale(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Stale { last: 0.0 })
+}
+
+fn stale() -> PluginRef {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9004,
+        label: "stale",
+        properties: Properties::PROP_HARD_REALTIME_CAPABLE,
+        name: "Stale",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() }],
+        new: new_stale,
+    })
+}
+
+#[test]
+fn delay_resets() {
+    // At 1 kHz the delay line holds input from before the reset.
+    reset::check_noise(own_plugins()[0], 1000, 3000, 0.0).unwrap();
+}
+
+#[test]
+fn state_kept_across_reset() {
+    let difference = reset::check(stale(), 48000, &[signal::dc(16, 0.5)], 16, 0.0).unwrap_err();
+    assert_eq!((difference.output, difference.sample), (0, 0));
+    assert_eq!((difference.expected, difference.actual), (0.0, 0.5));
+    assert!(reset::check(stale(), 48000, &[signal::dc(16, 0.5)], 16, 0.5).is_ok());
+}
@@ -0,0 +1,51 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::ffi::ladspa_h;
+use ladspa::host::{own_plugins, PluginRef};
+use ladspa::testing::response::Analysis;
+use std::ptr;
+
+// The delay with a logarithmic dry/wet port on the left.
+fn logarithmic_delay() -> PluginRef {
+    let mut descriptor = unsafe { ptr::read(own_plugins()[0].0) };
+    let mut hints: Vec<ladspa_h::PortRangeHint> =
+        (0..8).map(|i| unsafe { *descriptor.port_range_hints.add(i) }).collect();
+    hints[6] = ladspa_h::PortRangeHint {
+        hint_descriptor: ladspa_h::HINT_BOUNDED_BELOW | ladspa_h::HINT_BOUNDED_ABOVE | ladspa_h::HINT_LOGARITHMIC,
+        lower_bound: 0.01,
+        upper_bound: 1.0,
+    };
+    descriptor.port_range_hints = Box::leak(hints.into_boxed_slice()).as_mut_ptr();
+    PluginRef(Box::leak(Box::new(descriptor)))
+}
+
+#[test]
+fn sweep_values_follow_the_bounds() {
+    let analysis = Analysis::new(own_plugins()[0]);
+    assert_eq!(analysis.sweep_values(6, 5), Some(vec![0.0, 0.25, 0.5, 0.75, 1.0]));
+    assert_eq!(analysis.sweep_values(6, 1), Some(vec![0.0]));
+    assert_eq!(analysis.sweep_values(0, 5), None);
+    assert_eq!(analysis.sweep_values(8, 5), None);
+
+    let values = Analysis::new(logarithmic_delay()).sweep_values(6, 3).unwrap();
+    assert_eq!(values.len(), 3);
+    for (value, expected) in values.iter().zip([0.01, 0.1, 1.0]) {
+        assert!((value - expected).abs() < 1e-6, "{:?}", values);
+    }
+}
+
+#[test]
+fn thd_skips_frequencies_above_nyquist() {
+    let response = Analysis::new(own_plugins()[0])
+        .length(4096)
+        .controls(&[(6, 0.0)])
+        .thd_at(&[1000.0, 24000.0, 30000.0])
+        .run();
+    assert_eq!(response.thd.len(), 1);
+    assert_eq!(response.thd[0].frequency, 1000.0);
+    assert!(!response.thd[0].ratio.is_nan());
+
+    let sweep = Analysis::new(own_plugins()[0]).length(1024).sweep_control(6, &[0.0, 1.0]);
+    assert_eq!(sweep.len(), 2);
+}
@@ -0,0 +1,86 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::ffi::ladspa_h::Handle;
+use ladspa::host::sandbox::{Error, Instance};
+use ladspa::host::{own_plugins, PluginRef};
+use ladspa::Data;
+use std::os::raw::c_ulong;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+fn impulse(length: usize) -> Vec<Data> {
+    let mut x = vec![0.0; length];
+    x[0] = 1.0;
+    x
+}
+
+// Half-scale white noise from a xorshift generator.
+fn noise(length: usize, seed: u64) -> Vec<Data> {
+    let mut state = 0x9e3779b97f4a7c15u64 ^ seed;
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as Data / (1u64 << 24) as Data - 0.5
+        })
+        .collect()
+}
+
+// The delay with its run replaced by ```run```.
+fn with_run(run: unsafe extern "C" fn(Handle, c_ulong)) -> PluginRef {
+    let mut descriptor = unsafe { ptr::read(own_plugins()[0].0) };
+    descriptor.run = Some(run);
+    PluginRef(Box::leak(Box::new(descriptor)))
+}
+
+unsafe extern "C" fn abort(_: Handle, _: c_ulong) {
+    std::process::abort();
+}
+
+unsafe extern "C" fn hang(_: Handle, _: c_ulong) {
+    loop {
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+#[test]
+fn matches_in_process() {
+    let delay = own_plugins()[0];
+    let inputs = vec![noise(3000, 1), noise(3000, 2)];
+
+    let mut local = delay.instantiate(48000).unwrap();
+    local.set_control(4, 0.01).unwrap();
+    local.activate();
+    let expected = local.render(&inputs, 3000, std::iter::repeat(700));
+
+    let mut sandboxed = Instance::spawn(delay, 48000).unwrap();
+    sandboxed.set_control(4, 0.01).unwrap();
+    assert_eq!(sandboxed.control(5), 1.0);
+    sandboxed.activate().unwrap();
+    // Longer than a shared buffer, so runs are split.
+    let actual = sandboxed.render(&inputs, 3000, 1500).unwrap();
+    assert_eq!(actual, expected);
+    sandboxed.deactivate().unwrap();
+}
+
+#[test]
+fn crash_is_an_error() {
+    let mut instance = Instance::spawn(with_run(abort), 48000).unwrap();
+    instance.activate().unwrap();
+    let inputs = vec![impulse(64), impulse(64)];
+    // SIGABRT
+    assert_eq!(instance.render(&inputs, 64, 64), Err(Error::Crashed { signal: 6 }));
+    assert_eq!(instance.activate(), Err(Error::Crashed { signal: 6 }));
+    assert_eq!(instance.error(), Some(&Error::Crashed { signal: 6 }));
+}
+
+#[test]
+fn hang_times_out() {
+    let mut instance = Instance::spawn(with_run(hang), 48000).unwrap();
+    instance.set_timeout(Duration::from_millis(200));
+    let inputs = vec![impulse(64), impulse(64)];
+    assert_eq!(instance.render(&inputs, 64, 64), Err(Error::Timeout(Duration::from_millis(200))));
+}
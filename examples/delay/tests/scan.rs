@@ -0,0 +1,101 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::scan::{Mode, ScanCache};
+use ladspa::PortDescriptor;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+const CRASH: &str = "void *ladspa_descriptor(unsigned long index) { return *(void * volatile *)0; }";
+const HANG: &str = "#include <unistd.h>\n\
+                    __attribute__((constructor)) static void hang(void) { for (;;) sleep(1); }\n\
+                    void *ladspa_descriptor(unsigned long index) { return 0; }";
+
+// Builds a broken library with the system C compiler, if there is one.
+fn compile(dir: &Path, name: &str, source: &str) -> Option<PathBuf> {
+    let source_path = dir.join(format!("{}.c", name));
+    let library = dir.join(format!("{}.so", name));
+    fs::write(&source_path, source).unwrap();
+    let status = Command::new("cc").arg("-shared").arg("-fPIC").arg("-o").arg(&library).arg(&source_path).status();
+    fs::remove_file(&source_path).unwrap();
+    match status {
+        Ok(status) if status.success() => Some(library),
+        _ => {
+            eprintln!("cannot compile {}, skipping it", name);
+            None
+        }
+    }
+}
+
+#[test]
+fn broken_libraries_are_marked_bad() {
+    let dir = env::temp_dir().join(format!("ladspa-rs-scan-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    let own = env::current_exe().unwrap().parent().unwrap().parent().unwrap().join("librustdelay.so");
+    let delay = dir.join("delay.so");
+    fs::copy(own, &delay).unwrap();
+    let text = dir.join("text.so");
+    fs::write(&text, "not a library").unwrap();
+    fs::write(dir.join("README"), "ignored").unwrap();
+    let crash = compile(&dir, "crash", CRASH);
+    let hang = compile(&dir, "hang", HANG);
+
+    let dirs = [dir.clone()];
+    let mut cache = ScanCache::new();
+    cache.scan(&dirs, Mode::Subprocess { timeout: Duration::from_millis(500) });
+
+    let plugins: Vec<_> = cache.plugins().collect();
+    assert_eq!(plugins.len(), 1);
+    assert_eq!(plugins[0].0, delay.as_path());
+    assert_eq!((plugins[0].1.unique_id, plugins[0].1.label.as_str()), (400, "stereo_delay"));
+    assert_eq!(plugins[0].1.ports.len(), 8);
+    assert_eq!(plugins[0].1.ports[0].0, PortDescriptor::AudioInput);
+    assert_eq!(plugins[0].1.ports[4].0, PortDescriptor::ControlInput);
+
+    assert!(cache.get(&text).unwrap().result.is_err());
+    if let Some(ref crash) = crash {
+        let reason = cache.get(crash).unwrap().result.clone().unwrap_err();
+        assert!(reason.starts_with("crashed with signal"), "{}", reason);
+    }
+    if let Some(ref hang) = hang {
+        assert_eq!(cache.get(hang).unwrap().result, Err("timed out after 500ms".to_string()));
+    }
+    assert_eq!(cache.libraries().count(), 2 + crash.iter().count() + hang.iter().count());
+
+    let cache_path = dir.join("cache");
+    cache.save(&cache_path).unwrap();
+    let mut loaded = ScanCache::load(&cache_path).unwrap();
+    assert_eq!(loaded, cache);
+
+    // Unchanged libraries are not scanned again, so a bad one stays bad without hanging the scan.
+    if let Some(ref hang) = hang {
+        loaded.scan(&dirs, Mode::Subprocess { timeout: Duration::from_secs(60) });
+        assert_eq!(loaded, cache);
+        fs::remove_file(hang).unwrap();
+        loaded.scan(&dirs, Mode::Subprocess { timeout: Duration::from_secs(60) });
+        assert!(loaded.get(hang).is_none());
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn in_process() {
+    let dir = env::current_exe().unwrap().parent().unwrap().parent().unwrap().to_path_buf();
+    let mut cache = ScanCache::new();
+    cache.scan(&[dir.clone()], Mode::InProcess);
+    let scanned = cache.get(&dir.join("librustdelay.so")).unwrap();
+    assert_eq!(scanned.result.as_ref().unwrap()[0].label, "stereo_delay");
+}
+
+#[test]
+fn load_rejects_other_files() {
+    let path = env::temp_dir().join(format!("ladspa-rs-not-a-cache-{}", std::process::id()));
+    fs::write(&path, "library\tx\n").unwrap();
+    assert!(ScanCache::load(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,125 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::chain::{Chain, Stage};
+use ladspa::host::graph::Graph;
+use ladspa::host::{own_plugins, Library, PluginRef, MAX_TAIL_SECONDS, TAIL_THRESHOLD};
+use ladspa::testing::{plugin_ref, signal};
+use ladspa::{Data, Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+use std::env;
+
+// The tail of the delay at 48 kHz: its longest delay of five seconds.
+const DELAY_TAIL: usize = 5 * 48000 + 1;
+
+// The delay with a 10 ms echo at half level on the left.
+fn echo(plugin: PluginRef) -> Stage {
+    Stage { plugin, controls: vec![(4, 0.01), (6, 0.5)] }
+}
+
+// Feeds back its output at the given gain and reports an infinite tail.
+struct Feedback {
+    gain: Data,
+    last: Data,
+}
+
+impl Plugin for Feedback {
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        for i in 0..sample_count {
+            self.last = input[i] + self.gain * self.last;
+            output[i] = self.last;
+        }
+    }
+
+    fn tail_samples(&self) -> usize {
+        usize::MAX
+    }
+}
+
+fn feedback(new: fn(&PluginDescriptor, u64) -> Box<dyn Plugin + Send>) -> PluginRef {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9001,
+        label: "feedback",
+        properties: Properties::PROP_HARD_REALTIME_CAPABLE,
+        name: "Feedback",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() }],
+        new,
+    })
+}
+
+fn decaying(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Feedback { gain: 0.5, last: 0.0 })
+}
+
+fn endless(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Feedback { gain: 1.0, last: 0.0 })
+}
+
+#[test]
+fn instances_report_tail() {
+    let instance = own_plugins()[0].instantiate(48000).unwrap();
+    assert_eq!(instance.tail_samples(), Some(DELAY_TAIL));
+
+    // Through dlsym, for a library built with this crate but loaded by path.
+    let path = env::current_exe().unwrap().parent().unwrap().parent().unwrap().join("librustdelay.so");
+    let plugin = Library::open(&path).unwrap().find("stereo_delay").unwrap();
+    assert_eq!(plugin.instantiate(48000).unwrap().tail_samples(), Some(DELAY_TAIL));
+}
+
+#[test]
+fn chain_renders_tail() {
+    let mut chain = Chain::new(&[echo(own_plugins()[0])], 48000).unwrap();
+    assert_eq!(chain.tail_samples(), Some(DELAY_TAIL));
+    assert_eq!(chain.render(&[signal::impulse(100)], 100, 512)[0].len(), 100);
+
+    let outputs = chain.render_tail(&[signal::impulse(100)], 100, 512, TAIL_THRESHOLD);
+    // The run of silence after the echo is removed again.
+    assert_eq!(outputs[0].len(), 481);
+    assert_eq!(outputs[0][0], 0.5);
+    assert_eq!(outputs[0][480], 0.5);
+    assert_eq!(outputs[1].len(), 481);
+}
+
+#[test]
+fn graph_renders_longest_tail() {
+    let mut graph = Graph::new(48000);
+    let first = graph.add(own_plugins()[0], &[(4, 0.01), (6, 0.5)]).unwrap();
+    let second = graph.add(own_plugins()[0], &[(4, 0.01), (6, 0.5)]).unwrap();
+    let side = graph.add(own_plugins()[0], &[(4, 0.001), (6, 0.5)]).unwrap();
+    graph.connect_input(0, first, 0).unwrap();
+    graph.connect(first, 2, second, 0).unwrap();
+    graph.connect_input(0, side, 0).unwrap();
+    graph.connect_output(second, 2, 0).unwrap();
+    graph.connect_output(side, 2, 1).unwrap();
+    let mut processor = graph.compile(2).unwrap();
+    assert_eq!(processor.tail_samples(), Some(2 * DELAY_TAIL));
+
+    let outputs = processor.render_tail(&[signal::impulse(100)], 100, 512, TAIL_THRESHOLD);
+    // Two echoes of 10 ms one after the other.
+    assert_eq!(outputs[0].len(), 961);
+    assert_eq!(outputs[0][960], 0.25);
+    assert_eq!(outputs[1][48], 0.5);
+}
+
+#[test]
+fn infinite_tail_ends_when_quiet() {
+    let mut chain = Chain::new(&[Stage { plugin: feedback(decaying), controls: Vec::new() }], 1000).unwrap();
+    assert_eq!(chain.tail_samples(), Some(usize::MAX));
+    let outputs = chain.render_tail(&[signal::impulse(10)], 10, 64, TAIL_THRESHOLD);
+    // 0.5^16 is the last power of a half above the threshold.
+    assert_eq!(outputs[0].len(), 17);
+    assert!(outputs[0][16] >= TAIL_THRESHOLD);
+}
+
+#[test]
+fn infinite_tail_is_limited() {
+    let mut chain = Chain::new(&[Stage { plugin: feedback(endless), controls: Vec::new() }], 100).unwrap();
+    let outputs = chain.render_tail(&[signal::impulse(10)], 10, 64, TAIL_THRESHOLD);
+    assert_eq!(outputs[0].len(), 10 + MAX_TAIL_SECONDS * 100);
+    assert!(outputs[0].iter().all(|&x| x == 1.0));
+}
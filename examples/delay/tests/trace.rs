@@ -0,0 +1,50 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::own_plugins;
+use ladspa::testing::signal;
+use ladspa::trace::{summarize, TRACE_VAR};
+use std::env;
+use std::fs;
+use std::thread;
+
+// The only test in this binary, since tracing starts with the first instance in the process.
+#[test]
+fn trace() {
+    let path = env::temp_dir().join(format!("ladspa-rs-trace-{}", std::process::id()));
+    env::set_var(TRACE_VAR, &path);
+
+    let input = signal::impulse(1000);
+    let inputs = vec![input.clone(), input];
+    // Calls from several threads at once, each on its own instance.
+    let threads: Vec<_> = (0..4).map(|_| {
+        let inputs = inputs.clone();
+        thread::spawn(move || {
+            let mut instance = own_plugins()[0].instantiate(48000).unwrap();
+            instance.set_control(4, 0.5).unwrap();
+            instance.activate();
+            instance.render(&inputs, 1000, [100, 300, 600]);
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // The cleanup of the last instance writes every remaining event.
+    let summary = summarize(&fs::read_to_string(&path).unwrap());
+    assert_eq!(summary.matches(": stereo_delay at 48000 Hz").count(), 4, "{}", summary);
+    assert_eq!(summary.matches("blocks: 3, 1000 samples, 100 to 600 per block").count(), 4, "{}", summary);
+    assert_eq!(summary.matches("  control 4: 0.5 to 0.5, 0 changes").count(), 4, "{}", summary);
+    assert_eq!(summary.matches("cleanup 1").count(), 4, "{}", summary);
+    assert!(!summary.contains("dropped"), "{}", summary);
+
+    // Instances created after that are traced too, into the same file.
+    let mut instance = own_plugins()[0].instantiate(44100).unwrap();
+    instance.activate();
+    instance.render(&inputs, 1000, [1000]);
+    drop(instance);
+    let summary = summarize(&fs::read_to_string(&path).unwrap());
+    fs::remove_file(&path).unwrap();
+    assert_eq!(summary.matches(": stereo_delay at 44100 Hz").count(), 1, "{}", summary);
+    assert_eq!(summary.matches("cleanup 1").count(), 5, "{}", summary);
+}
@@ -0,0 +1,158 @@
+extern crate ladspa;
+extern crate rustdelay;
+
+use ladspa::host::{own_plugins, Library, PluginRef, DEFAULT_BLOCK};
+use ladspa::testing::validate::{validate, validate_library, validate_own, Report};
+use ladspa::testing::{plugin_ref, signal};
+use ladspa::{Plugin, PluginDescriptor, Port, PortConnection, PortDescriptor, Properties};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::env;
+use std::path::PathBuf;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// Counts the allocations made on the current thread while COUNTING is set.
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(|x| x.get()) {
+            ALLOCATIONS.with(|x| x.set(x.get() + 1));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    ALLOCATIONS.with(|x| x.set(0));
+    COUNTING.with(|x| x.set(true));
+    f();
+    COUNTING.with(|x| x.set(false));
+    ALLOCATIONS.with(|x| x.get())
+}
+
+// Copies its input and reports its peak on a control output, unless it is forgetful. A run of 0
+// samples counts as a run for a plugin that fades in by counting runs.
+struct Meter {
+    forgetful: bool,
+    counting: bool,
+    runs: usize,
+}
+
+impl Plugin for Meter {
+    fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]) {
+        let input = ports[0].unwrap_audio();
+        let mut output = ports[1].unwrap_audio_mut();
+        self.runs += 1;
+        let gain = if self.counting { (self.runs as f32 / 8.0).min(1.0) } else { 1.0 };
+        for i in 0..sample_count {
+            output[i] = input[i] * gain;
+        }
+        if !self.forgetful {
+            **ports[2].unwrap_control_mut() = input.iter().fold(0.0, |a, &b| a.max(b.abs()));
+        }
+    }
+}
+
+fn meter(new: fn(&PluginDescriptor, u64) -> Box<dyn Plugin + Send>) -> PluginRef {
+    plugin_ref(PluginDescriptor {
+        unique_id: 9002,
+        label: "meter",
+        properties: Properties::PROP_HARD_REALTIME_CAPABLE,
+        name: "Meter",
+        maker: "",
+        copyright: "",
+        category: None,
+        ports: vec![Port { name: "In", desc: PortDescriptor::AudioInput, ..Default::default() },
+                    Port { name: "Out", desc: PortDescriptor::AudioOutput, ..Default::default() },
+                    Port { name: "Peak", desc: PortDescriptor::ControlOutput, ..Default::default() }],
+        new,
+    })
+}
+
+fn new_meter(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Meter { forgetful: false, counting: false, runs: 0 })
+}
+
+fn new_forgetful(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Meter { forgetful: true, counting: false, runs: 0 })
+}
+
+fn new_counting(_: &PluginDescriptor, _: u64) -> Box<dyn Plugin + Send> {
+    Box::new(Meter { forgetful: false, counting: true, runs: 0 })
+}
+
+fn failures(report: &Report) -> Vec<&str> {
+    report.checks.iter().filter(|x| x.result.is_err()).map(|x| x.name).collect()
+}
+
+fn own_library() -> PathBuf {
+    env::current_exe().unwrap().parent().unwrap().parent().unwrap().join("librustdelay.so")
+}
+
+#[test]
+fn conformance() {
+    for report in validate_own(44100) {
+        assert!(report.passed(), "{}", report);
+    }
+}
+
+#[test]
+fn library() {
+    let reports = validate_library(&own_library(), 48000).unwrap();
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].passed(), "{}", reports[0]);
+}
+
+#[test]
+fn control_outputs() {
+    let report = validate(meter(new_meter), 48000);
+    assert!(report.passed(), "{}", report);
+
+    let report = validate(meter(new_forgetful), 48000);
+    assert_eq!(failures(&report), vec!["control outputs"], "{}", report);
+    assert!(report.to_string().contains("port 2 (\"Peak\") was not written in block 0"), "{}", report);
+}
+
+#[test]
+fn zero_length_runs_change_state() {
+    let report = validate(meter(new_counting), 48000);
+    assert_eq!(failures(&report), vec!["zero-length run"], "{}", report);
+    assert!(report.to_string().contains("FAIL zero-length run: the following runs:"), "{}", report);
+}
+
+#[test]
+fn run_does_not_allocate() {
+    // A separately loaded copy of the plugin, whose allocations are not counted, so that only
+    // Instance::run's own are.
+    let library = Library::open(&own_library()).unwrap();
+    let mut instance = library.plugins()[0].instantiate(48000).unwrap();
+    instance.activate();
+    let input = signal::noise(DEFAULT_BLOCK * 2, 1, 0.5);
+    let mut output = vec![0.0; DEFAULT_BLOCK * 2];
+
+    // The second input is silence and the second output a scratch buffer.
+    assert_eq!(allocations(|| instance.run(&[&input[..DEFAULT_BLOCK]], &mut [&mut output[..DEFAULT_BLOCK]])), 0);
+    assert!(allocations(|| instance.run(&[&input], &mut [&mut output])) > 0);
+    assert_eq!(allocations(|| instance.run(&[&input], &mut [&mut output])), 0);
+}
+
+#[test]
+fn zero_block_sizes() {
+    let plugin = own_plugins()[0];
+    let inputs = vec![signal::noise(100, 1, 0.5), signal::noise(100, 2, 0.5)];
+    let expected = plugin.instantiate(48000).unwrap().render(&inputs, 100, std::iter::repeat(1));
+    let actual = plugin.instantiate(48000).unwrap().render(&inputs, 100, std::iter::repeat(0));
+    assert_eq!(actual, expected);
+}
@@ -44,6 +44,7 @@ pub extern fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                 name: "Mono Ring Modulator",
                 maker: "Noah Weninger",
                 copyright: "None",
+                category: Some(ladspa::Category::Modulator),
                 ports: vec![Port {
                     name: "Audio In",
                     desc: ladspa::PortDescriptor::AudioInput,
@@ -55,10 +56,11 @@ pub extern fn get_ladspa_descriptor(index: u64) -> Option<PluginDescriptor> {
                 }, Port {
                     name: "Frequency",
                     desc: ladspa::PortDescriptor::ControlInput,
-                    hint: Some(ladspa::HINT_SAMPLE_RATE | ladspa::HINT_LOGARITHMIC),
+                    hint: Some(ladspa::ControlHint::HINT_SAMPLE_RATE | ladspa::ControlHint::HINT_LOGARITHMIC),
                     default: Some(ladspa::DefaultValue::Value440),
                     lower_bound: Some(0.0),
                     upper_bound: Some(0.5),
+                    optional: false,
                 }],
                 new: new_ringmod
             })
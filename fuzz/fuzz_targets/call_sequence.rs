@@ -0,0 +1,15 @@
+#![no_main]
+
+// Drives the example delay's C functions in orders chosen by the fuzzer.
+
+use libfuzzer_sys::fuzz_target;
+
+extern crate rustdelay;
+
+fuzz_target!(|data: &[u8]| {
+    for plugin in ladspa::host::own_plugins() {
+        if let Err(failure) = ladspa::testing::fuzz::call_sequence(plugin, 44100, data) {
+            panic!("{}: {}", plugin.label(), failure);
+        }
+    }
+});
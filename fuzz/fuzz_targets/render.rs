@@ -0,0 +1,15 @@
+#![no_main]
+
+// Renders the example delay with controls, inputs and block sizes chosen by the fuzzer.
+
+use libfuzzer_sys::fuzz_target;
+
+extern crate rustdelay;
+
+fuzz_target!(|data: &[u8]| {
+    for plugin in ladspa::host::own_plugins() {
+        if let Err(failure) = ladspa::testing::fuzz::run(plugin, 44100, data) {
+            panic!("{}: {}", plugin.label(), failure);
+        }
+    }
+});
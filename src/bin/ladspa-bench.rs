@@ -0,0 +1,106 @@
+/*!
+ * Measures how fast a LADSPA plugin processes audio and compares it against a stored baseline.
+ *
+ * ```text
+ * ladspa-bench [--block N]... [--rate N]... [--seconds S] [--threshold T] [--baseline FILE [--save]] delay.so:stereo_delay left=0.3
+ * ```
+ *
+ * The plugin and its controls are given like a stage of ```ladspa-chain```. Without ```--block```
+ * or ```--rate```, the defaults of ```Bench::new``` are measured. With ```--baseline```, the
+ * results are compared against the file and the program exits with status 1 if any configuration
+ * got slower by more than the threshold (default 0.1, i.e. 10%); with ```--save``` as well, the
+ * results are written to the file instead, as JSON.
+ */
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use ladspa::host::chain::Stage;
+use ladspa::testing::bench::{self, Bench};
+use ladspa::PluginDescriptor;
+
+// Plugin libraries must define this symbol; this program only hosts plugins.
+#[unsafe(no_mangle)]
+pub fn get_ladspa_descriptor(_: u64) -> Option<PluginDescriptor> {
+    None
+}
+
+const USAGE: &str = "usage: ladspa-bench [--block N]... [--rate N]... [--seconds S] [--threshold T] \
+                     [--baseline FILE [--save]] LIBRARY:LABEL [NAME=VALUE...]";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("ladspa-bench: {}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut block_sizes = Vec::new();
+    let mut sample_rates = Vec::new();
+    let mut seconds = None;
+    let mut threshold = 0.1;
+    let mut baseline = None;
+    let mut save = false;
+    loop {
+        let value = args.get(1).map(|x| x.as_str());
+        match args.first().map(|x| x.as_str()) {
+            Some("--block") => {
+                block_sizes.push(value.and_then(|x| x.parse().ok()).filter(|&x| x > 0).unwrap_or_else(|| fail(USAGE)));
+            }
+            Some("--rate") => {
+                sample_rates.push(value.and_then(|x| x.parse().ok()).filter(|&x| x > 0).unwrap_or_else(|| fail(USAGE)));
+            }
+            Some("--seconds") => {
+                seconds = Some(value.and_then(|x| x.parse().ok()).filter(|&x: &f64| x > 0.0).unwrap_or_else(|| fail(USAGE)));
+            }
+            Some("--threshold") => {
+                threshold = value.and_then(|x| x.parse().ok()).filter(|&x: &f64| x >= 0.0).unwrap_or_else(|| fail(USAGE));
+            }
+            Some("--baseline") => baseline = Some(PathBuf::from(value.unwrap_or_else(|| fail(USAGE)))),
+            Some("--save") => {
+                save = true;
+                args.remove(0);
+                continue;
+            }
+            _ => break,
+        }
+        args.drain(..2);
+    }
+    if args.is_empty() || (save && baseline.is_none()) {
+        fail(USAGE);
+    }
+
+    let stage = Stage::parse(&args.join(" ")).unwrap_or_else(|e| fail(&e.to_string()));
+    let mut bench = Bench::new(stage.plugin);
+    if !block_sizes.is_empty() {
+        bench = bench.block_sizes(&block_sizes);
+    }
+    if !sample_rates.is_empty() {
+        bench = bench.sample_rates(&sample_rates);
+    }
+    if let Some(seconds) = seconds {
+        bench = bench.seconds(seconds);
+    }
+    if !stage.controls.is_empty() {
+        bench = bench.preset(&args[1..].join(" "), &stage.controls);
+    }
+    let results = bench.run();
+    for result in results.iter() {
+        println!("{}", result);
+    }
+
+    let Some(baseline) = baseline else { return };
+    if save {
+        bench::save_baseline(&baseline, &results).unwrap_or_else(|e| fail(&format!("{}: {}", baseline.display(), e)));
+        return;
+    }
+    let expected = bench::load_baseline(&baseline).unwrap_or_else(|e| fail(&format!("{}: {}", baseline.display(), e)));
+    let regressions = bench::compare(&expected, &results, threshold);
+    for regression in regressions.iter() {
+        println!("regression: {}", regression);
+    }
+    if !regressions.is_empty() {
+        process::exit(1);
+    }
+}
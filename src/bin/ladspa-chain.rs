@@ -0,0 +1,75 @@
+/*!
+ * Renders a WAV file through a chain of LADSPA plugins.
+ *
+ * ```text
+ * ladspa-chain [--block N] [--no-tail] INPUT.wav OUTPUT.wav delay.so:stereo_delay left=0.3 ! ringmod.so:ring_mod freq=0.01
+ * ```
+ *
+ * The input must hold 32-bit float samples. A mono input feeds every audio input of the first
+ * plugin; otherwise the file must have one channel per input. The output has one channel per audio
+ * output of the last plugin. The latency the plugins report is removed from its start, and it goes
+ * on past the end of the input for the tails the plugins report, until the output falls below
+ * ```TAIL_THRESHOLD```. With ```--no-tail``` it is as long as the input.
+ */
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use ladspa::host::chain::Chain;
+use ladspa::host::TAIL_THRESHOLD;
+use ladspa::host::wav::Wav;
+use ladspa::PluginDescriptor;
+
+// Plugin libraries must define this symbol; this program only hosts plugins.
+#[unsafe(no_mangle)]
+pub fn get_ladspa_descriptor(_: u64) -> Option<PluginDescriptor> {
+    None
+}
+
+const USAGE: &str = "usage: ladspa-chain [--block N] [--no-tail] INPUT.wav OUTPUT.wav LIBRARY:LABEL [NAME=VALUE...] [! LIBRARY:LABEL ...]";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("ladspa-chain: {}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut block_size = 512;
+    let mut tail = true;
+    loop {
+        match args.first().map(|x| x.as_str()) {
+            Some("--block") => {
+                block_size = args.get(1).and_then(|x| x.parse().ok()).filter(|&x| x > 0).unwrap_or_else(|| fail(USAGE));
+                args.drain(..2);
+            }
+            Some("--no-tail") => {
+                tail = false;
+                args.remove(0);
+            }
+            _ => break,
+        }
+    }
+    if args.len() < 3 {
+        fail(USAGE);
+    }
+
+    let input = Wav::read(Path::new(&args[0])).unwrap_or_else(|e| fail(&format!("{}: {}", args[0], e)));
+    let mut chain = Chain::parse(&args[2..].join(" "), input.sample_rate as u64).unwrap_or_else(|e| fail(&e.to_string()));
+    let inputs = match (input.channels.len(), chain.inputs()) {
+        (_, 0) => Vec::new(),
+        (1, n) => vec![input.channels[0].clone(); n],
+        (channels, n) if channels == n => input.channels.clone(),
+        (channels, n) => fail(&format!("{} has {} channels, but the chain has {} inputs", args[0], channels, n)),
+    };
+    let output = Wav {
+        sample_rate: input.sample_rate,
+        channels: if tail {
+            chain.render_tail(&inputs, input.frames(), block_size, TAIL_THRESHOLD)
+        } else {
+            chain.render(&inputs, input.frames(), block_size)
+        },
+    };
+    output.write(Path::new(&args[1])).unwrap_or_else(|e| fail(&format!("{}: {}", args[1], e)));
+}
@@ -0,0 +1,119 @@
+/*!
+ * Measures the frequency response and harmonic distortion of a LADSPA plugin as CSV.
+ *
+ * ```text
+ * ladspa-response [--rate N] [--length N] [--output N] [--sweep] [--thd HZ]... [--control NAME [--steps N]] [--csv FILE] eq.so:lowpass cutoff=1000
+ * ```
+ *
+ * The plugin and its controls are given like a stage of ```ladspa-chain```. The response is
+ * printed, followed by the distortion if any ```--thd``` frequencies are given, unless
+ * ```--csv``` writes them to files as ```Response::write_csv``` does. With ```--control```, the
+ * response is measured at ```--steps``` values (default 5) across the bounds of that control
+ * instead, as one table.
+ */
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use ladspa::host::chain::{find_control, Stage};
+use ladspa::testing::response::{self, Analysis, Excitation};
+use ladspa::{PluginDescriptor, PortDescriptor};
+
+// Plugin libraries must define this symbol; this program only hosts plugins.
+#[unsafe(no_mangle)]
+pub fn get_ladspa_descriptor(_: u64) -> Option<PluginDescriptor> {
+    None
+}
+
+const USAGE: &str = "usage: ladspa-response [--rate N] [--length N] [--output N] [--sweep] [--thd HZ]... \
+                     [--control NAME [--steps N]] [--csv FILE] LIBRARY:LABEL [NAME=VALUE...]";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("ladspa-response: {}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut sample_rate = 48000;
+    let mut length = None;
+    let mut output = 0;
+    let mut excitation = Excitation::Impulse;
+    let mut thd = Vec::new();
+    let mut control = None;
+    let mut steps = 5;
+    let mut csv = None;
+    loop {
+        let value = args.get(1).map(|x| x.as_str());
+        match args.first().map(|x| x.as_str()) {
+            Some("--rate") => {
+                sample_rate = value.and_then(|x| x.parse().ok()).filter(|&x| x > 0).unwrap_or_else(|| fail(USAGE));
+            }
+            Some("--length") => {
+                length = Some(value.and_then(|x| x.parse().ok()).filter(|&x| x > 0).unwrap_or_else(|| fail(USAGE)));
+            }
+            Some("--output") => output = value.and_then(|x| x.parse().ok()).unwrap_or_else(|| fail(USAGE)),
+            Some("--sweep") => {
+                excitation = Excitation::Sweep;
+                args.remove(0);
+                continue;
+            }
+            Some("--thd") => thd.push(value.and_then(|x| x.parse().ok()).unwrap_or_else(|| fail(USAGE))),
+            Some("--control") => control = Some(value.unwrap_or_else(|| fail(USAGE)).to_string()),
+            Some("--steps") => {
+                steps = value.and_then(|x| x.parse().ok()).filter(|&x| x > 0).unwrap_or_else(|| fail(USAGE));
+            }
+            Some("--csv") => csv = Some(PathBuf::from(value.unwrap_or_else(|| fail(USAGE)))),
+            _ => break,
+        }
+        args.drain(..2);
+    }
+    if args.is_empty() {
+        fail(USAGE);
+    }
+
+    let stage = Stage::parse(&args.join(" ")).unwrap_or_else(|e| fail(&e.to_string()));
+    let plugin = stage.plugin;
+    if output >= plugin.ports_of(PortDescriptor::AudioOutput).len() {
+        fail(&format!("{} has no audio output {}", plugin.label(), output));
+    }
+    let nyquist = sample_rate as f64 / 2.0;
+    if let Some(frequency) = thd.iter().find(|&&f| f <= 0.0 || f >= nyquist) {
+        fail(&format!("cannot measure THD at {} Hz with a sample rate of {} Hz", frequency, sample_rate));
+    }
+    let mut analysis = Analysis::new(plugin)
+        .sample_rate(sample_rate)
+        .output(output)
+        .controls(&stage.controls)
+        .excitation(excitation)
+        .thd_at(&thd);
+    if let Some(length) = length {
+        analysis = analysis.length(length);
+    }
+
+    let write = |text: String, path: Option<PathBuf>| match path {
+        Some(path) => fs::write(&path, text).unwrap_or_else(|e| fail(&format!("{}: {}", path.display(), e))),
+        None => print!("{}", text),
+    };
+    if let Some(control) = control {
+        let port = find_control(plugin, &control)
+            .unwrap_or_else(|| fail(&format!("{} has no control input named {:?}", plugin.label(), control)));
+        let values = analysis
+            .sweep_values(port, steps)
+            .unwrap_or_else(|| fail(&format!("{} has no lower and upper bound", control)));
+        write(response::sweep_csv(&analysis.sweep_control(port, &values)), csv);
+        return;
+    }
+    let response = analysis.run();
+    match csv {
+        Some(path) => response.write_csv(&path).unwrap_or_else(|e| fail(&format!("{}: {}", path.display(), e))),
+        None => {
+            write(response.to_csv(), None);
+            if !response.thd.is_empty() {
+                write(response.thd_csv(), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+/*!
+ * Summarises a trace written with ```LADSPA_RS_TRACE```.
+ *
+ * ```text
+ * LADSPA_RS_TRACE=/tmp/trace.log some-host ...
+ * ladspa-trace-summary /tmp/trace.log
+ * ```
+ *
+ * Reads standard input if no file is given.
+ */
+
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+use ladspa::trace::summarize;
+use ladspa::PluginDescriptor;
+
+// Plugin libraries must define this symbol; this program only reads traces.
+#[unsafe(no_mangle)]
+pub fn get_ladspa_descriptor(_: u64) -> Option<PluginDescriptor> {
+    None
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("ladspa-trace-summary: {}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let trace = match args.as_slice() {
+        [] => {
+            let mut trace = String::new();
+            io::stdin().read_to_string(&mut trace).unwrap_or_else(|e| fail(&e.to_string()));
+            trace
+        }
+        [path] => fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e))),
+        _ => fail("usage: ladspa-trace-summary [TRACE]"),
+    };
+    print!("{}", summarize(&trace));
+}
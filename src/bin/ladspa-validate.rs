@@ -0,0 +1,78 @@
+/*!
+ * Validates the plugins of LADSPA libraries and prints a report per plugin.
+ *
+ * ```text
+ * ladspa-validate [--rate N] [--label LABEL] LIBRARY...
+ * ```
+ *
+ * Libraries are resolved like ```ladspa-chain``` resolves them, so a bare file name is looked up
+ * in ```LADSPA_PATH```. Exits with status 1 if any plugin fails a check or a library cannot be
+ * opened.
+ */
+
+use std::env;
+use std::process;
+
+use ladspa::host::{find_library, Library};
+use ladspa::testing::validate::validate;
+use ladspa::PluginDescriptor;
+
+// Plugin libraries must define this symbol; this program only hosts plugins.
+#[unsafe(no_mangle)]
+pub fn get_ladspa_descriptor(_: u64) -> Option<PluginDescriptor> {
+    None
+}
+
+const USAGE: &str = "usage: ladspa-validate [--rate N] [--label LABEL] LIBRARY...";
+
+fn fail(msg: &str) -> ! {
+    eprintln!("ladspa-validate: {}", msg);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut sample_rate = 48000;
+    let mut label = None;
+    loop {
+        match args.first().map(|x| x.as_str()) {
+            Some("--rate") => {
+                sample_rate = args.get(1).and_then(|x| x.parse().ok()).filter(|&x| x > 0).unwrap_or_else(|| fail(USAGE));
+            }
+            Some("--label") => label = Some(args.get(1).cloned().unwrap_or_else(|| fail(USAGE))),
+            _ => break,
+        }
+        args.drain(..2);
+    }
+    if args.is_empty() {
+        fail(USAGE);
+    }
+
+    let mut passed = true;
+    for name in args.iter() {
+        let library = find_library(name)
+            .ok_or_else(|| "not found".to_string())
+            .and_then(|path| Library::open(&path).map_err(|e| e.to_string()));
+        let library = match library {
+            Ok(library) => library,
+            Err(error) => {
+                eprintln!("ladspa-validate: cannot open {}: {}", name, error);
+                passed = false;
+                continue;
+            }
+        };
+        let plugins: Vec<_> = library.plugins().into_iter().filter(|x| label.as_ref().is_none_or(|l| x.label() == *l)).collect();
+        if plugins.is_empty() {
+            eprintln!("ladspa-validate: {} has no plugins to validate", name);
+            passed = false;
+        }
+        for plugin in plugins {
+            let report = validate(plugin, sample_rate);
+            passed &= report.passed();
+            print!("{}", report);
+        }
+    }
+    if !passed {
+        process::exit(1);
+    }
+}
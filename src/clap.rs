@@ -0,0 +1,1003 @@
+/*!
+ * Exposes the plugins returned by ```get_ladspa_descriptor``` as CLAP plugins.
+ *
+ * The exported ```clap_entry``` provides a plugin factory with one CLAP plugin per
+ * ```PluginDescriptor```, identified by ```plugin_id```. The mapping is:
+ *
+ * * All audio input ports form a single main input audio port, one channel per LADSPA port, and
+ *   likewise for audio outputs.
+ * * Every control port becomes a parameter whose id is the LADSPA port index. Control outputs are
+ *   read-only parameters.
+ * * A ```Port::latency``` output is reported through the latency extension, and
+ *   ```Plugin::tail_samples``` through the tail extension.
+ * * ```HINT_INTEGER``` and ```HINT_TOGGLED``` ports are stepped. CLAP has no logarithmic flag, so
+ *   ```HINT_LOGARITHMIC``` ports with positive bounds are exposed in the log domain, which gives
+ *   hosts a perceptually even range. ```HINT_SAMPLE_RATE``` ports keep their bounds as fractions
+ *   of the sample rate, which is only known after activation.
+ *
+ * Parameter changes are applied sample accurately by splitting the block at each event. A LADSPA
+ * plugin's state can only be cleared by ```Plugin::activate```, which may allocate, so ```reset```
+ * does not clear it on the audio thread but asks the host to restart the plugin.
+ * The ```host``` module contains a minimal in-process host for exercising the wrapper.
+ */
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_ulong, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::ffi::{self, ladspa_h};
+use crate::{get_ladspa_descriptor, Category, ControlHint, Data, Port, PluginDescriptor,
+            PortDescriptor};
+
+pub mod host;
+
+// Prevent clap_entry from being stripped during release builds
+#[used]
+static EXPORT_KEEPER: &clap_h::PluginEntry = &clap_entry;
+
+#[allow(non_camel_case_types)]
+pub mod clap_h {
+    use std::os::raw::{c_char, c_void};
+
+    pub type Id = u32;
+    pub type ProcessStatus = i32;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct Version {
+        pub major: u32,
+        pub minor: u32,
+        pub revision: u32,
+    }
+
+    pub const VERSION: Version = Version { major: 1, minor: 2, revision: 2 };
+
+    #[repr(C)]
+    pub struct PluginEntry {
+        pub clap_version: Version,
+        pub init: Option<unsafe extern "C" fn(plugin_path: *const c_char) -> bool>,
+        pub deinit: Option<unsafe extern "C" fn()>,
+        pub get_factory: Option<unsafe extern "C" fn(factory_id: *const c_char) -> *const c_void>,
+    }
+
+    #[repr(C)]
+    pub struct PluginFactory {
+        pub get_plugin_count: Option<unsafe extern "C" fn(factory: *const PluginFactory) -> u32>,
+        pub get_plugin_descriptor: Option<unsafe extern "C" fn(factory: *const PluginFactory,
+                                                               index: u32)
+                                                               -> *const PluginDescriptor>,
+        pub create_plugin: Option<unsafe extern "C" fn(factory: *const PluginFactory,
+                                                       host: *const Host,
+                                                       plugin_id: *const c_char)
+                                                       -> *const Plugin>,
+    }
+
+    #[repr(C)]
+    pub struct PluginDescriptor {
+        pub clap_version: Version,
+        pub id: *const c_char,
+        pub name: *const c_char,
+        pub vendor: *const c_char,
+        pub url: *const c_char,
+        pub manual_url: *const c_char,
+        pub support_url: *const c_char,
+        pub version: *const c_char,
+        pub description: *const c_char,
+        pub features: *const *const c_char,
+    }
+
+    #[repr(C)]
+    pub struct Host {
+        pub clap_version: Version,
+        pub host_data: *mut c_void,
+        pub name: *const c_char,
+        pub vendor: *const c_char,
+        pub url: *const c_char,
+        pub version: *const c_char,
+        pub get_extension: Option<unsafe extern "C" fn(host: *const Host, extension_id: *const c_char) -> *const c_void>,
+        pub request_restart: Option<unsafe extern "C" fn(host: *const Host)>,
+        pub request_process: Option<unsafe extern "C" fn(host: *const Host)>,
+        pub request_callback: Option<unsafe extern "C" fn(host: *const Host)>,
+    }
+
+    #[repr(C)]
+    pub struct Plugin {
+        pub desc: *const PluginDescriptor,
+        pub plugin_data: *mut c_void,
+        pub init: Option<unsafe extern "C" fn(plugin: *const Plugin) -> bool>,
+        pub destroy: Option<unsafe extern "C" fn(plugin: *const Plugin)>,
+        pub activate: Option<unsafe extern "C" fn(plugin: *const Plugin,
+                                                  sample_rate: f64,
+                                                  min_frames_count: u32,
+                                                  max_frames_count: u32)
+                                                  -> bool>,
+        pub deactivate: Option<unsafe extern "C" fn(plugin: *const Plugin)>,
+        pub start_processing: Option<unsafe extern "C" fn(plugin: *const Plugin) -> bool>,
+        pub stop_processing: Option<unsafe extern "C" fn(plugin: *const Plugin)>,
+        pub reset: Option<unsafe extern "C" fn(plugin: *const Plugin)>,
+        pub process: Option<unsafe extern "C" fn(plugin: *const Plugin, process: *const Process) -> ProcessStatus>,
+        pub get_extension: Option<unsafe extern "C" fn(plugin: *const Plugin, id: *const c_char) -> *const c_void>,
+        pub on_main_thread: Option<unsafe extern "C" fn(plugin: *const Plugin)>,
+    }
+
+    #[repr(C)]
+    pub struct AudioBuffer {
+        pub data32: *mut *mut f32,
+        pub data64: *mut *mut f64,
+        pub channel_count: u32,
+        pub latency: u32,
+        pub constant_mask: u64,
+    }
+
+    #[repr(C)]
+    pub struct Process {
+        pub steady_time: i64,
+        pub frames_count: u32,
+        pub transport: *const c_void,
+        pub audio_inputs: *const AudioBuffer,
+        pub audio_outputs: *mut AudioBuffer,
+        pub audio_inputs_count: u32,
+        pub audio_outputs_count: u32,
+        pub in_events: *const InputEvents,
+        pub out_events: *const OutputEvents,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct EventHeader {
+        pub size: u32,
+        pub time: u32,
+        pub space_id: u16,
+        pub type_: u16,
+        pub flags: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct EventParamValue {
+        pub header: EventHeader,
+        pub param_id: Id,
+        pub cookie: *mut c_void,
+        pub note_id: i32,
+        pub port_index: i16,
+        pub channel: i16,
+        pub key: i16,
+        pub value: f64,
+    }
+
+    #[repr(C)]
+    pub struct InputEvents {
+        pub ctx: *mut c_void,
+        pub size: Option<unsafe extern "C" fn(list: *const InputEvents) -> u32>,
+        pub get: Option<unsafe extern "C" fn(list: *const InputEvents, index: u32) -> *const EventHeader>,
+    }
+
+    #[repr(C)]
+    pub struct OutputEvents {
+        pub ctx: *mut c_void,
+        pub try_push: Option<unsafe extern "C" fn(list: *const OutputEvents, event: *const EventHeader) -> bool>,
+    }
+
+    pub const NAME_SIZE: usize = 256;
+    pub const PATH_SIZE: usize = 1024;
+
+    #[repr(C)]
+    pub struct ParamInfo {
+        pub id: Id,
+        pub flags: u32,
+        pub cookie: *mut c_void,
+        pub name: [c_char; NAME_SIZE],
+        pub module: [c_char; PATH_SIZE],
+        pub min_value: f64,
+        pub max_value: f64,
+        pub default_value: f64,
+    }
+
+    #[repr(C)]
+    pub struct PluginParams {
+        pub count: Option<unsafe extern "C" fn(plugin: *const Plugin) -> u32>,
+        pub get_info: Option<unsafe extern "C" fn(plugin: *const Plugin, param_index: u32, param_info: *mut ParamInfo) -> bool>,
+        pub get_value: Option<unsafe extern "C" fn(plugin: *const Plugin, param_id: Id, out_value: *mut f64) -> bool>,
+        pub value_to_text: Option<unsafe extern "C" fn(plugin: *const Plugin,
+                                                       param_id: Id,
+                                                       value: f64,
+                                                       out_buffer: *mut c_char,
+                                                       out_buffer_capacity: u32)
+                                                       -> bool>,
+        pub text_to_value: Option<unsafe extern "C" fn(plugin: *const Plugin,
+                                                       param_id: Id,
+                                                       param_value_text: *const c_char,
+                                                       out_value: *mut f64)
+                                                       -> bool>,
+        pub flush: Option<unsafe extern "C" fn(plugin: *const Plugin, input: *const InputEvents, output: *const OutputEvents)>,
+    }
+
+    #[repr(C)]
+    pub struct AudioPortInfo {
+        pub id: Id,
+        pub name: [c_char; NAME_SIZE],
+        pub flags: u32,
+        pub channel_count: u32,
+        pub port_type: *const c_char,
+        pub in_place_pair: Id,
+    }
+
+    #[repr(C)]
+    pub struct PluginAudioPorts {
+        pub count: Option<unsafe extern "C" fn(plugin: *const Plugin, is_input: bool) -> u32>,
+        pub get: Option<unsafe extern "C" fn(plugin: *const Plugin,
+                                             index: u32,
+                                             is_input: bool,
+                                             info: *mut AudioPortInfo)
+                                             -> bool>,
+    }
+
+    #[repr(C)]
+    pub struct PluginLatency {
+        pub get: Option<unsafe extern "C" fn(plugin: *const Plugin) -> u32>,
+    }
+
+    #[repr(C)]
+    pub struct PluginTail {
+        pub get: Option<unsafe extern "C" fn(plugin: *const Plugin) -> u32>,
+    }
+
+    pub const INVALID_ID: Id = u32::MAX;
+
+    pub const PLUGIN_FACTORY_ID: &[u8] = b"clap.plugin-factory\0";
+    pub const EXT_PARAMS: &[u8] = b"clap.params\0";
+    pub const EXT_AUDIO_PORTS: &[u8] = b"clap.audio-ports\0";
+    pub const EXT_LATENCY: &[u8] = b"clap.latency\0";
+    pub const EXT_TAIL: &[u8] = b"clap.tail\0";
+    pub const PORT_MONO: &[u8] = b"mono\0";
+    pub const PORT_STEREO: &[u8] = b"stereo\0";
+
+    pub const PROCESS_ERROR: ProcessStatus = 0;
+    pub const PROCESS_CONTINUE: ProcessStatus = 1;
+
+    pub const CORE_EVENT_SPACE_ID: u16 = 0;
+    pub const EVENT_PARAM_VALUE: u16 = 5;
+
+    pub const PARAM_IS_STEPPED: u32 = 1 << 0;
+    pub const PARAM_IS_READONLY: u32 = 1 << 3;
+    pub const PARAM_IS_AUTOMATABLE: u32 = 1 << 5;
+
+    pub const AUDIO_PORT_IS_MAIN: u32 = 1 << 0;
+}
+
+// The sample rate assumed when converting absolute defaults of HINT_SAMPLE_RATE ports to
+// fractions of the sample rate.
+const NOMINAL_SAMPLE_RATE: u64 = 48000;
+
+#[doc(hidden)]
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)]
+pub static clap_entry: clap_h::PluginEntry = clap_h::PluginEntry {
+    clap_version: clap_h::VERSION,
+    init: Some(entry_init),
+    deinit: Some(entry_deinit),
+    get_factory: Some(entry_get_factory),
+};
+
+static FACTORY: clap_h::PluginFactory = clap_h::PluginFactory {
+    get_plugin_count: Some(factory_get_plugin_count),
+    get_plugin_descriptor: Some(factory_get_plugin_descriptor),
+    create_plugin: Some(factory_create_plugin),
+};
+
+static PARAMS: clap_h::PluginParams = clap_h::PluginParams {
+    count: Some(params_count),
+    get_info: Some(params_get_info),
+    get_value: Some(params_get_value),
+    value_to_text: Some(params_value_to_text),
+    text_to_value: Some(params_text_to_value),
+    flush: Some(params_flush),
+};
+
+static AUDIO_PORTS: clap_h::PluginAudioPorts = clap_h::PluginAudioPorts {
+    count: Some(audio_ports_count),
+    get: Some(audio_ports_get),
+};
+
+static LATENCY: clap_h::PluginLatency = clap_h::PluginLatency {
+    get: Some(latency_get),
+};
+
+static TAIL: clap_h::PluginTail = clap_h::PluginTail {
+    get: Some(tail_get),
+};
+
+// A registered plugin with the C strings its CLAP descriptor points into.
+struct ClapPlugin {
+    descriptor: clap_h::PluginDescriptor,
+    plugin: PluginDescriptor,
+    strings: Vec<CString>,
+    // Owns the array behind descriptor.features.
+    #[allow(dead_code)]
+    features: Vec<*const c_char>,
+}
+
+static mut PLUGINS: *mut Vec<ClapPlugin> = ptr::null_mut();
+
+// How many times entry_init was called without a matching entry_deinit. Hosts may init the entry
+// more than once, from any thread, and instances keep pointing into PLUGINS until the last
+// deinit. The lock also serialises creating and freeing PLUGINS in init and deinit.
+static INIT_COUNT: Mutex<usize> = Mutex::new(0);
+
+unsafe fn plugins() -> &'static Vec<ClapPlugin> {
+    unsafe {
+        if PLUGINS.is_null() {
+            let mut plugins = Vec::new();
+            while let Some(desc) = call_user_code!(get_ladspa_descriptor(plugins.len() as u64),
+                                                   "get_ladspa_descriptor") {
+                plugins.push(new_clap_plugin(desc));
+            }
+            PLUGINS = Box::into_raw(Box::new(plugins));
+        }
+        &*PLUGINS
+    }
+}
+
+fn new_clap_plugin(plugin: PluginDescriptor) -> ClapPlugin {
+    let mut strings = vec![
+        CString::new(plugin_id(&plugin)).unwrap(),
+        CString::new(plugin.name).unwrap(),
+        CString::new(plugin.maker).unwrap(),
+        CString::new("").unwrap(),
+    ];
+    strings.extend(features(&plugin).iter().map(|f| CString::new(*f).unwrap()));
+
+    let mut features: Vec<*const c_char> = strings[4..].iter().map(|s| s.as_ptr()).collect();
+    features.push(ptr::null());
+
+    // The CStrings own heap buffers, so the pointers stay valid when the struct is moved.
+    let empty = strings[3].as_ptr();
+    ClapPlugin {
+        descriptor: clap_h::PluginDescriptor {
+            clap_version: clap_h::VERSION,
+            id: strings[0].as_ptr(),
+            name: strings[1].as_ptr(),
+            vendor: strings[2].as_ptr(),
+            url: empty,
+            manual_url: empty,
+            support_url: empty,
+            version: empty,
+            description: empty,
+            features: features.as_ptr(),
+        },
+        plugin,
+        strings,
+        features,
+    }
+}
+
+/// The CLAP id of a plugin, derived from its LADSPA unique id.
+pub fn plugin_id(desc: &PluginDescriptor) -> String {
+    format!("ladspa.{}", desc.unique_id)
+}
+
+/**
+ * The CLAP feature strings of a plugin: its main category, channel layout and category. Plugins
+ * without audio outputs or in ```Category::FrequencyMeter``` are analyzers, plugins without audio
+ * inputs are instruments unless their category is outside ```Category::Generator```, and
+ * everything else is an audio effect.
+ */
+pub fn features(desc: &PluginDescriptor) -> Vec<&'static str> {
+    let inputs = desc.ports.iter().filter(|p| p.desc == PortDescriptor::AudioInput).count();
+    let outputs = desc.ports.iter().filter(|p| p.desc == PortDescriptor::AudioOutput).count();
+
+    let mut features = vec![match desc.category {
+        _ if outputs == 0 => "analyzer",
+        Some(Category::FrequencyMeter) => "analyzer",
+        _ if inputs > 0 => "audio-effect",
+        Some(category) if !category.ancestors().any(|c| c == Category::Generator) => "audio-effect",
+        _ => "instrument",
+    }];
+    match outputs.max(inputs) {
+        1 => features.push("mono"),
+        2 => features.push("stereo"),
+        _ => {}
+    }
+    if let Some(feature) = desc.category.and_then(category_feature) &&
+       !features.contains(&feature) {
+        features.push(feature);
+    }
+    features
+}
+
+fn category_feature(category: Category) -> Option<&'static str> {
+    category.ancestors().find_map(|c| match c {
+        Category::Utility => Some("utility"),
+        Category::Reverb => Some("reverb"),
+        Category::Delay => Some("delay"),
+        Category::Phaser => Some("phaser"),
+        Category::Flanger => Some("flanger"),
+        Category::Chorus => Some("chorus"),
+        Category::FrequencyMeter => Some("analyzer"),
+        Category::Pitch => Some("pitch-shifter"),
+        Category::EQ => Some("equalizer"),
+        Category::Filter => Some("filter"),
+        Category::Distortion => Some("distortion"),
+        Category::Compressor => Some("compressor"),
+        Category::Expander => Some("expander"),
+        Category::Limiter => Some("limiter"),
+        Category::Gate => Some("gate"),
+        _ => None,
+    })
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+    let mut count = INIT_COUNT.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe {
+        plugins();
+    }
+    *count += 1;
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {
+    let mut count = INIT_COUNT.lock().unwrap_or_else(|e| e.into_inner());
+    if *count == 0 {
+        return;
+    }
+    *count -= 1;
+    unsafe {
+        if *count == 0 && !PLUGINS.is_null() {
+            let _ = Box::from_raw(PLUGINS);
+            PLUGINS = ptr::null_mut();
+        }
+    }
+}
+
+unsafe extern "C" fn entry_get_factory(factory_id: *const c_char) -> *const c_void {
+    unsafe {
+        if CStr::from_ptr(factory_id).to_bytes_with_nul() == clap_h::PLUGIN_FACTORY_ID {
+            &FACTORY as *const _ as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+}
+
+unsafe extern "C" fn factory_get_plugin_count(_factory: *const clap_h::PluginFactory) -> u32 {
+    unsafe { plugins().len() as u32 }
+}
+
+unsafe extern "C" fn factory_get_plugin_descriptor(_factory: *const clap_h::PluginFactory,
+                                                   index: u32)
+                                                   -> *const clap_h::PluginDescriptor {
+    unsafe {
+        match plugins().get(index as usize) {
+            Some(plugin) => &plugin.descriptor,
+            None => ptr::null(),
+        }
+    }
+}
+
+unsafe extern "C" fn factory_create_plugin(_factory: *const clap_h::PluginFactory,
+                                           host: *const clap_h::Host,
+                                           plugin_id: *const c_char)
+                                           -> *const clap_h::Plugin {
+    unsafe {
+        let id = CStr::from_ptr(plugin_id);
+        let info = match plugins().iter().find(|p| p.strings[0].as_c_str() == id) {
+            Some(info) => info,
+            None => return ptr::null(),
+        };
+        let desc = &info.plugin;
+
+        let controls = desc.ports
+            .iter()
+            .map(|port| param_to_port(port, param_default(port), NOMINAL_SAMPLE_RATE as f64))
+            .collect();
+        let values = desc.ports.iter().map(|port| AtomicU64::new(param_default(port).to_bits())).collect();
+
+        let instance = Box::into_raw(Box::new(Instance {
+            clap: clap_h::Plugin {
+                desc: &info.descriptor,
+                plugin_data: ptr::null_mut(),
+                init: Some(plugin_init),
+                destroy: Some(plugin_destroy),
+                activate: Some(plugin_activate),
+                deactivate: Some(plugin_deactivate),
+                start_processing: Some(plugin_start_processing),
+                stop_processing: Some(plugin_stop_processing),
+                reset: Some(plugin_reset),
+                process: Some(plugin_process),
+                get_extension: Some(plugin_get_extension),
+                on_main_thread: Some(plugin_on_main_thread),
+            },
+            host,
+            descriptor: desc,
+            handle: ptr::null_mut(),
+            sample_rate: 0.0,
+            max_frames: 0,
+            controls,
+            values,
+            params: (0..desc.ports.len()).filter(|&i| is_control(&desc.ports[i])).collect(),
+            inputs: (0..desc.ports.len()).filter(|&i| desc.ports[i].desc == PortDescriptor::AudioInput).collect(),
+            outputs: (0..desc.ports.len()).filter(|&i| desc.ports[i].desc == PortDescriptor::AudioOutput).collect(),
+            silence: Vec::new(),
+            discard: Vec::new(),
+        }));
+        (*instance).clap.plugin_data = instance as *mut c_void;
+        &(*instance).clap
+    }
+}
+
+// The state behind a clap_plugin. The LADSPA handle only exists while the plugin is activated,
+// since that is when CLAP provides the sample rate.
+struct Instance {
+    clap: clap_h::Plugin,
+    host: *const clap_h::Host,
+    descriptor: &'static PluginDescriptor,
+    handle: ladspa_h::Handle,
+    sample_rate: f64,
+    max_frames: usize,
+    // Port values in the plugin's own domain, indexed by port. Only touched by the thread that
+    // processes.
+    controls: Vec<Data>,
+    // Parameter values in the CLAP domain, indexed by port, published for the main thread.
+    values: Vec<AtomicU64>,
+    params: Vec<usize>,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+    silence: Vec<Data>,
+    discard: Vec<Data>,
+}
+
+unsafe fn instance<'a>(plugin: *const clap_h::Plugin) -> &'a mut Instance {
+    unsafe { &mut *((*plugin).plugin_data as *mut Instance) }
+}
+
+// Latency outputs are reported through the latency extension rather than as parameters.
+fn is_control(port: &Port) -> bool {
+    (port.desc == PortDescriptor::ControlInput || port.desc == PortDescriptor::ControlOutput) &&
+    !port.is_latency()
+}
+
+fn is_log(port: &Port) -> bool {
+    port.hint.is_some_and(|h| h.contains(ControlHint::HINT_LOGARITHMIC)) &&
+    port.lower_bound.is_some_and(|x| x > 0.0) && port.upper_bound.is_some_and(|x| x > 0.0)
+}
+
+fn is_sample_rate(port: &Port) -> bool {
+    port.hint.is_some_and(|h| h.contains(ControlHint::HINT_SAMPLE_RATE))
+}
+
+/// Converts a CLAP parameter value to the value seen by the plugin on its port.
+pub fn param_to_port(port: &Port, value: f64, sample_rate: f64) -> Data {
+    let value = if is_log(port) { value.exp() } else { value };
+    let value = if is_sample_rate(port) { value * sample_rate } else { value };
+    value as Data
+}
+
+/// Converts a port value to the corresponding CLAP parameter value.
+pub fn port_to_param(port: &Port, value: Data, sample_rate: f64) -> f64 {
+    let value = value as f64;
+    let value = if is_sample_rate(port) { value / sample_rate } else { value };
+    if is_log(port) { value.max(f64::MIN_POSITIVE).ln() } else { value }
+}
+
+/// The range of the parameter of a control port. Unbounded ports fall back to ```0..1```.
+pub fn param_range(port: &Port) -> (f64, f64) {
+    let toggled = port.hint.is_some_and(|h| h.contains(ControlHint::HINT_TOGGLED));
+    let lower = if toggled { 0.0 } else { port.lower_bound.unwrap_or(0.0) as f64 };
+    let upper = if toggled { 1.0 } else { port.upper_bound.map(|x| x as f64).unwrap_or(lower.max(0.0) + 1.0) };
+    if is_log(port) {
+        (lower.ln(), upper.ln())
+    } else {
+        (lower, upper)
+    }
+}
+
+/// The default value of the parameter of a control port, within ```param_range```.
+pub fn param_default(port: &Port) -> f64 {
+    let (lower, upper) = param_range(port);
+    match port.resolved_default(NOMINAL_SAMPLE_RATE) {
+        Some(value) => port_to_param(port, value, NOMINAL_SAMPLE_RATE as f64).clamp(lower, upper),
+        None => lower,
+    }
+}
+
+impl Instance {
+    fn set_param(&mut self, id: clap_h::Id, value: f64) {
+        let port = match self.descriptor.ports.get(id as usize) {
+            Some(port) if port.desc == PortDescriptor::ControlInput => port,
+            _ => return,
+        };
+        let (lower, upper) = param_range(port);
+        let value = value.clamp(lower, upper);
+        let sample_rate = if self.handle.is_null() { NOMINAL_SAMPLE_RATE as f64 } else { self.sample_rate };
+        self.controls[id as usize] = param_to_port(port, value, sample_rate);
+        self.values[id as usize].store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    unsafe fn apply_events(&mut self, events: *const clap_h::InputEvents, from: u32, until: Option<u32>) -> u32 {
+        unsafe {
+            if events.is_null() {
+                return from;
+            }
+            let events = &*events;
+            let (size, get) = match (events.size, events.get) {
+                (Some(size), Some(get)) => (size, get),
+                _ => return from,
+            };
+            let count = size(events);
+            let mut index = from;
+            while index < count {
+                let header = get(events, index);
+                if header.is_null() {
+                    index += 1;
+                    continue;
+                }
+                if until.is_some_and(|until| (*header).time > until) {
+                    break;
+                }
+                if (*header).space_id == clap_h::CORE_EVENT_SPACE_ID &&
+                   (*header).type_ == clap_h::EVENT_PARAM_VALUE {
+                    let event = &*(header as *const clap_h::EventParamValue);
+                    self.set_param(event.param_id, event.value);
+                }
+                index += 1;
+            }
+            index
+        }
+    }
+
+    unsafe fn next_event_time(&self, events: *const clap_h::InputEvents, index: u32) -> Option<u32> {
+        unsafe {
+            let events = events.as_ref()?;
+            if index >= events.size?(events) {
+                return None;
+            }
+            events.get?(events, index).as_ref().map(|h| h.time)
+        }
+    }
+
+    fn publish_outputs(&self) {
+        for &i in self.params.iter() {
+            let port = &self.descriptor.ports[i];
+            if port.desc == PortDescriptor::ControlOutput {
+                let value = port_to_param(port, self.controls[i], self.sample_rate);
+                self.values[i].store(value.to_bits(), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn plugin_init(_plugin: *const clap_h::Plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const clap_h::Plugin) {
+    unsafe {
+        plugin_deactivate(plugin);
+        let _ = Box::from_raw((*plugin).plugin_data as *mut Instance);
+    }
+}
+
+unsafe extern "C" fn plugin_activate(plugin: *const clap_h::Plugin,
+                                     sample_rate: f64,
+                                     _min_frames_count: u32,
+                                     max_frames_count: u32)
+                                     -> bool {
+    unsafe {
+        let instance = instance(plugin);
+        if !instance.handle.is_null() {
+            return false;
+        }
+        let handle = ffi::new_handle(instance.descriptor, sample_rate as c_ulong);
+        if handle.is_null() {
+            return false;
+        }
+
+        // Parameters set before activation used the nominal sample rate.
+        for &i in instance.params.iter() {
+            let port = &instance.descriptor.ports[i];
+            let value = f64::from_bits(instance.values[i].load(Ordering::Relaxed));
+            instance.controls[i] = param_to_port(port, value, sample_rate);
+            ffi::connect_port(handle, i as c_ulong, &mut instance.controls[i]);
+        }
+        for (i, port) in instance.descriptor.ports.iter().enumerate() {
+            if port.is_latency() {
+                ffi::connect_port(handle, i as c_ulong, &mut instance.controls[i]);
+            }
+        }
+
+        instance.handle = handle;
+        instance.sample_rate = sample_rate;
+        instance.max_frames = (max_frames_count as usize).max(1);
+        instance.silence = vec![0.0; instance.max_frames];
+        instance.discard = vec![0.0; instance.max_frames];
+        ffi::activate(handle);
+        true
+    }
+}
+
+unsafe extern "C" fn plugin_deactivate(plugin: *const clap_h::Plugin) {
+    unsafe {
+        let instance = instance(plugin);
+        if instance.handle.is_null() {
+            return;
+        }
+        ffi::deactivate(instance.handle);
+        ffi::cleanup(instance.handle);
+        instance.handle = ptr::null_mut();
+    }
+}
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const clap_h::Plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_stop_processing(_plugin: *const clap_h::Plugin) {}
+
+// Called on the audio thread, where Plugin::activate must not run; the host deactivates and
+// activates the plugin on the main thread instead.
+unsafe extern "C" fn plugin_reset(plugin: *const clap_h::Plugin) {
+    unsafe {
+        let instance = instance(plugin);
+        if let Some(host) = instance.host.as_ref() &&
+           let Some(request_restart) = host.request_restart {
+            request_restart(host);
+        }
+    }
+}
+
+unsafe extern "C" fn plugin_process(plugin: *const clap_h::Plugin,
+                                    process: *const clap_h::Process)
+                                    -> clap_h::ProcessStatus {
+    unsafe {
+        let instance = instance(plugin);
+        if instance.handle.is_null() || process.is_null() {
+            return clap_h::PROCESS_ERROR;
+        }
+        let process = &*process;
+        let frames = process.frames_count as usize;
+
+        let channel = |buffers: *const clap_h::AudioBuffer, count: u32, channel: usize| -> *mut Data {
+            if count == 0 || buffers.is_null() {
+                return ptr::null_mut();
+            }
+            let buffer = &*buffers;
+            if buffer.data32.is_null() || channel >= buffer.channel_count as usize {
+                return ptr::null_mut();
+            }
+            *buffer.data32.add(channel)
+        };
+
+        let mut event = 0;
+        let mut pos = 0;
+        while pos < frames {
+            event = instance.apply_events(process.in_events, event, Some(pos as u32));
+            let mut end = match instance.next_event_time(process.in_events, event) {
+                Some(time) => (time as usize).clamp(pos + 1, frames),
+                None => frames,
+            };
+            end = end.min(pos + instance.max_frames);
+
+            for (c, &i) in instance.inputs.iter().enumerate() {
+                let data = channel(process.audio_inputs, process.audio_inputs_count, c);
+                let data = if data.is_null() { instance.silence.as_mut_ptr() } else { data.add(pos) };
+                ffi::connect_port(instance.handle, i as c_ulong, data);
+            }
+            for (c, &i) in instance.outputs.iter().enumerate() {
+                let data = channel(process.audio_outputs, process.audio_outputs_count, c);
+                let data = if data.is_null() { instance.discard.as_mut_ptr() } else { data.add(pos) };
+                ffi::connect_port(instance.handle, i as c_ulong, data);
+            }
+            ffi::run(instance.handle, (end - pos) as c_ulong);
+            pos = end;
+        }
+        instance.apply_events(process.in_events, event, None);
+        instance.publish_outputs();
+
+        clap_h::PROCESS_CONTINUE
+    }
+}
+
+unsafe extern "C" fn plugin_get_extension(_plugin: *const clap_h::Plugin,
+                                          id: *const c_char)
+                                          -> *const c_void {
+    unsafe {
+        let id = CStr::from_ptr(id).to_bytes_with_nul();
+        if id == clap_h::EXT_PARAMS {
+            &PARAMS as *const _ as *const c_void
+        } else if id == clap_h::EXT_AUDIO_PORTS {
+            &AUDIO_PORTS as *const _ as *const c_void
+        } else if id == clap_h::EXT_LATENCY {
+            &LATENCY as *const _ as *const c_void
+        } else if id == clap_h::EXT_TAIL {
+            &TAIL as *const _ as *const c_void
+        } else {
+            ptr::null()
+        }
+    }
+}
+
+unsafe extern "C" fn plugin_on_main_thread(_plugin: *const clap_h::Plugin) {}
+
+unsafe fn copy_str(dest: *mut c_char, capacity: usize, src: &str) {
+    unsafe {
+        if capacity == 0 {
+            return;
+        }
+        let len = src.len().min(capacity - 1);
+        ptr::copy_nonoverlapping(src.as_ptr() as *const c_char, dest, len);
+        *dest.add(len) = 0;
+    }
+}
+
+unsafe extern "C" fn params_count(plugin: *const clap_h::Plugin) -> u32 {
+    unsafe { instance(plugin).params.len() as u32 }
+}
+
+unsafe extern "C" fn params_get_info(plugin: *const clap_h::Plugin,
+                                     param_index: u32,
+                                     param_info: *mut clap_h::ParamInfo)
+                                     -> bool {
+    unsafe {
+        let instance = instance(plugin);
+        let index = match instance.params.get(param_index as usize) {
+            Some(&index) => index,
+            None => return false,
+        };
+        let port = &instance.descriptor.ports[index];
+        let hint = port.hint.unwrap_or(ControlHint::empty());
+        let info = &mut *param_info;
+
+        info.id = index as clap_h::Id;
+        info.flags = 0;
+        if hint.intersects(ControlHint::HINT_INTEGER | ControlHint::HINT_TOGGLED) {
+            info.flags |= clap_h::PARAM_IS_STEPPED;
+        }
+        if port.desc == PortDescriptor::ControlOutput {
+            info.flags |= clap_h::PARAM_IS_READONLY;
+        } else {
+            info.flags |= clap_h::PARAM_IS_AUTOMATABLE;
+        }
+        info.cookie = ptr::null_mut();
+        copy_str(info.name.as_mut_ptr(), clap_h::NAME_SIZE, port.name);
+        copy_str(info.module.as_mut_ptr(), clap_h::PATH_SIZE, "");
+        let (lower, upper) = param_range(port);
+        info.min_value = lower;
+        info.max_value = upper;
+        info.default_value = param_default(port);
+        true
+    }
+}
+
+unsafe extern "C" fn params_get_value(plugin: *const clap_h::Plugin,
+                                      param_id: clap_h::Id,
+                                      out_value: *mut f64)
+                                      -> bool {
+    unsafe {
+        let instance = instance(plugin);
+        if !instance.params.contains(&(param_id as usize)) {
+            return false;
+        }
+        *out_value = f64::from_bits(instance.values[param_id as usize].load(Ordering::Relaxed));
+        true
+    }
+}
+
+unsafe extern "C" fn params_value_to_text(plugin: *const clap_h::Plugin,
+                                          param_id: clap_h::Id,
+                                          value: f64,
+                                          out_buffer: *mut c_char,
+                                          out_buffer_capacity: u32)
+                                          -> bool {
+    unsafe {
+        let instance = instance(plugin);
+        if !instance.params.contains(&(param_id as usize)) {
+            return false;
+        }
+        let port = &instance.descriptor.ports[param_id as usize];
+        let hint = port.hint.unwrap_or(ControlHint::empty());
+        let sample_rate = if instance.handle.is_null() { 1.0 } else { instance.sample_rate };
+        let value = param_to_port(port, value, sample_rate);
+        let text = if hint.contains(ControlHint::HINT_TOGGLED) {
+            if value > 0.0 { "on".to_string() } else { "off".to_string() }
+        } else if hint.contains(ControlHint::HINT_INTEGER) {
+            format!("{}", value.round())
+        } else {
+            format!("{:.3}", value)
+        };
+        copy_str(out_buffer, out_buffer_capacity as usize, &text);
+        true
+    }
+}
+
+unsafe extern "C" fn params_text_to_value(plugin: *const clap_h::Plugin,
+                                          param_id: clap_h::Id,
+                                          param_value_text: *const c_char,
+                                          out_value: *mut f64)
+                                          -> bool {
+    unsafe {
+        let instance = instance(plugin);
+        if !instance.params.contains(&(param_id as usize)) {
+            return false;
+        }
+        let port = &instance.descriptor.ports[param_id as usize];
+        let text = CStr::from_ptr(param_value_text).to_string_lossy();
+        let value = match text.trim() {
+            "on" => 1.0,
+            "off" => 0.0,
+            text => match text.parse::<Data>() {
+                Ok(value) => value,
+                Err(_) => return false,
+            },
+        };
+        let sample_rate = if instance.handle.is_null() { 1.0 } else { instance.sample_rate };
+        *out_value = port_to_param(port, value, sample_rate);
+        true
+    }
+}
+
+unsafe extern "C" fn params_flush(plugin: *const clap_h::Plugin,
+                                  input: *const clap_h::InputEvents,
+                                  _output: *const clap_h::OutputEvents) {
+    unsafe {
+        instance(plugin).apply_events(input, 0, None);
+    }
+}
+
+unsafe extern "C" fn audio_ports_count(plugin: *const clap_h::Plugin, is_input: bool) -> u32 {
+    unsafe {
+        let instance = instance(plugin);
+        let ports = if is_input { &instance.inputs } else { &instance.outputs };
+        if ports.is_empty() { 0 } else { 1 }
+    }
+}
+
+unsafe extern "C" fn audio_ports_get(plugin: *const clap_h::Plugin,
+                                     index: u32,
+                                     is_input: bool,
+                                     info: *mut clap_h::AudioPortInfo)
+                                     -> bool {
+    unsafe {
+        let instance = instance(plugin);
+        let ports = if is_input { &instance.inputs } else { &instance.outputs };
+        if index != 0 || ports.is_empty() {
+            return false;
+        }
+        let info = &mut *info;
+        info.id = 0;
+        copy_str(info.name.as_mut_ptr(), clap_h::NAME_SIZE, if is_input { "Audio In" } else { "Audio Out" });
+        info.flags = clap_h::AUDIO_PORT_IS_MAIN;
+        info.channel_count = ports.len() as u32;
+        info.port_type = match ports.len() {
+            1 => clap_h::PORT_MONO.as_ptr() as *const c_char,
+            2 => clap_h::PORT_STEREO.as_ptr() as *const c_char,
+            _ => ptr::null(),
+        };
+        info.in_place_pair = clap_h::INVALID_ID;
+        true
+    }
+}
+
+unsafe extern "C" fn latency_get(plugin: *const clap_h::Plugin) -> u32 {
+    unsafe {
+        let instance = instance(plugin);
+        if instance.handle.is_null() {
+            return 0;
+        }
+        let plugin = ffi::plugin(instance.handle);
+        call_user_code!(Some(plugin.latency()), "Plugin::latency").unwrap_or(0) as u32
+    }
+}
+
+unsafe extern "C" fn tail_get(plugin: *const clap_h::Plugin) -> u32 {
+    unsafe {
+        let instance = instance(plugin);
+        if instance.handle.is_null() {
+            return 0;
+        }
+        let plugin = ffi::plugin(instance.handle);
+        let tail = call_user_code!(Some(plugin.tail_samples()), "Plugin::tail_samples").unwrap_or(0);
+        tail.min(u32::MAX as usize) as u32
+    }
+}
@@ -0,0 +1,333 @@
+/*!
+ * A minimal in-process CLAP host, enough to drive a ```clap_entry``` from tests: it creates a
+ * plugin, reads its parameters and audio ports, and processes blocks of audio with parameter
+ * events. Restart requests from the plugin are recorded rather than acted on.
+ */
+
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::clap_h;
+
+/// A parameter as reported by the plugin.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamInfo {
+    pub id: u32,
+    pub flags: u32,
+    pub name: String,
+    pub min_value: f64,
+    pub max_value: f64,
+    pub default_value: f64,
+}
+
+/// A parameter change at a sample offset within a block.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParamEvent {
+    pub time: u32,
+    pub param_id: u32,
+    pub value: f64,
+}
+
+static HOST: clap_h::Host = clap_h::Host {
+    clap_version: clap_h::VERSION,
+    host_data: ptr::null_mut(),
+    name: c"ladspa.rs test host".as_ptr(),
+    vendor: c"".as_ptr(),
+    url: c"".as_ptr(),
+    version: c"".as_ptr(),
+    get_extension: Some(host_get_extension),
+    request_restart: Some(host_request_restart),
+    request_process: Some(host_request),
+    request_callback: Some(host_request),
+};
+
+// The pointers in HOST only refer to static data.
+unsafe impl Sync for clap_h::Host {}
+
+unsafe extern "C" fn host_get_extension(_host: *const clap_h::Host,
+                                        _extension_id: *const c_char)
+                                        -> *const c_void {
+    ptr::null()
+}
+
+unsafe extern "C" fn host_request(_host: *const clap_h::Host) {}
+
+unsafe extern "C" fn host_request_restart(host: *const clap_h::Host) {
+    unsafe {
+        if let Some(restart) = ((*host).host_data as *const AtomicBool).as_ref() {
+            restart.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+unsafe extern "C" fn events_size(list: *const clap_h::InputEvents) -> u32 {
+    unsafe { (*((*list).ctx as *const Vec<clap_h::EventParamValue>)).len() as u32 }
+}
+
+unsafe extern "C" fn events_get(list: *const clap_h::InputEvents,
+                                index: u32)
+                                -> *const clap_h::EventHeader {
+    unsafe {
+        let events = &*((*list).ctx as *const Vec<clap_h::EventParamValue>);
+        match events.get(index as usize) {
+            Some(event) => &event.header,
+            None => ptr::null(),
+        }
+    }
+}
+
+unsafe extern "C" fn events_try_push(_list: *const clap_h::OutputEvents,
+                                     _event: *const clap_h::EventHeader)
+                                     -> bool {
+    false
+}
+
+/// A plugin instance created through a plugin entry.
+pub struct Host {
+    entry: &'static clap_h::PluginEntry,
+    plugin: *const clap_h::Plugin,
+    active: bool,
+    // The plugin keeps pointing to the host, whose host_data points to restart.
+    #[allow(dead_code)]
+    host: Box<clap_h::Host>,
+    restart: Box<AtomicBool>,
+}
+
+impl Host {
+    /// Lists the plugin ids offered by the entry's plugin factory.
+    pub fn plugin_ids(entry: &'static clap_h::PluginEntry) -> Vec<String> {
+        unsafe {
+            let factory = match Self::init(entry) {
+                Some(factory) => factory,
+                None => return Vec::new(),
+            };
+            let count = factory.get_plugin_count.unwrap()(factory);
+            let ids = (0..count)
+                .filter_map(|i| factory.get_plugin_descriptor.unwrap()(factory, i).as_ref())
+                .map(|desc| CStr::from_ptr(desc.id).to_string_lossy().into_owned())
+                .collect();
+            entry.deinit.unwrap()();
+            ids
+        }
+    }
+
+    unsafe fn init(entry: &'static clap_h::PluginEntry) -> Option<&'static clap_h::PluginFactory> {
+        unsafe {
+            if !entry.init?(c"".as_ptr()) {
+                return None;
+            }
+            let factory = entry.get_factory?(clap_h::PLUGIN_FACTORY_ID.as_ptr() as *const c_char)
+                as *const clap_h::PluginFactory;
+            if factory.is_null() {
+                entry.deinit?();
+                return None;
+            }
+            Some(&*factory)
+        }
+    }
+
+    /// Creates and initializes the plugin with the given id.
+    pub fn new(entry: &'static clap_h::PluginEntry, plugin_id: &str) -> Option<Host> {
+        unsafe {
+            let factory = Self::init(entry)?;
+            let id = CString::new(plugin_id).ok()?;
+            let restart = Box::new(AtomicBool::new(false));
+            let host = Box::new(clap_h::Host { host_data: &*restart as *const AtomicBool as *mut c_void, ..HOST });
+            let plugin = factory.create_plugin?(factory, &*host, id.as_ptr());
+            if plugin.is_null() || !(*plugin).init?(plugin) {
+                if !plugin.is_null() {
+                    (*plugin).destroy?(plugin);
+                }
+                entry.deinit?();
+                return None;
+            }
+            Some(Host { entry, plugin, active: false, host, restart })
+        }
+    }
+
+    unsafe fn extension<T>(&self, id: &[u8]) -> Option<&T> {
+        unsafe {
+            let ext = (*self.plugin).get_extension?(self.plugin, id.as_ptr() as *const c_char);
+            (ext as *const T).as_ref()
+        }
+    }
+
+    /// The parameters of the plugin, in the order the plugin reports them.
+    pub fn params(&self) -> Vec<ParamInfo> {
+        unsafe {
+            let params = match self.extension::<clap_h::PluginParams>(clap_h::EXT_PARAMS) {
+                Some(params) => params,
+                None => return Vec::new(),
+            };
+            let count = params.count.unwrap()(self.plugin);
+            (0..count)
+                .filter_map(|i| {
+                    let mut info: clap_h::ParamInfo = mem::zeroed();
+                    if !params.get_info.unwrap()(self.plugin, i, &mut info) {
+                        return None;
+                    }
+                    Some(ParamInfo {
+                        id: info.id,
+                        flags: info.flags,
+                        name: CStr::from_ptr(info.name.as_ptr()).to_string_lossy().into_owned(),
+                        min_value: info.min_value,
+                        max_value: info.max_value,
+                        default_value: info.default_value,
+                    })
+                })
+                .collect()
+        }
+    }
+
+    /// The current value of a parameter.
+    pub fn param_value(&self, param_id: u32) -> Option<f64> {
+        unsafe {
+            let params = self.extension::<clap_h::PluginParams>(clap_h::EXT_PARAMS)?;
+            let mut value = 0.0;
+            if params.get_value?(self.plugin, param_id, &mut value) {
+                Some(value)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// The channel counts of the audio ports on the input or output side.
+    pub fn audio_ports(&self, is_input: bool) -> Vec<u32> {
+        unsafe {
+            let ports = match self.extension::<clap_h::PluginAudioPorts>(clap_h::EXT_AUDIO_PORTS) {
+                Some(ports) => ports,
+                None => return Vec::new(),
+            };
+            let count = ports.count.unwrap()(self.plugin, is_input);
+            (0..count)
+                .filter_map(|i| {
+                    let mut info: clap_h::AudioPortInfo = mem::zeroed();
+                    if ports.get.unwrap()(self.plugin, i, is_input, &mut info) {
+                        Some(info.channel_count)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        }
+    }
+
+    pub fn activate(&mut self, sample_rate: f64, max_frames: u32) -> bool {
+        unsafe {
+            if !self.active {
+                self.active = (*self.plugin).activate.unwrap()(self.plugin, sample_rate, 1, max_frames) &&
+                              (*self.plugin).start_processing.unwrap()(self.plugin);
+            }
+            self.active
+        }
+    }
+
+    pub fn deactivate(&mut self) {
+        unsafe {
+            if self.active {
+                (*self.plugin).stop_processing.unwrap()(self.plugin);
+                (*self.plugin).deactivate.unwrap()(self.plugin);
+                self.active = false;
+            }
+        }
+    }
+
+    /// Calls the plugin's ```reset```, which CLAP allows on the audio thread while active.
+    pub fn reset(&mut self) {
+        unsafe {
+            (*self.plugin).reset.unwrap()(self.plugin);
+        }
+    }
+
+    /// Whether the plugin asked for a restart since the last call.
+    pub fn take_restart_request(&self) -> bool {
+        self.restart.swap(false, Ordering::Relaxed)
+    }
+
+    /**
+     * Processes one block. ```inputs``` and ```outputs``` hold one slice per channel of the main
+     * audio ports and must all be at least ```frames``` long; ```events``` must be sorted by time.
+     * Returns the plugin's process status.
+     */
+    pub fn process(&mut self,
+                   frames: usize,
+                   inputs: &[&[f32]],
+                   outputs: &mut [&mut [f32]],
+                   events: &[ParamEvent])
+                   -> i32 {
+        unsafe {
+            let mut input_ptrs: Vec<*mut f32> = inputs.iter().map(|x| x.as_ptr() as *mut f32).collect();
+            let mut output_ptrs: Vec<*mut f32> = outputs.iter_mut().map(|x| x.as_mut_ptr()).collect();
+            let input_buffer = clap_h::AudioBuffer {
+                data32: input_ptrs.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: input_ptrs.len() as u32,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let mut output_buffer = clap_h::AudioBuffer {
+                data32: output_ptrs.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: output_ptrs.len() as u32,
+                latency: 0,
+                constant_mask: 0,
+            };
+
+            let events: Vec<clap_h::EventParamValue> = events
+                .iter()
+                .map(|e| clap_h::EventParamValue {
+                    header: clap_h::EventHeader {
+                        size: mem::size_of::<clap_h::EventParamValue>() as u32,
+                        time: e.time,
+                        space_id: clap_h::CORE_EVENT_SPACE_ID,
+                        type_: clap_h::EVENT_PARAM_VALUE,
+                        flags: 0,
+                    },
+                    param_id: e.param_id,
+                    cookie: ptr::null_mut(),
+                    note_id: -1,
+                    port_index: -1,
+                    channel: -1,
+                    key: -1,
+                    value: e.value,
+                })
+                .collect();
+            let in_events = clap_h::InputEvents {
+                ctx: &events as *const _ as *mut c_void,
+                size: Some(events_size),
+                get: Some(events_get),
+            };
+            let out_events = clap_h::OutputEvents {
+                ctx: ptr::null_mut(),
+                try_push: Some(events_try_push),
+            };
+
+            let process = clap_h::Process {
+                steady_time: -1,
+                frames_count: frames as u32,
+                transport: ptr::null(),
+                audio_inputs: &input_buffer,
+                audio_outputs: &mut output_buffer,
+                audio_inputs_count: if inputs.is_empty() { 0 } else { 1 },
+                audio_outputs_count: if outputs.is_empty() { 0 } else { 1 },
+                in_events: &in_events,
+                out_events: &out_events,
+            };
+            (*self.plugin).process.unwrap()(self.plugin, &process)
+        }
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        self.deactivate();
+        unsafe {
+            (*self.plugin).destroy.unwrap()(self.plugin);
+            self.entry.deinit.unwrap()();
+        }
+    }
+}
@@ -0,0 +1,329 @@
+/*!
+ * Support for [DSSI](http://dssi.sourceforge.net/), the extension of LADSPA for instruments.
+ *
+ * With the ```dssi``` feature, the exported ```dssi_descriptor``` wraps the LADSPA descriptor of
+ * every plugin returned by ```get_ladspa_descriptor```. Plugins that want MIDI implement
+ * ```SynthPlugin``` and return themselves from ```Plugin::as_synth```; all other plugins are run
+ * as plain effects. The types here are always available, so a plugin crate can implement
+ * ```SynthPlugin``` unconditionally and leave exporting it to the feature.
+ *
+ * Controllers mapped with ```SynthPlugin::midi_controller``` are handled by the host, which turns
+ * them into values on the mapped control port instead of passing them to ```run_synth```.
+ */
+
+#[cfg(feature = "dssi")]
+use std::os::raw::{c_int, c_ulong};
+#[cfg(feature = "dssi")]
+use std::ptr;
+#[cfg(feature = "dssi")]
+use std::slice;
+
+#[cfg(feature = "dssi")]
+use crate::ffi::{self, ladspa_h};
+use crate::{Plugin, PortConnection};
+
+// Prevent dssi_descriptor from being stripped during release builds
+#[cfg(feature = "dssi")]
+#[used]
+static EXPORT_KEEPER: unsafe extern "C" fn(c_ulong) -> *const dssi_h::Descriptor = dssi_descriptor;
+
+pub mod dssi_h {
+    use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong};
+
+    use crate::ffi::ladspa_h;
+
+    pub const API_VERSION: c_int = 1;
+
+    pub const CC_BITS: c_int = 0x20000000;
+    pub const NRPN_BITS: c_int = 0x40000000;
+    pub const NONE: c_int = -1;
+
+    pub const EVENT_NOTEON: c_uchar = 6;
+    pub const EVENT_NOTEOFF: c_uchar = 7;
+    pub const EVENT_KEYPRESS: c_uchar = 8;
+    pub const EVENT_CONTROLLER: c_uchar = 10;
+    pub const EVENT_PGMCHANGE: c_uchar = 11;
+    pub const EVENT_CHANPRESS: c_uchar = 12;
+    pub const EVENT_PITCHBEND: c_uchar = 13;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct Addr {
+        pub client: c_uchar,
+        pub port: c_uchar,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct Note {
+        pub channel: c_uchar,
+        pub note: c_uchar,
+        pub velocity: c_uchar,
+        pub off_velocity: c_uchar,
+        pub duration: c_uint,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct Ctrl {
+        pub channel: c_uchar,
+        pub unused: [c_uchar; 3],
+        pub param: c_uint,
+        pub value: i32,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub union EventData {
+        pub note: Note,
+        pub control: Ctrl,
+        pub raw8: [c_uchar; 12],
+        pub raw32: [c_uint; 3],
+    }
+
+    /// ALSA's ```snd_seq_event_t```. DSSI stores the frame offset within the block in ```tick```.
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct SeqEvent {
+        pub type_: c_uchar,
+        pub flags: c_uchar,
+        pub tag: c_uchar,
+        pub queue: c_uchar,
+        pub tick: c_uint,
+        pub tv_nsec: c_uint,
+        pub source: Addr,
+        pub dest: Addr,
+        pub data: EventData,
+    }
+
+    const _: () = assert!(std::mem::size_of::<SeqEvent>() == 28);
+
+    #[repr(C)]
+    pub struct ProgramDescriptor {
+        pub bank: c_ulong,
+        pub program: c_ulong,
+        pub name: *const c_char,
+    }
+
+    #[repr(C)]
+    #[allow(missing_copy_implementations)]
+    pub struct Descriptor {
+        pub api_version: c_int,
+        pub ladspa_plugin: *const ladspa_h::Descriptor,
+        pub configure: Option<unsafe extern "C" fn(instance: ladspa_h::Handle,
+                                                   key: *const c_char,
+                                                   value: *const c_char)
+                                                   -> *mut c_char>,
+        pub get_program: Option<unsafe extern "C" fn(instance: ladspa_h::Handle, index: c_ulong) -> *const ProgramDescriptor>,
+        pub select_program: Option<unsafe extern "C" fn(instance: ladspa_h::Handle, bank: c_ulong, program: c_ulong)>,
+        pub get_midi_controller_for_port: Option<unsafe extern "C" fn(instance: ladspa_h::Handle, port: c_ulong) -> c_int>,
+        pub run_synth: Option<unsafe extern "C" fn(instance: ladspa_h::Handle,
+                                                   sample_count: c_ulong,
+                                                   events: *mut SeqEvent,
+                                                   event_count: c_ulong)>,
+        pub run_synth_adding: Option<unsafe extern "C" fn(instance: ladspa_h::Handle,
+                                                          sample_count: c_ulong,
+                                                          events: *mut SeqEvent,
+                                                          event_count: c_ulong)>,
+        pub run_multiple_synths: Option<unsafe extern "C" fn(instance_count: c_ulong,
+                                                             instances: *mut ladspa_h::Handle,
+                                                             sample_count: c_ulong,
+                                                             events: *mut *mut SeqEvent,
+                                                             event_counts: *mut c_ulong)>,
+        pub run_multiple_synths_adding: Option<unsafe extern "C" fn(instance_count: c_ulong,
+                                                                    instances: *mut ladspa_h::Handle,
+                                                                    sample_count: c_ulong,
+                                                                    events: *mut *mut SeqEvent,
+                                                                    event_counts: *mut c_ulong)>,
+    }
+}
+
+/// A MIDI controller that the host should map onto a control input port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiController {
+    /// A standard 7-bit controller, 0 to 127.
+    Cc(u8),
+    /// A non-registered parameter number, 0 to 16383.
+    Nrpn(u16),
+}
+
+/// A MIDI message delivered to ```SynthPlugin::run_synth```. Channels are 0 based.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    KeyPressure { channel: u8, note: u8, pressure: u8 },
+    Controller { channel: u8, controller: u32, value: i32 },
+    ProgramChange { channel: u8, program: u32 },
+    ChannelPressure { channel: u8, pressure: i32 },
+    /// The bend amount, from -8192 to 8191.
+    PitchBend { channel: u8, value: i32 },
+}
+
+/// A MIDI event as passed by the host, with the frame in the current block it applies to.
+#[repr(transparent)]
+#[derive(Copy, Clone)]
+pub struct MidiEvent(pub dssi_h::SeqEvent);
+
+impl MidiEvent {
+    /// The offset of the event within the block, in samples.
+    pub fn frame(&self) -> usize {
+        self.0.tick as usize
+    }
+
+    /// Decodes the event, or returns ```None``` for sequencer events that carry no MIDI message.
+    pub fn message(&self) -> Option<MidiMessage> {
+        unsafe {
+            let note = self.0.data.note;
+            let control = self.0.data.control;
+            Some(match self.0.type_ {
+                dssi_h::EVENT_NOTEON if note.velocity == 0 => MidiMessage::NoteOff {
+                    channel: note.channel,
+                    note: note.note,
+                    velocity: 0,
+                },
+                dssi_h::EVENT_NOTEON => MidiMessage::NoteOn {
+                    channel: note.channel,
+                    note: note.note,
+                    velocity: note.velocity,
+                },
+                dssi_h::EVENT_NOTEOFF => MidiMessage::NoteOff {
+                    channel: note.channel,
+                    note: note.note,
+                    velocity: note.velocity,
+                },
+                dssi_h::EVENT_KEYPRESS => MidiMessage::KeyPressure {
+                    channel: note.channel,
+                    note: note.note,
+                    pressure: note.velocity,
+                },
+                dssi_h::EVENT_CONTROLLER => MidiMessage::Controller {
+                    channel: control.channel,
+                    controller: control.param,
+                    value: control.value,
+                },
+                dssi_h::EVENT_PGMCHANGE => MidiMessage::ProgramChange {
+                    channel: control.channel,
+                    program: control.value as u32,
+                },
+                dssi_h::EVENT_CHANPRESS => MidiMessage::ChannelPressure {
+                    channel: control.channel,
+                    pressure: control.value,
+                },
+                dssi_h::EVENT_PITCHBEND => MidiMessage::PitchBend {
+                    channel: control.channel,
+                    value: control.value,
+                },
+                _ => return None,
+            })
+        }
+    }
+}
+
+/**
+ * A plugin that receives MIDI through DSSI. Hosts call ```run_synth``` instead of ```Plugin::run```
+ * with the events for the block sorted by frame.
+ */
+pub trait SynthPlugin: Plugin {
+    fn run_synth<'a>(&mut self,
+                     sample_count: usize,
+                     ports: &[&'a PortConnection<'a>],
+                     events: &[MidiEvent]);
+
+    /// The MIDI controller the host should map onto the given control input port, if any.
+    fn midi_controller(&self, _port: usize) -> Option<MidiController> {
+        None
+    }
+}
+
+#[cfg(feature = "dssi")]
+static mut DESCRIPTORS: *mut Vec<*mut dssi_h::Descriptor> = ptr::null_mut();
+
+#[cfg(feature = "dssi")]
+extern "C" fn global_destruct() {
+    unsafe {
+        if !DESCRIPTORS.is_null() {
+            let descriptors = Box::from_raw(DESCRIPTORS);
+            for &descriptor in descriptors.iter() {
+                let _ = Box::from_raw(descriptor);
+            }
+            DESCRIPTORS = ptr::null_mut();
+        }
+    }
+}
+
+#[cfg(feature = "dssi")]
+#[doc(hidden)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dssi_descriptor(index: c_ulong) -> *const dssi_h::Descriptor {
+    unsafe {
+        if DESCRIPTORS.is_null() {
+            libc::atexit(global_destruct);
+            DESCRIPTORS = Box::into_raw(Box::new(Vec::<*mut dssi_h::Descriptor>::new()));
+        }
+
+        let descriptors = &*DESCRIPTORS;
+
+        if (index as usize) < descriptors.len() {
+            return descriptors[index as usize];
+        }
+
+        let ladspa_plugin = ffi::ladspa_descriptor(index);
+        if ladspa_plugin.is_null() {
+            return ptr::null();
+        }
+
+        let desc = Box::into_raw(Box::new(dssi_h::Descriptor {
+            api_version: dssi_h::API_VERSION,
+            ladspa_plugin,
+            configure: None,
+            get_program: None,
+            select_program: None,
+            get_midi_controller_for_port: Some(get_midi_controller_for_port),
+            run_synth: Some(run_synth),
+            run_synth_adding: None,
+            run_multiple_synths: None,
+            run_multiple_synths_adding: None,
+        }));
+
+        (*DESCRIPTORS).push(desc);
+        desc
+    }
+}
+
+#[cfg(feature = "dssi")]
+unsafe extern "C" fn get_midi_controller_for_port(instance: ladspa_h::Handle, port: c_ulong) -> c_int {
+    unsafe {
+        if instance.is_null() {
+            return dssi_h::NONE;
+        }
+        let plugin = ffi::plugin(instance);
+        let controller = call_user_code!(plugin.as_synth().and_then(|s| s.midi_controller(port as usize)),
+                                         "SynthPlugin::midi_controller");
+        match controller {
+            Some(MidiController::Cc(n)) => dssi_h::CC_BITS | (n as c_int & 0x7f),
+            Some(MidiController::Nrpn(n)) => dssi_h::NRPN_BITS | ((n as c_int & 0x3fff) << 7),
+            None => dssi_h::NONE,
+        }
+    }
+}
+
+#[cfg(feature = "dssi")]
+unsafe extern "C" fn run_synth(instance: ladspa_h::Handle,
+                               sample_count: c_ulong,
+                               events: *mut dssi_h::SeqEvent,
+                               event_count: c_ulong) {
+    unsafe {
+        let events = if events.is_null() || event_count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(events as *const MidiEvent, event_count as usize)
+        };
+        ffi::run_with(instance, "run_synth", sample_count, "SynthPlugin::run_synth", |plugin, ports| {
+            match plugin.as_synth() {
+                Some(synth) => synth.run_synth(sample_count as usize, ports, events),
+                None => plugin.run(sample_count as usize, ports),
+            }
+        });
+    }
+}
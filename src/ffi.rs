@@ -6,8 +6,11 @@ use std::os::raw::{c_char, c_ulong};
 use vec_map::VecMap;
 use std::cell::RefCell;
 use std::panic::AssertUnwindSafe;
+use std::time::Instant;
 
 use crate::PluginDescriptor;
+use crate::profile::{self, Counters};
+use crate::trace::{self, Call};
 use crate::get_ladspa_descriptor;
 
 // Prevent ladspa_descriptor from being stripped during release builds
@@ -23,12 +26,28 @@ macro_rules! call_user_code {
             Ok(v) => v,
             Err(e) => {
                 eprintln!("LADSPA Plugin Error in {}: {:?}", $context, e);
+                #[cfg(feature = "testing")]
+                $crate::ffi::count_panic();
                 Default::default()
             }
         }
     }}
 }
 
+#[cfg(feature = "testing")]
+thread_local!(static PANICS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) });
+
+#[cfg(feature = "testing")]
+pub(crate) fn count_panic() {
+    PANICS.with(|n| n.set(n.get() + 1));
+}
+
+/// The number of panics caught in user code on this thread.
+#[cfg(feature = "testing")]
+pub(crate) fn panic_count() -> usize {
+    PANICS.with(|n| n.get())
+}
+
 static mut DESCRIPTORS: *mut Vec<*mut ladspa_h::Descriptor> = ptr::null_mut();
 
 extern "C" fn global_destruct() {
@@ -130,6 +149,8 @@ pub mod ladspa_h {
     pub const HINT_SAMPLE_RATE: PortRangeHintDescriptor = 0x8;
     pub const HINT_LOGARITHMIC: PortRangeHintDescriptor = 0x10;
     pub const HINT_INTEGER: PortRangeHintDescriptor = 0x20;
+    pub const HINT_DEFAULT_MASK: PortRangeHintDescriptor = 0x3C0;
+    pub const HINT_DEFAULT_NONE: PortRangeHintDescriptor = 0x0;
     pub const HINT_DEFAULT_MINIMUM: PortRangeHintDescriptor = 0x40;
     pub const HINT_DEFAULT_LOW: PortRangeHintDescriptor = 0x80;
     pub const HINT_DEFAULT_MIDDLE: PortRangeHintDescriptor = 0xC0;
@@ -160,42 +181,7 @@ pub unsafe extern "C" fn ladspa_descriptor(index: c_ulong) -> *mut ladspa_h::Des
         match descriptor {
             None => ptr::null_mut(),
             Some(plugin) => {
-                let desc = Box::into_raw(Box::new(ladspa_h::Descriptor {
-                    unique_id: plugin.unique_id as c_ulong,
-                    label: CString::new(plugin.label).unwrap().into_raw(),
-                    properties: plugin.properties.bits(),
-                    name: CString::new(plugin.name).unwrap().into_raw(),
-                    maker: CString::new(plugin.maker).unwrap().into_raw(),
-                    copyright: CString::new(plugin.copyright).unwrap().into_raw(),
-                    port_count: plugin.ports.len() as c_ulong,
-                    port_descriptors: Box::into_raw(
-                        plugin.ports.iter().map(|port|
-                            port.desc as i32
-                        ).collect::<Vec<_>>().into_boxed_slice()) as *mut i32,
-                    port_names: Box::into_raw(
-                        plugin.ports.iter().map(|port|
-                            CString::new(port.name).unwrap().into_raw()
-                        ).collect::<Vec<_>>().into_boxed_slice()) as *mut *mut c_char,
-                    port_range_hints: Box::into_raw(
-                        plugin.ports.iter().map(|port|
-                            ladspa_h::PortRangeHint {
-                                hint_descriptor: port.hint.map(|x| x.bits()).unwrap_or(0) |
-                                port.default.map(|x| x as i32).unwrap_or(0),
-                                lower_bound: port.lower_bound.unwrap_or(0.0),
-                                upper_bound: port.upper_bound.unwrap_or(0.0),
-                            }
-                        ).collect::<Vec<_>>().into_boxed_slice()) as *mut ladspa_h::PortRangeHint,
-                    implementation_data: Box::into_raw(Box::new(plugin)) as *mut _,
-                    instantiate: Some(instantiate),
-                    connect_port: Some(connect_port),
-                    activate: Some(activate),
-                    run: Some(run),
-                    run_adding: Some(run_adding),
-                    set_run_adding_gain: Some(set_run_adding_gain),
-                    deactivate: Some(deactivate),
-                    cleanup: Some(cleanup),
-                }));
-
+                let desc = new_descriptor(plugin);
                 (*DESCRIPTORS).push(desc);
                 desc
             }
@@ -203,6 +189,62 @@ pub unsafe extern "C" fn ladspa_descriptor(index: c_ulong) -> *mut ladspa_h::Des
     }
 }
 
+/**
+ * The tail of an instance, as reported by ```Plugin::tail_samples```. LADSPA has no way to ask for
+ * it, so libraries built with this crate export this function for hosts that look it up with
+ * ```dlsym```, like those in ```host```.
+ */
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ladspa_rs_tail_samples(instance: ladspa_h::Handle) -> c_ulong {
+    unsafe {
+        let Some(handle) = handle_of(instance, "ladspa_rs_tail_samples") else { return 0 };
+        let tail = call_user_code!(Some(handle.plugin.tail_samples()), "Plugin::tail_samples").unwrap_or(0);
+        tail as c_ulong
+    }
+}
+
+// Builds the C descriptor of a plugin. The descriptor owns the plugin and its strings and arrays,
+// which drop_descriptor frees.
+pub(crate) fn new_descriptor(plugin: PluginDescriptor) -> *mut ladspa_h::Descriptor {
+    Box::into_raw(Box::new(ladspa_h::Descriptor {
+        unique_id: plugin.unique_id as c_ulong,
+        label: CString::new(plugin.label).unwrap().into_raw(),
+        properties: plugin.properties.bits(),
+        name: CString::new(plugin.name).unwrap().into_raw(),
+        maker: CString::new(plugin.maker).unwrap().into_raw(),
+        copyright: CString::new(plugin.copyright).unwrap().into_raw(),
+        port_count: plugin.ports.len() as c_ulong,
+        port_descriptors: Box::into_raw(
+            plugin.ports.iter().map(|port|
+                port.desc as i32
+            ).collect::<Vec<_>>().into_boxed_slice()) as *mut i32,
+        port_names: Box::into_raw(
+            plugin.ports.iter().map(|port|
+                CString::new(port.name).unwrap().into_raw()
+            ).collect::<Vec<_>>().into_boxed_slice()) as *mut *mut c_char,
+        port_range_hints: Box::into_raw(
+            plugin.ports.iter().map(|port|
+                ladspa_h::PortRangeHint {
+                    hint_descriptor: port.hint.map(|x| x.bits()).unwrap_or(0) |
+                    port.default.map(|x| x as i32).unwrap_or(0) |
+                    port.lower_bound.map(|_| ladspa_h::HINT_BOUNDED_BELOW).unwrap_or(0) |
+                    port.upper_bound.map(|_| ladspa_h::HINT_BOUNDED_ABOVE).unwrap_or(0),
+                    lower_bound: port.lower_bound.unwrap_or(0.0),
+                    upper_bound: port.upper_bound.unwrap_or(0.0),
+                }
+            ).collect::<Vec<_>>().into_boxed_slice()) as *mut ladspa_h::PortRangeHint,
+        implementation_data: Box::into_raw(Box::new(plugin)) as *mut _,
+        instantiate: Some(instantiate),
+        connect_port: Some(connect_port),
+        activate: Some(activate),
+        run: Some(run),
+        run_adding: Some(run_adding),
+        set_run_adding_gain: Some(set_run_adding_gain),
+        deactivate: Some(deactivate),
+        cleanup: Some(cleanup),
+    }))
+}
+
 // The handle that is given to ladspa.
 struct Handle<'a> {
     descriptor: &'static super::PluginDescriptor,
@@ -212,19 +254,252 @@ struct Handle<'a> {
     adding_gain: ladspa_h::Data,
     scratch_buffers: Vec<Vec<ladspa_h::Data>>,
     ptr_storage: Vec<*mut ladspa_h::Data>,
+    // What unconnected ports are connected to instead. Control dummies are never reallocated,
+    // since control port data points into them; audio dummies hold a second of audio from
+    // instantiate or connect_port on, so that runs do not allocate, and are reconnected before
+    // every run.
+    control_dummies: Box<[ladspa_h::Data]>,
+    audio_dummies: Vec<Vec<ladspa_h::Data>>,
+    sample_rate: c_ulong,
+    trace: trace::Recorder,
+    counters: Option<Counters>,
+    active: bool,
+    reported: u32,
+    calls: [(&'static str, u32); CALL_HISTORY],
+    call_count: usize,
+}
+
+// The number of distinct calls kept for misuse reports.
+const CALL_HISTORY: usize = 8;
+
+// Ways a host can misuse an instance. Each is reported once per instance.
+#[derive(Copy, Clone)]
+enum Misuse {
+    PortOutOfRange,
+    InvalidPort,
+    NullPort,
+    Unconnected,
+    ActivateTwice,
+    DeactivateInactive,
+    RunInactive,
+    CleanupActive,
+}
+
+// Reports a call that the LADSPA specification does not allow. Such calls never reach plugin code.
+fn host_error(msg: &str) {
+    eprintln!("LADSPA Host Error: {}", msg);
+}
+
+// The handle behind an instance, or None after reporting a NULL instance.
+unsafe fn handle_of<'a>(instance: ladspa_h::Handle, call: &str) -> Option<&'a mut Handle<'a>> {
+    if instance.is_null() {
+        host_error(&format!("{} called with a NULL instance", call));
+        None
+    } else {
+        unsafe { Some(&mut *(instance as *mut Handle<'a>)) }
+    }
+}
+
+impl<'a> Handle<'a> {
+    // Records a call for the pattern shown in misuse reports. Repeated calls are counted, so this
+    // never allocates.
+    fn record(&mut self, call: &'static str) {
+        if self.call_count > 0 {
+            let last = &mut self.calls[(self.call_count - 1) % CALL_HISTORY];
+            if last.0 == call {
+                last.1 = last.1.saturating_add(1);
+                return;
+            }
+        }
+        self.calls[self.call_count % CALL_HISTORY] = (call, 1);
+        self.call_count += 1;
+    }
+
+    // Traces a run call along with the current values of the control inputs.
+    fn trace_run(&self, call: &'static str, sample_count: c_ulong) {
+        if !self.trace.enabled() {
+            return;
+        }
+        self.trace.record(Call::Run { call, sample_count });
+        for (port, conn) in self.port_map.iter() {
+            if let super::PortData::ControlInput(&value) = conn.data {
+                self.trace.record(Call::Control { port, value });
+            }
+        }
+    }
+
+    fn call_pattern(&self) -> String {
+        let start = self.call_count.saturating_sub(CALL_HISTORY);
+        let calls: Vec<String> = (start..self.call_count)
+            .map(|i| match self.calls[i % CALL_HISTORY] {
+                (call, 1) => call.to_string(),
+                (call, n) => format!("{} x{}", call, n),
+            })
+            .collect();
+        format!("{}{}", if start > 0 { "..., " } else { "" }, calls.join(", "))
+    }
+
+    fn misuse<F: FnOnce(&Self) -> String>(&mut self, misuse: Misuse, msg: F) {
+        let bit = 1 << misuse as u32;
+        if self.reported & bit == 0 {
+            self.reported |= bit;
+            host_error(&format!("{}: {} (calls: {})", self.descriptor.label, msg(self), self.call_pattern()));
+        }
+    }
+
+    // Whether the instance may run: it is active and every port is connected, so that ports lines
+    // up with the descriptor's ports.
+    fn runnable(&mut self, call: &str) -> bool {
+        if !self.active {
+            self.misuse(Misuse::RunInactive, |_| format!("{} called on an inactive instance", call));
+            return false;
+        }
+        if self.ports.len() == self.descriptor.ports.len() {
+            return true;
+        }
+        let unconnected: Vec<usize> = (0..self.descriptor.ports.len())
+            .filter(|&i| !self.port_map.contains_key(i))
+            .collect();
+        if unconnected.iter().all(|&i| self.descriptor.ports[i].optional) {
+            for i in unconnected {
+                unsafe { self.connect(i, ptr::null_mut()) };
+            }
+            if self.ports.len() == self.descriptor.ports.len() {
+                return true;
+            }
+        }
+        self.misuse(Misuse::Unconnected, |handle| {
+            let unconnected: Vec<String> = (0..handle.descriptor.ports.len())
+                .filter(|&i| !handle.port_map.contains_key(i))
+                .map(|i| i.to_string())
+                .collect();
+            format!("{} called before connecting ports {}", call, unconnected.join(", "))
+        });
+        false
+    }
+
+    // Connects a port to data_location, or to a scratch buffer if it is NULL.
+    unsafe fn connect(&mut self, port_num: usize, data_location: *mut ladspa_h::Data) {
+        let port = self.descriptor.ports[port_num];
+        let connected = !data_location.is_null();
+        let data_location = if connected { data_location } else { self.dummy(port_num) };
+
+        // Create appropriate pointers to port data. Mutable locations are wrapped in refcells.
+        let data = unsafe {
+            match port.desc {
+                super::PortDescriptor::AudioInput => {
+                    super::PortData::AudioInput(slice::from_raw_parts(data_location, 0))
+                }
+                super::PortDescriptor::AudioOutput => {
+                    super::PortData::AudioOutput(RefCell::new(slice::from_raw_parts_mut(data_location, 0)))
+                }
+                super::PortDescriptor::ControlInput => {
+                    super::PortData::ControlInput(&*data_location)
+                }
+                super::PortDescriptor::ControlOutput => {
+                    super::PortData::ControlOutput(RefCell::new(&mut *data_location))
+                }
+                super::PortDescriptor::Invalid => return,
+            }
+        };
+
+        let conn = super::PortConnection {
+            port,
+            data,
+            connected,
+        };
+        self.port_map.insert(port_num, conn);
+
+        // Depends on the assumption that ports will be recreated whenever port_map changes. Once
+        // port_map holds every port its storage no longer moves, so the references stay valid.
+        if self.port_map.len() == self.descriptor.ports.len() {
+            let port_map: *const VecMap<super::PortConnection<'a>> = &self.port_map;
+            self.ports = unsafe { (*port_map).values().collect() };
+        }
+    }
+
+    // The scratch buffer standing in for an unconnected port. Control inputs read their default.
+    fn dummy(&mut self, port_num: usize) -> *mut ladspa_h::Data {
+        let port = &self.descriptor.ports[port_num];
+        match port.desc {
+            super::PortDescriptor::ControlInput | super::PortDescriptor::ControlOutput => {
+                let value = match port.desc {
+                    super::PortDescriptor::ControlInput => port.resolved_default(self.sample_rate),
+                    _ => None,
+                };
+                self.control_dummies[port_num] = value.unwrap_or(0.0);
+                &mut self.control_dummies[port_num]
+            }
+            _ => {
+                let buffer = &mut self.audio_dummies[port_num];
+                if buffer.is_empty() {
+                    buffer.resize(dummy_samples(self.sample_rate), 0.0);
+                }
+                buffer.as_mut_ptr()
+            }
+        }
+    }
+
+    // Sets the length of the audio ports to sample_count. Audio dummies only grow for blocks
+    // longer than a second, which is the one case where a run allocates.
+    unsafe fn resize_ports(&mut self, sample_count: usize) {
+        for (i, port) in self.port_map.iter_mut() {
+            let audio = matches!(port.data, super::PortData::AudioInput(_) | super::PortData::AudioOutput(_));
+            let dummy = if port.connected || !audio {
+                None
+            } else {
+                let buffer = &mut self.audio_dummies[i];
+                if buffer.len() < sample_count {
+                    buffer.resize(sample_count, 0.0);
+                }
+                Some(buffer.as_mut_ptr())
+            };
+            unsafe {
+                match port.data {
+                    super::PortData::AudioOutput(ref mut data) => {
+                        let ptr = dummy.unwrap_or(data.borrow_mut().as_mut_ptr());
+                        *data.borrow_mut() = slice::from_raw_parts_mut(ptr, sample_count);
+                    }
+                    super::PortData::AudioInput(ref mut data) => {
+                        let ptr = dummy.unwrap_or(data.as_ptr() as *mut ladspa_h::Data);
+                        *data = slice::from_raw_parts(ptr, sample_count);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Fills the audio outputs that the host connected with silence.
+    unsafe fn silence_outputs(&mut self, sample_count: usize) {
+        for (_, port) in self.port_map.iter_mut().filter(|(_, port)| port.connected) {
+            if let super::PortData::AudioOutput(ref mut data) = port.data {
+                let ptr = data.borrow_mut().as_mut_ptr();
+                unsafe { slice::from_raw_parts_mut(ptr, sample_count).fill(0.0) };
+            }
+        }
+    }
 }
 
 unsafe extern "C" fn set_run_adding_gain(instance: ladspa_h::Handle, gain: ladspa_h::Data) {
     unsafe {
-        let handle = &mut *(instance as *mut Handle);
+        let Some(handle) = handle_of(instance, "set_run_adding_gain") else { return };
+        handle.record("set_run_adding_gain");
+        handle.trace.record(Call::SetRunAddingGain { gain });
         handle.adding_gain = gain;
     }
 }
 
 unsafe extern "C" fn run_adding(instance: ladspa_h::Handle, sample_count: c_ulong) {
     unsafe {
-        let handle = &mut *(instance as *mut Handle);
+        let Some(handle) = handle_of(instance, "run_adding") else { return };
+        handle.record("run_adding");
+        handle.trace_run("run_adding", sample_count);
+        if !handle.runnable("run_adding") {
+            return;
+        }
         let samples = sample_count as usize;
+        handle.resize_ports(samples);
 
         // 1. Prepare Scratch Buffers
         // Ensure we have enough buffers for all output ports
@@ -267,10 +542,19 @@ unsafe extern "C" fn run_adding(instance: ladspa_h::Handle, sample_count: c_ulon
         }
 
         // 3. Run the Plugin (Writes to scratch buffers)
-        call_user_code!({
+        #[cfg(feature = "testing")]
+        let realtime = crate::testing::realtime::Realtime::enter();
+        let start = handle.counters.as_ref().map(|_| Instant::now());
+        let ran = call_user_code!({
             handle.plugin.run(samples, &handle.ports);
             Some(())
         }, "Plugin::run_adding");
+        if let (Some(counters), Some(start)) = (handle.counters.as_mut(), start) {
+            counters.record(samples, start.elapsed(), ran.is_none());
+        }
+        report_latency(handle);
+        #[cfg(feature = "testing")]
+        drop(realtime);
 
         // 4. Mix Scratch into Host Buffers and Restore Pointers
         let mut host_ptr_iter = handle.ptr_storage.iter();
@@ -300,97 +584,197 @@ unsafe extern "C" fn instantiate(descriptor: *const ladspa_h::Descriptor,
                           sample_rate: c_ulong)
                           -> ladspa_h::Handle {
     unsafe {
+        if descriptor.is_null() {
+            host_error("instantiate called with a NULL descriptor");
+            return ptr::null_mut();
+        }
         let desc = &*descriptor;
         let rust_desc = &*(desc.implementation_data as *const PluginDescriptor);
-
-        let rust_plugin = match call_user_code!(Some((rust_desc.new)(rust_desc, sample_rate)),
-                                                "PluginDescriptor::run") {
-            Some(plug) => plug,
-            None => return ptr::null_mut(),
-        };
-        let port_map: VecMap<super::PortConnection> = VecMap::new();
-        let ports: Vec<&super::PortConnection> = Vec::new();
-
-        Box::into_raw(Box::new(Handle {
-            descriptor: rust_desc,
-            plugin: rust_plugin,
-            port_map,
-            ports,
-            adding_gain: 1.0,
-            scratch_buffers: Vec::new(),
-            ptr_storage: Vec::new(),
-        })) as *mut _
+        new_handle(rust_desc, sample_rate)
     }
 }
 
-unsafe extern "C" fn connect_port(instance: ladspa_h::Handle,
+// The length of an audio dummy: a second of audio, which covers the block sizes that hosts use.
+fn dummy_samples(sample_rate: c_ulong) -> usize {
+    (sample_rate as usize).max(1)
+}
+
+// Creates an instance of the plugin behind a handle that the other exported functions accept.
+// Shared with the other plugin APIs that reuse the LADSPA calling sequence.
+pub(crate) fn new_handle(rust_desc: &'static PluginDescriptor,
+                         sample_rate: c_ulong)
+                         -> ladspa_h::Handle {
+    let rust_plugin = match call_user_code!(Some((rust_desc.new)(rust_desc, sample_rate)),
+                                            "PluginDescriptor::run") {
+        Some(plug) => plug,
+        None => return ptr::null_mut(),
+    };
+    let trace = trace::Recorder::new();
+    trace.record(Call::Instantiate { label: rust_desc.label, sample_rate });
+    let port_map: VecMap<super::PortConnection> = VecMap::new();
+    let ports: Vec<&super::PortConnection> = Vec::new();
+
+    Box::into_raw(Box::new(Handle {
+        descriptor: rust_desc,
+        plugin: rust_plugin,
+        port_map,
+        ports,
+        adding_gain: 1.0,
+        scratch_buffers: Vec::new(),
+        ptr_storage: Vec::new(),
+        control_dummies: vec![0.0; rust_desc.ports.len()].into_boxed_slice(),
+        audio_dummies: rust_desc.ports.iter()
+            .map(|port| match port.desc {
+                super::PortDescriptor::AudioInput | super::PortDescriptor::AudioOutput if port.optional => {
+                    vec![0.0; dummy_samples(sample_rate)]
+                }
+                _ => Vec::new(),
+            })
+            .collect(),
+        sample_rate,
+        trace,
+        counters: if profile::enabled() { Some(Counters::new()) } else { None },
+        active: false,
+        reported: 0,
+        calls: [("instantiate", 1); CALL_HISTORY],
+        call_count: 1,
+    })) as *mut _
+}
+
+pub(crate) unsafe extern "C" fn connect_port(instance: ladspa_h::Handle,
                            port_num: c_ulong,
                            data_location: *mut ladspa_h::Data) {
     unsafe {
-        let handle = &mut *(instance as *mut Handle);
-
-        let port = handle.descriptor.ports[port_num as usize];
-
-        // Create appropriate pointers to port data. Mutable locations are wrapped in refcells.
-        let data = match port.desc {
-            super::PortDescriptor::AudioInput => {
-                super::PortData::AudioInput(slice::from_raw_parts(data_location, 0))
-            }
-            super::PortDescriptor::AudioOutput => {
-                super::PortData::AudioOutput(RefCell::new(slice::from_raw_parts_mut(data_location,
-                                                                                    0)))
-            }
-            super::PortDescriptor::ControlInput => {
-                super::PortData::ControlInput(&*data_location)
+        let Some(handle) = handle_of(instance, "connect_port") else { return };
+        handle.record("connect_port");
+        handle.trace.record(Call::ConnectPort { port: port_num, location: data_location as usize });
+
+        let port = match handle.descriptor.ports.get(port_num as usize) {
+            Some(&port) => port,
+            None => {
+                let count = handle.descriptor.ports.len();
+                handle.misuse(Misuse::PortOutOfRange, |_| {
+                    format!("connect_port called for port {}, but there are {} ports", port_num, count)
+                });
+                return;
             }
-            super::PortDescriptor::ControlOutput => {
-                super::PortData::ControlOutput(RefCell::new(&mut *data_location))
-            }
-            super::PortDescriptor::Invalid => panic!("Invalid port descriptor!"),
-        };
-
-        let conn = super::PortConnection {
-            port,
-            data,
         };
-        handle.port_map.insert(port_num as usize, conn);
 
-        // Depends on the assumption that ports will be recreated whenever port_map changes
-        if handle.port_map.len() == handle.descriptor.ports.len() {
-            handle.ports = handle.port_map.values().collect();
+        if port.desc == super::PortDescriptor::Invalid {
+            handle.misuse(Misuse::InvalidPort, |_| {
+                format!("connect_port called for port {}, which has an invalid port descriptor", port_num)
+            });
+            return;
         }
+        if data_location.is_null() && !port.optional {
+            handle.misuse(Misuse::NullPort, |_| {
+                format!("connect_port called with NULL for port {}, which is not optional", port_num)
+            });
+        }
+        handle.connect(port_num as usize, data_location);
     }
 }
 
-unsafe extern "C" fn run(instance: ladspa_h::Handle, sample_count: c_ulong) {
+pub(crate) unsafe extern "C" fn run(instance: ladspa_h::Handle, sample_count: c_ulong) {
     unsafe {
-        let handle = &mut *(instance as *mut Handle);
-        for (_, port) in handle.port_map.iter_mut() {
-            match port.data {
-                super::PortData::AudioOutput(ref mut data) => {
-                    let ptr = data.borrow_mut().as_mut_ptr();
-                    *data.borrow_mut() = slice::from_raw_parts_mut(ptr, sample_count as usize);
-                }
-                super::PortData::AudioInput(ref mut data) => {
-                    let ptr = data.as_ptr();
-                    *data = slice::from_raw_parts(ptr, sample_count as usize);
-                }
-                _ => {}
-            }
+        run_with(instance, "run", sample_count, "Plugin::run", |plugin, ports| {
+            plugin.run(sample_count as usize, ports)
+        });
+    }
+}
+
+// Resizes the audio ports to sample_count and hands the plugin and its ports to plugin code.
+// Shared with the other plugin APIs that have their own run callbacks.
+pub(crate) unsafe fn run_with<F>(instance: ladspa_h::Handle,
+                                 call: &'static str,
+                                 sample_count: c_ulong,
+                                 context: &str,
+                                 f: F)
+    where F: for<'a> FnOnce(&mut (dyn super::Plugin + Send), &[&'a super::PortConnection<'a>])
+{
+    unsafe {
+        let Some(handle) = handle_of(instance, call) else { return };
+        handle.record(call);
+        handle.trace_run(call, sample_count);
+        if !handle.runnable(call) {
+            handle.silence_outputs(sample_count as usize);
+            return;
         }
+        #[cfg(feature = "testing")]
+        let _realtime = crate::testing::realtime::Realtime::enter();
+        handle.resize_ports(sample_count as usize);
+        let start = handle.counters.as_ref().map(|_| Instant::now());
         let mut handle = AssertUnwindSafe(handle);
-        call_user_code!({
-                            let handle = &mut *handle;
-                            handle.plugin.run(sample_count as usize, &handle.ports);
-                            Some(())
-                        },
-                        "Plugin::run");
+        let ran = call_user_code!({
+                                      let handle = &mut *handle;
+                                      f(&mut *handle.plugin, &handle.ports);
+                                      Some(())
+                                  },
+                                  context);
+        if let (Some(counters), Some(start)) = (handle.counters.as_mut(), start) {
+            counters.record(sample_count as usize, start.elapsed(), ran.is_none());
+        }
+        report_latency(&mut handle);
+    }
+}
+
+// Writes Plugin::latency to the ports declared with Port::latency.
+fn report_latency(handle: &mut Handle) {
+    let latency = call_user_code!(Some(handle.plugin.latency()), "Plugin::latency").unwrap_or(0);
+    for (_, port) in handle.port_map.iter_mut() {
+        if let super::PortData::ControlOutput(ref data) = port.data && port.port.is_latency() {
+            **data.borrow_mut() = latency as ladspa_h::Data;
+        }
     }
 }
 
-unsafe extern "C" fn activate(instance: ladspa_h::Handle) {
+// Gives the other plugin APIs access to the plugin behind a handle.
+#[cfg(any(feature = "dssi", feature = "clap"))]
+pub(crate) unsafe fn plugin<'a>(instance: ladspa_h::Handle) -> &'a mut (dyn super::Plugin + Send) {
     unsafe {
         let handle = &mut *(instance as *mut Handle);
+        &mut *handle.plugin
+    }
+}
+
+// Whether a descriptor instantiates through this wrapper, like those created by new_descriptor,
+// so that its instances are Handles and its implementation data a PluginDescriptor.
+#[cfg(feature = "host")]
+pub(crate) fn is_own_descriptor(descriptor: *const ladspa_h::Descriptor) -> bool {
+    let wrapper: unsafe extern "C" fn(*const ladspa_h::Descriptor, c_ulong) -> ladspa_h::Handle = instantiate;
+    unsafe { descriptor.as_ref().and_then(|d| d.instantiate).is_some_and(|f| ptr::fn_addr_eq(f, wrapper)) }
+}
+
+// Whether a port of a descriptor created by new_descriptor may be connected to NULL. Always
+// false for other descriptors, which cannot say.
+#[cfg(feature = "host")]
+pub(crate) fn is_optional_port(descriptor: &ladspa_h::Descriptor, port: usize) -> bool {
+    if !is_own_descriptor(descriptor) {
+        return false;
+    }
+    let rust_desc = unsafe { &*(descriptor.implementation_data as *const PluginDescriptor) };
+    rust_desc.ports.get(port).is_some_and(|x| x.optional)
+}
+
+// The profile of an instance created through ladspa_descriptor, if it is being profiled.
+#[cfg(feature = "host")]
+pub(crate) unsafe fn profile(instance: ladspa_h::Handle) -> Option<profile::Profile> {
+    unsafe {
+        let handle = handle_of(instance, "profile")?;
+        handle.counters.as_ref().map(|counters| counters.profile(handle.descriptor.label))
+    }
+}
+
+pub(crate) unsafe extern "C" fn activate(instance: ladspa_h::Handle) {
+    unsafe {
+        let Some(handle) = handle_of(instance, "activate") else { return };
+        handle.record("activate");
+        handle.trace.record(Call::Activate);
+        if handle.active {
+            handle.misuse(Misuse::ActivateTwice, |_| "activate called twice without deactivate".to_string());
+            return;
+        }
+        handle.active = true;
         let mut handle = AssertUnwindSafe(handle);
         call_user_code!({
             handle.plugin.activate();
@@ -399,9 +783,16 @@ unsafe extern "C" fn activate(instance: ladspa_h::Handle) {
     }
 }
 
-unsafe extern "C" fn deactivate(instance: ladspa_h::Handle) {
+pub(crate) unsafe extern "C" fn deactivate(instance: ladspa_h::Handle) {
     unsafe {
-        let handle = &mut *(instance as *mut Handle);
+        let Some(handle) = handle_of(instance, "deactivate") else { return };
+        handle.record("deactivate");
+        handle.trace.record(Call::Deactivate);
+        if !handle.active {
+            handle.misuse(Misuse::DeactivateInactive, |_| "deactivate called on an inactive instance".to_string());
+            return;
+        }
+        handle.active = false;
         let mut handle = AssertUnwindSafe(handle);
         call_user_code!({
             handle.plugin.deactivate();
@@ -410,8 +801,22 @@ unsafe extern "C" fn deactivate(instance: ladspa_h::Handle) {
     }
 }
 
-unsafe extern "C" fn cleanup(instance: ladspa_h::Handle) {
+pub(crate) unsafe extern "C" fn cleanup(instance: ladspa_h::Handle) {
     unsafe {
+        let Some(handle) = handle_of(instance, "cleanup") else { return };
+        handle.record("cleanup");
+        handle.trace.record(Call::Cleanup);
+        if let Some(ref counters) = handle.counters {
+            profile::dump(&counters.profile(handle.descriptor.label));
+        }
+        if handle.active {
+            handle.misuse(Misuse::CleanupActive, |_| "cleanup called without deactivate".to_string());
+            let mut handle = AssertUnwindSafe(handle);
+            call_user_code!({
+                handle.plugin.deactivate();
+                Some(())
+            }, "Plugin::deactivate");
+        }
         let _ = Box::from_raw(instance as *mut Handle);
     }
 }
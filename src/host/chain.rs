@@ -0,0 +1,320 @@
+/*!
+ * A serial chain of plugins from one or more libraries, for offline rendering.
+ *
+ * Chains are built from ```Stage```s or parsed from a ```gst-launch```-like description, in which
+ * stages are separated by ```!``` and each names a library and a plugin label followed by control
+ * settings:
+ *
+ * ```rust,ignore
+ * let mut chain = Chain::parse("delay.so:stereo_delay left=0.3 ! ringmod.so:ring_mod freq=0.01", 48000)?;
+ * let outputs = chain.render(&inputs, length, 512);
+ * ```
+ *
+ * Libraries are resolved with ```find_library```. A control is named by its port index, by the
+ * symbol of its port name (lower case, with everything but letters and digits replaced by
+ * ```_```), or by a prefix of that symbol ending at a ```_```; the first control input in port
+ * order that matches is set, so ```left``` names ```Left Delay (seconds)``` of the stereo delay.
+ *
+ * Consecutive stages are linked output to input in port order, and a mono output feeds every input
+ * of the next stage. Buffers are allocated once when the chain is built: a stage writes into the
+ * buffers it read from unless its plugin has ```PROP_INPLACE_BROKEN```, and buffers whose signal
+ * has been consumed are reused by later stages.
+ *
+ * Each stage that has a latency output is run for one sample when the chain is built, so that it
+ * reports its latency. ```render``` removes the total latency of the stages from the start of its
+ * output, and ```render_tail``` also keeps running the chain on silence for the tails the stages
+ * report through ```Instance::tail_samples```.
+ */
+
+use std::error;
+use std::fmt;
+
+use super::{find_library, render_offline, Instance, Library, PluginRef, Tail};
+use crate::{Data, PortDescriptor, Properties};
+
+/// The largest number of samples a stage is run for at once; longer blocks are split.
+pub const MAX_BLOCK: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The chain contains no stages.
+    Empty,
+    /// The description could not be parsed.
+    Syntax(String),
+    /// A library could not be found or opened.
+    Library { library: String, error: String },
+    /// A library has no plugin with the given label.
+    UnknownPlugin { library: String, label: String },
+    /// A control setting named no control input of the plugin.
+    UnknownControl { plugin: String, control: String },
+    /// A stage's audio outputs cannot be linked to the next stage's audio inputs.
+    ChannelMismatch { from: String, to: String, outputs: usize, inputs: usize },
+    /// ```instantiate``` returned NULL.
+    Instantiate(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Empty => write!(f, "the chain contains no plugins"),
+            Error::Syntax(ref msg) => write!(f, "syntax error: {}", msg),
+            Error::Library { ref library, ref error } => write!(f, "cannot open {}: {}", library, error),
+            Error::UnknownPlugin { ref library, ref label } => {
+                write!(f, "{} has no plugin labelled {:?}", library, label)
+            }
+            Error::UnknownControl { ref plugin, ref control } => {
+                write!(f, "{} has no control input named {:?}", plugin, control)
+            }
+            Error::ChannelMismatch { ref from, ref to, outputs, inputs } => {
+                write!(f, "cannot link {} audio outputs of {} to {} audio inputs of {}",
+                       outputs, from, inputs, to)
+            }
+            Error::Instantiate(ref plugin) => write!(f, "cannot instantiate {}", plugin),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A plugin in a chain with the control inputs to set on it, as port index and value.
+#[derive(Clone)]
+pub struct Stage {
+    pub plugin: PluginRef,
+    pub controls: Vec<(usize, Data)>,
+}
+
+fn symbol(name: &str) -> String {
+    name.trim().chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect()
+}
+
+/// The control input of ```plugin``` named by ```control```, as described in the module docs.
+pub fn find_control(plugin: PluginRef, control: &str) -> Option<usize> {
+    let ports = plugin.ports();
+    let is_control = |port: usize| ports.get(port).is_some_and(|x| x.port.desc == PortDescriptor::ControlInput);
+    if let Ok(port) = control.parse::<usize>() {
+        return Some(port).filter(|&x| is_control(x));
+    }
+    let control = symbol(control);
+    (0..ports.len()).filter(|&x| is_control(x)).find(|&x| {
+        let symbol = symbol(&ports[x].name);
+        symbol == control || symbol.strip_prefix(&control).is_some_and(|rest| rest.starts_with('_'))
+    })
+}
+
+impl Stage {
+    /**
+     * Parses one stage of a chain description, ```library:label name=value ...```. The library
+     * name may itself contain colons; the label follows the last one.
+     */
+    pub fn parse(spec: &str) -> Result<Stage, Error> {
+        let mut words = spec.split_whitespace();
+        let plugin = words.next().ok_or_else(|| Error::Syntax("empty stage".to_string()))?;
+        let (library, label) = plugin
+            .rsplit_once(':')
+            .ok_or_else(|| Error::Syntax(format!("expected library:label, found {:?}", plugin)))?;
+        let path = find_library(library).ok_or_else(|| Error::Library {
+            library: library.to_string(),
+            error: "not found".to_string(),
+        })?;
+        let plugin = Library::open(&path)
+            .map_err(|e| Error::Library { library: library.to_string(), error: e.to_string() })?
+            .find(label)
+            .ok_or_else(|| Error::UnknownPlugin { library: library.to_string(), label: label.to_string() })?;
+
+        let controls = words
+            .map(|word| {
+                let (control, value) = word
+                    .split_once('=')
+                    .ok_or_else(|| Error::Syntax(format!("expected name=value, found {:?}", word)))?;
+                let value = value
+                    .parse::<Data>()
+                    .map_err(|_| Error::Syntax(format!("{:?} is not a number", value)))?;
+                let port = find_control(plugin, control).ok_or_else(|| Error::UnknownControl {
+                    plugin: plugin.label(),
+                    control: control.to_string(),
+                })?;
+                Ok((port, value))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Stage { plugin, controls })
+    }
+}
+
+struct Node {
+    instance: Instance,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+}
+
+/// A running chain of plugin instances.
+pub struct Chain {
+    nodes: Vec<Node>,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+    buffers: Vec<Box<[Data]>>,
+    sample_rate: u64,
+}
+
+impl Chain {
+    /**
+     * Instantiates and activates the stages at ```sample_rate``` and plans their buffers. Fails if
+     * a stage cannot be instantiated or linked to the next.
+     */
+    pub fn new(stages: &[Stage], sample_rate: u64) -> Result<Chain, Error> {
+        let first = stages.first().ok_or(Error::Empty)?;
+        let mut count = 0;
+        let mut free: Vec<usize> = Vec::new();
+        let mut allocate = |free: &mut Vec<usize>| free.pop().unwrap_or_else(|| {
+            count += 1;
+            count - 1
+        });
+
+        let inputs: Vec<usize> = first.plugin.ports_of(PortDescriptor::AudioInput).iter().map(|_| allocate(&mut free)).collect();
+        let mut current = inputs.clone();
+        let mut previous: Option<PluginRef> = None;
+        let mut nodes = Vec::with_capacity(stages.len());
+        for stage in stages {
+            let plugin = stage.plugin;
+            let input_ports = plugin.ports_of(PortDescriptor::AudioInput);
+            let output_ports = plugin.ports_of(PortDescriptor::AudioOutput);
+            let node_inputs = match previous {
+                Some(_) if current.len() == 1 => vec![current[0]; input_ports.len()],
+                Some(previous) if current.len() != input_ports.len() => {
+                    return Err(Error::ChannelMismatch {
+                        from: previous.label(),
+                        to: plugin.label(),
+                        outputs: current.len(),
+                        inputs: input_ports.len(),
+                    });
+                }
+                _ => current.clone(),
+            };
+
+            // Everything the previous stage wrote is dead once this stage has run.
+            let mut dead = current.iter();
+            let node_outputs: Vec<usize> = output_ports
+                .iter()
+                .map(|_| match dead.next() {
+                    Some(&buffer) if !plugin.properties().contains(Properties::PROP_INPLACE_BROKEN) => buffer,
+                    _ => allocate(&mut free),
+                })
+                .collect();
+            free.extend(current.iter().filter(|x| !node_outputs.contains(x)));
+
+            let mut instance = plugin.instantiate(sample_rate).ok_or_else(|| Error::Instantiate(plugin.label()))?;
+            for &(port, value) in stage.controls.iter() {
+                instance
+                    .set_control(port, value)
+                    .map_err(|_| Error::UnknownControl { plugin: plugin.label(), control: port.to_string() })?;
+            }
+            instance.probe_latency();
+            nodes.push(Node { instance, inputs: node_inputs, outputs: node_outputs });
+            current = nodes.last().unwrap().outputs.clone();
+            previous = Some(plugin);
+        }
+
+        let mut buffers: Vec<Box<[Data]>> = (0..count).map(|_| vec![0.0; MAX_BLOCK].into_boxed_slice()).collect();
+        for node in nodes.iter_mut() {
+            let input_ports = node.instance.plugin().ports_of(PortDescriptor::AudioInput);
+            let output_ports = node.instance.plugin().ports_of(PortDescriptor::AudioOutput);
+            for (&port, &buffer) in input_ports.iter().zip(node.inputs.iter()).chain(output_ports.iter().zip(node.outputs.iter())) {
+                // The boxes never move or resize, so the plugins can keep these pointers.
+                unsafe { node.instance.connect(port, buffers[buffer].as_mut_ptr()) };
+            }
+            node.instance.activate();
+        }
+        Ok(Chain { nodes, inputs, outputs: current, buffers, sample_rate })
+    }
+
+    /// Parses a chain description as described in the module docs and builds the chain.
+    pub fn parse(spec: &str, sample_rate: u64) -> Result<Chain, Error> {
+        let stages = spec.split('!').map(Stage::parse).collect::<Result<Vec<_>, _>>()?;
+        Chain::new(&stages, sample_rate)
+    }
+
+    /// The number of audio inputs of the first stage.
+    pub fn inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// The number of audio outputs of the last stage.
+    pub fn outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// The number of intermediate buffers the chain allocated.
+    pub fn buffers(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// The sum of the latencies the stages report, in samples.
+    pub fn latency(&self) -> usize {
+        self.nodes.iter().map(|x| x.instance.latency()).sum()
+    }
+
+    /// The sum of the tails the stages report, or ```None``` if any of them cannot report one.
+    pub fn tail_samples(&self) -> Option<usize> {
+        self.nodes.iter().map(|x| x.instance.tail_samples()).try_fold(0, |a: usize, b| b.map(|b| a.saturating_add(b)))
+    }
+
+    /// The instance of each stage, e.g. to read its control outputs.
+    pub fn instance(&self, stage: usize) -> &Instance {
+        &self.nodes[stage].instance
+    }
+
+    /**
+     * Runs one block. ```inputs``` and ```outputs``` hold one buffer per audio input of the first
+     * stage and audio output of the last; the block length is the length of the shortest buffer.
+     * Missing inputs are silence.
+     */
+    pub fn run(&mut self, inputs: &[&[Data]], outputs: &mut [&mut [Data]]) {
+        let length = inputs.iter().map(|x| x.len())
+            .chain(outputs.iter().map(|x| x.len()))
+            .min()
+            .unwrap_or(0);
+        let mut pos = 0;
+        while pos < length {
+            let size = (length - pos).min(MAX_BLOCK);
+            for (n, &buffer) in self.inputs.iter().enumerate() {
+                match inputs.get(n) {
+                    Some(input) => self.buffers[buffer][..size].copy_from_slice(&input[pos..pos + size]),
+                    None => self.buffers[buffer][..size].fill(0.0),
+                }
+            }
+            for node in self.nodes.iter_mut() {
+                unsafe { node.instance.run_raw(size) };
+            }
+            for (output, &buffer) in outputs.iter_mut().zip(self.outputs.iter()) {
+                output[pos..pos + size].copy_from_slice(&self.buffers[buffer][..size]);
+            }
+            pos += size;
+        }
+    }
+
+    /**
+     * Renders whole signals through the chain in blocks of ```block_size``` samples. Returns one
+     * buffer per audio output of the last stage of ```length``` samples, without the chain's
+     * latency at the start.
+     */
+    pub fn render(&mut self, inputs: &[Vec<Data>], length: usize, block_size: usize) -> Vec<Vec<Data>> {
+        let (outputs, latency) = (self.outputs(), self.latency());
+        render_offline(inputs, length, block_size, outputs, latency, None, |x, y| self.run(x, y))
+    }
+
+    /**
+     * Like ```render```, but then keeps feeding the chain silence for as long as ```tail_samples```,
+     * at most ```MAX_TAIL_SECONDS```, and removes the output after the last sample with a magnitude
+     * of at least ```threshold```. When the tail is infinite or unknown, the chain runs until its
+     * output stays below ```threshold``` for a second.
+     */
+    pub fn render_tail(&mut self,
+                       inputs: &[Vec<Data>],
+                       length: usize,
+                       block_size: usize,
+                       threshold: Data)
+                       -> Vec<Vec<Data>> {
+        let (outputs, latency) = (self.outputs(), self.latency());
+        let tail = Tail { samples: self.tail_samples(), threshold, sample_rate: self.sample_rate };
+        render_offline(inputs, length, block_size, outputs, latency, Some(tail), |x, y| self.run(x, y))
+    }
+}
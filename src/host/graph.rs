@@ -0,0 +1,642 @@
+/*!
+ * A host running plugins as a directed acyclic graph, for renders that split and merge signals.
+ *
+ * Nodes are plugin instances and edges link an audio output of one node to an audio input of
+ * another; an input fed by several edges receives their sum. The graph's own inputs and outputs
+ * are numbered and connected to node ports the same way:
+ *
+ * ```rust,ignore
+ * let mut graph = Graph::new(48000);
+ * let split = graph.add(delay, &[])?;
+ * let left = graph.add(reverb, &[(2, 0.8)])?;
+ * let right = graph.add(chorus, &[])?;
+ * graph.connect_input(0, split, 0)?;
+ * graph.connect(split, 2, left, 0)?;
+ * graph.connect(split, 3, right, 0)?;
+ * graph.connect_output(left, 1, 0)?;
+ * graph.connect_output(right, 1, 0)?;
+ * let mut processor = graph.compile(4)?;
+ * let outputs = processor.render(&inputs, length, 512);
+ * for timing in processor.timings() {
+ *     println!("{}", timing);
+ * }
+ * ```
+ *
+ * ```compile``` computes the schedule once: nodes are grouped into levels by their longest path
+ * from a source, and the nodes of a level, which cannot depend on each other, run in parallel on a
+ * pool of worker threads before the next level starts. Buffers are assigned by liveness, so a
+ * buffer is reused once the last node reading it has run. Unconnected audio inputs read silence.
+ *
+ * Nodes that have a latency output are run for one sample when compiling, so that they report
+ * their latency. Where signals of different latency meet, at an input or a graph output, the
+ * earlier ones are delayed to line up with the latest, and ```render``` removes the latency of the
+ * graph outputs from the start of its output. ```render_tail``` keeps running the graph on silence
+ * for the longest tail along any path to an output, as ```Chain::render_tail``` does.
+ */
+
+use std::cell::UnsafeCell;
+use std::error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::chain::MAX_BLOCK;
+use super::{render_offline, tail_sum, Instance, PluginRef, Tail};
+use crate::{Data, PortDescriptor};
+
+/// The index of a node, in the order the nodes were added.
+pub type NodeId = usize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    UnknownNode(NodeId),
+    /// A port does not exist or is not of the kind the connection needs.
+    BadPort { node: NodeId, port: usize, expected: PortDescriptor },
+    /// The edges form a cycle.
+    Cycle,
+    /// ```instantiate``` returned NULL.
+    Instantiate(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownNode(node) => write!(f, "there is no node {}", node),
+            Error::BadPort { node, port, expected } => {
+                write!(f, "port {} of node {} is not a {:?} port", port, node, expected)
+            }
+            Error::Cycle => write!(f, "the graph contains a cycle"),
+            Error::Instantiate(ref plugin) => write!(f, "cannot instantiate {}", plugin),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// The time a node spent in ```run```.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timing {
+    pub node: NodeId,
+    pub label: String,
+    pub blocks: u64,
+    pub total: Duration,
+}
+
+impl Timing {
+    pub fn per_block(&self) -> Duration {
+        self.total / self.blocks.max(1) as u32
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {} ({}): {} blocks, {:?} total, {:?} per block",
+               self.node, self.label, self.blocks, self.total, self.per_block())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Source {
+    Input(usize),
+    Node(NodeId, usize),
+}
+
+/// A graph under construction.
+pub struct Graph {
+    sample_rate: u64,
+    nodes: Vec<(PluginRef, Vec<(usize, Data)>)>,
+    edges: Vec<(Source, NodeId, usize)>,
+    outputs: Vec<(Source, usize)>,
+}
+
+impl Graph {
+    pub fn new(sample_rate: u64) -> Graph {
+        Graph { sample_rate, nodes: Vec::new(), edges: Vec::new(), outputs: Vec::new() }
+    }
+
+    fn check(&self, node: NodeId, port: usize, expected: PortDescriptor) -> Result<(), Error> {
+        let (plugin, _) = self.nodes.get(node).ok_or(Error::UnknownNode(node))?;
+        if plugin.ports().get(port).map(|x| x.port.desc) != Some(expected) {
+            return Err(Error::BadPort { node, port, expected });
+        }
+        Ok(())
+    }
+
+    /// Adds a node running ```plugin``` with the given control inputs set, as port and value.
+    pub fn add(&mut self, plugin: PluginRef, controls: &[(usize, Data)]) -> Result<NodeId, Error> {
+        let node = self.nodes.len();
+        self.nodes.push((plugin, controls.to_vec()));
+        for &(port, _) in controls {
+            if let Err(error) = self.check(node, port, PortDescriptor::ControlInput) {
+                self.nodes.pop();
+                return Err(error);
+            }
+        }
+        Ok(node)
+    }
+
+    /// Feeds audio output ```output``` of ```from``` to audio input ```input``` of ```to```.
+    pub fn connect(&mut self, from: NodeId, output: usize, to: NodeId, input: usize) -> Result<(), Error> {
+        self.check(from, output, PortDescriptor::AudioOutput)?;
+        self.check(to, input, PortDescriptor::AudioInput)?;
+        self.edges.push((Source::Node(from, output), to, input));
+        Ok(())
+    }
+
+    /// Feeds input ```input``` of the graph to audio input ```port``` of ```to```.
+    pub fn connect_input(&mut self, input: usize, to: NodeId, port: usize) -> Result<(), Error> {
+        self.check(to, port, PortDescriptor::AudioInput)?;
+        self.edges.push((Source::Input(input), to, port));
+        Ok(())
+    }
+
+    /// Adds audio output ```port``` of ```from``` to output ```output``` of the graph.
+    pub fn connect_output(&mut self, from: NodeId, port: usize, output: usize) -> Result<(), Error> {
+        self.check(from, port, PortDescriptor::AudioOutput)?;
+        self.outputs.push((Source::Node(from, port), output));
+        Ok(())
+    }
+
+    // The level of every node: 0 for nodes fed by no other node, else one more than the highest
+    // level feeding it.
+    fn levels(&self) -> Result<Vec<usize>, Error> {
+        let mut indegree = vec![0; self.nodes.len()];
+        for &(source, to, _) in self.edges.iter() {
+            if let Source::Node(..) = source {
+                indegree[to] += 1;
+            }
+        }
+        let mut levels = vec![0; self.nodes.len()];
+        let mut ready: Vec<NodeId> = (0..self.nodes.len()).filter(|&x| indegree[x] == 0).collect();
+        let mut done = 0;
+        while let Some(node) = ready.pop() {
+            done += 1;
+            for &(source, to, _) in self.edges.iter() {
+                if matches!(source, Source::Node(from, _) if from == node) {
+                    levels[to] = levels[to].max(levels[node] + 1);
+                    indegree[to] -= 1;
+                    if indegree[to] == 0 {
+                        ready.push(to);
+                    }
+                }
+            }
+        }
+        if done < self.nodes.len() {
+            return Err(Error::Cycle);
+        }
+        Ok(levels)
+    }
+
+    /**
+     * Instantiates and activates the nodes, schedules them and assigns their buffers. Each level
+     * runs on up to ```threads``` threads, counting the one calling ```Processor::run```.
+     */
+    pub fn compile(&self, threads: usize) -> Result<Processor, Error> {
+        let levels = self.levels()?;
+        let depth = levels.iter().map(|x| x + 1).max().unwrap_or(0);
+        let mut schedule = vec![Vec::new(); depth];
+        for (node, &level) in levels.iter().enumerate() {
+            schedule[level].push(node);
+        }
+
+        let mut instances = Vec::with_capacity(self.nodes.len());
+        for (plugin, controls) in self.nodes.iter() {
+            let mut instance = plugin.instantiate(self.sample_rate).ok_or_else(|| Error::Instantiate(plugin.label()))?;
+            for &(port, value) in controls.iter() {
+                instance.set_control(port, value).expect("checked by add");
+            }
+            instance.probe_latency();
+            instances.push(instance);
+        }
+        // The inputs of a node are aligned to the latency of the latest signal feeding it, so every
+        // edge delays its signal by the difference. The sources of a level are on earlier levels.
+        let mut aligned = vec![0; self.nodes.len()];
+        let latency_of = |aligned: &[usize], source: Source| match source {
+            Source::Input(_) => 0,
+            Source::Node(node, _) => aligned[node] + instances[node].latency(),
+        };
+        for &node in schedule.iter().flatten() {
+            aligned[node] = self.edges.iter().filter(|x| x.1 == node).map(|x| latency_of(&aligned, x.0)).max().unwrap_or(0);
+        }
+        let delays: Vec<usize> = self.edges.iter().map(|&(source, to, _)| aligned[to] - latency_of(&aligned, source)).collect();
+        let latency = self.outputs.iter().map(|x| latency_of(&aligned, x.0)).max().unwrap_or(0);
+        let output_delays: Vec<usize> = self.outputs.iter().map(|x| latency - latency_of(&aligned, x.0)).collect();
+
+        // Every signal is written at one step and read until a later one: graph inputs at step 0,
+        // the nodes of level n at step n + 1 and the graph outputs at step depth + 1.
+        let step = |source: Source| match source {
+            Source::Input(_) => 0,
+            Source::Node(node, _) => levels[node] + 1,
+        };
+        let mut signals: Vec<(Source, usize, usize)> = Vec::new();
+        let signal = |signals: &mut Vec<(Source, usize, usize)>, source: Source, read: usize| {
+            match signals.iter_mut().find(|x| x.0 == source) {
+                Some(x) => x.2 = x.2.max(read),
+                None => signals.push((source, step(source), read)),
+            }
+        };
+        for (node, (plugin, _)) in self.nodes.iter().enumerate() {
+            for port in plugin.ports_of(PortDescriptor::AudioOutput) {
+                signal(&mut signals, Source::Node(node, port), levels[node] + 1);
+            }
+        }
+        for &(source, to, _) in self.edges.iter() {
+            signal(&mut signals, source, levels[to] + 1);
+        }
+        for &(source, _) in self.outputs.iter() {
+            signal(&mut signals, source, depth + 1);
+        }
+        // Inputs fed by several edges, or by one that is delayed, are summed into a buffer of their
+        // own just before the node runs.
+        let mut sums: Vec<(NodeId, usize)> = Vec::new();
+        for (edge, &(_, to, port)) in self.edges.iter().enumerate() {
+            let fan_in = self.edges.iter().filter(|x| x.1 == to && x.2 == port).count();
+            if (fan_in > 1 || delays[edge] > 0) && !sums.contains(&(to, port)) {
+                sums.push((to, port));
+            }
+        }
+
+        // Live ranges in the order they start; a buffer is free again after the step of its last
+        // read.
+        let mut ranges: Vec<(usize, usize)> = signals.iter().map(|x| (x.1, x.2)).collect();
+        ranges.extend(sums.iter().map(|&(node, _)| (levels[node] + 1, levels[node] + 1)));
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_by_key(|&x| ranges[x].0);
+        let mut assigned = vec![0; ranges.len()];
+        let mut live: Vec<(usize, usize)> = Vec::new();
+        let mut free: Vec<usize> = Vec::new();
+        let mut count = 0;
+        for x in order {
+            let (start, end) = ranges[x];
+            live.retain(|&(buffer, last)| {
+                if last < start {
+                    free.push(buffer);
+                }
+                last >= start
+            });
+            assigned[x] = free.pop().unwrap_or_else(|| {
+                count += 1;
+                count - 1
+            });
+            live.push((assigned[x], end));
+        }
+        let buffer_of = |source: Source| assigned[signals.iter().position(|x| x.0 == source).unwrap()];
+        let silence = count;
+        let buffers: Vec<UnsafeCell<Box<[Data]>>> =
+            (0..count + 1).map(|_| UnsafeCell::new(vec![0.0; MAX_BLOCK].into_boxed_slice())).collect();
+        let pointer = |buffer: usize| unsafe { (*buffers[buffer].get()).as_mut_ptr() };
+
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        for (id, mut instance) in instances.into_iter().enumerate() {
+            let plugin = instance.plugin();
+            let mut node_sums = Vec::new();
+            for port in plugin.ports_of(PortDescriptor::AudioInput) {
+                let edges: Vec<usize> = (0..self.edges.len()).filter(|&x| self.edges[x].1 == id && self.edges[x].2 == port).collect();
+                let buffer = match sums.iter().position(|&x| x == (id, port)) {
+                    Some(sum) => {
+                        let sum = assigned[signals.len() + sum];
+                        let sources = edges.iter().map(|&x| (buffer_of(self.edges[x].0), Delay::new(delays[x]))).collect();
+                        node_sums.push((sum, sources));
+                        sum
+                    }
+                    None => edges.first().map_or(silence, |&x| buffer_of(self.edges[x].0)),
+                };
+                // The boxes never move or resize, so the plugins can keep these pointers.
+                unsafe { instance.connect(port, pointer(buffer)) };
+            }
+            for port in plugin.ports_of(PortDescriptor::AudioOutput) {
+                unsafe { instance.connect(port, pointer(buffer_of(Source::Node(id, port)))) };
+            }
+            instance.activate();
+            nodes.push(UnsafeCell::new(Node { instance, sums: node_sums, blocks: 0, time: Duration::ZERO }));
+        }
+
+        let input_count = self.edges.iter().filter_map(|x| match x.0 {
+            Source::Input(input) => Some(input + 1),
+            Source::Node(..) => None,
+        }).max().unwrap_or(0);
+        let inputs = (0..input_count)
+            .map(|input| signals.iter().position(|x| x.0 == Source::Input(input)).map(|x| assigned[x]))
+            .collect();
+        let mut outputs: Vec<Sources> =
+            (0..self.outputs.iter().map(|x| x.1 + 1).max().unwrap_or(0)).map(|_| Vec::new()).collect();
+        for (&(source, output), &delay) in self.outputs.iter().zip(output_delays.iter()) {
+            outputs[output].push((buffer_of(source), Delay::new(delay)));
+        }
+
+        let shared = Arc::new(Shared {
+            nodes,
+            buffers,
+            schedule,
+            next: AtomicUsize::new(0),
+            job: Mutex::new(Job { generation: 0, level: 0, size: 0, running: 0, quit: false }),
+            start: Condvar::new(),
+            done: Condvar::new(),
+        });
+        let workers = (1..threads.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || worker(shared))
+            })
+            .collect();
+        let feeds = (0..self.nodes.len())
+            .map(|node| {
+                self.edges.iter().filter_map(|x| match x.0 {
+                    Source::Node(from, _) if x.1 == node => Some(from),
+                    _ => None,
+                }).collect()
+            })
+            .collect();
+        let output_nodes = self.outputs.iter().filter_map(|x| match x.0 {
+            Source::Node(node, _) => Some(node),
+            Source::Input(_) => None,
+        }).collect();
+        Ok(Processor {
+            shared,
+            workers,
+            inputs,
+            outputs,
+            buffers: count,
+            latency,
+            sample_rate: self.sample_rate,
+            feeds,
+            output_nodes,
+        })
+    }
+}
+
+// Holds back a signal that arrives earlier than the others it is summed with.
+struct Delay {
+    line: Box<[Data]>,
+    pos: usize,
+}
+
+impl Delay {
+    fn new(length: usize) -> Option<Delay> {
+        (length > 0).then(|| Delay { line: vec![0.0; length].into_boxed_slice(), pos: 0 })
+    }
+}
+
+// The buffers summed into one, each with the delay line it goes through, if any.
+type Sources = Vec<(usize, Option<Delay>)>;
+
+// Adds input to output, through the delay line if there is one.
+fn mix(input: &[Data], output: &mut [Data], delay: Option<&mut Delay>) {
+    match delay {
+        Some(delay) => {
+            for (x, &y) in output.iter_mut().zip(input.iter()) {
+                *x += delay.line[delay.pos];
+                delay.line[delay.pos] = y;
+                delay.pos = (delay.pos + 1) % delay.line.len();
+            }
+        }
+        None => {
+            for (x, &y) in output.iter_mut().zip(input.iter()) {
+                *x += y;
+            }
+        }
+    }
+}
+
+struct Node {
+    instance: Instance,
+    sums: Vec<(usize, Sources)>,
+    blocks: u64,
+    time: Duration,
+}
+
+struct Job {
+    generation: u64,
+    level: usize,
+    size: usize,
+    running: usize,
+    quit: bool,
+}
+
+struct Shared {
+    nodes: Vec<UnsafeCell<Node>>,
+    buffers: Vec<UnsafeCell<Box<[Data]>>>,
+    schedule: Vec<Vec<NodeId>>,
+    next: AtomicUsize,
+    job: Mutex<Job>,
+    start: Condvar,
+    done: Condvar,
+}
+
+// Nodes of one level are claimed through next, so each runs on one thread at a time, and the buffers
+// a level writes are not read or written by any other node of that level. Levels are separated by
+// the job lock.
+unsafe impl Send for Shared {}
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    // Only for buffers that no node is running on.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn buffer(&self, buffer: usize) -> &mut [Data] {
+        unsafe { &mut *self.buffers[buffer].get() }
+    }
+
+    fn run_node(&self, node: NodeId, size: usize) {
+        unsafe {
+            let node = &mut *self.nodes[node].get();
+            for (sum, sources) in node.sums.iter_mut() {
+                let sum = &mut self.buffer(*sum)[..size];
+                sum.fill(0.0);
+                for (source, delay) in sources.iter_mut() {
+                    mix(&self.buffer(*source)[..size], sum, delay.as_mut());
+                }
+            }
+            let start = Instant::now();
+            node.instance.run_raw(size);
+            node.time += start.elapsed();
+            node.blocks += 1;
+        }
+    }
+
+    // Runs nodes of a level until none are left to claim.
+    fn work(&self, level: usize, size: usize) {
+        let nodes = &self.schedule[level];
+        loop {
+            let n = self.next.fetch_add(1, Ordering::Relaxed);
+            match nodes.get(n) {
+                Some(&node) => self.run_node(node, size),
+                None => return,
+            }
+        }
+    }
+}
+
+fn worker(shared: Arc<Shared>) {
+    let mut generation = 0;
+    loop {
+        let (level, size) = {
+            let mut job = shared.job.lock().unwrap();
+            while job.generation == generation && !job.quit {
+                job = shared.start.wait(job).unwrap();
+            }
+            if job.quit {
+                return;
+            }
+            generation = job.generation;
+            (job.level, job.size)
+        };
+        shared.work(level, size);
+        let mut job = shared.job.lock().unwrap();
+        job.running -= 1;
+        if job.running == 0 {
+            shared.done.notify_all();
+        }
+    }
+}
+
+/// A compiled graph, ready to process audio.
+pub struct Processor {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    inputs: Vec<Option<usize>>,
+    outputs: Vec<Sources>,
+    buffers: usize,
+    latency: usize,
+    sample_rate: u64,
+    // The nodes feeding each node, and those feeding the graph outputs.
+    feeds: Vec<Vec<NodeId>>,
+    output_nodes: Vec<NodeId>,
+}
+
+impl Processor {
+    /// The nodes of each level, in the order the levels run.
+    pub fn schedule(&self) -> &[Vec<NodeId>] {
+        &self.shared.schedule
+    }
+
+    /// The number of buffers assigned to signals, not counting the one holding silence.
+    pub fn buffers(&self) -> usize {
+        self.buffers
+    }
+
+    /// The latency of the graph outputs, in samples, after delaying the earlier ones.
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+
+    /**
+     * The longest sum of the tails the nodes report along a path to a graph output, or ```None```
+     * if a node on such a path cannot report its tail.
+     */
+    pub fn tail_samples(&self) -> Option<usize> {
+        let mut tails = vec![Some(0); self.feeds.len()];
+        for &node in self.shared.schedule.iter().flatten() {
+            let before = self.feeds[node].iter().map(|&x| tails[x]).try_fold(0, |a, b| b.map(|b| a.max(b)));
+            tails[node] = tail_sum(before, self.instance(node).tail_samples());
+        }
+        self.output_nodes.iter().map(|&x| tails[x]).try_fold(0, |a, b| b.map(|b| a.max(b)))
+    }
+
+    /// The instance of a node, e.g. to read its control outputs.
+    pub fn instance(&self, node: NodeId) -> &Instance {
+        unsafe { &(*self.shared.nodes[node].get()).instance }
+    }
+
+    /// The time each node has spent in ```run``` so far.
+    pub fn timings(&self) -> Vec<Timing> {
+        (0..self.shared.nodes.len())
+            .map(|id| {
+                let node = unsafe { &*self.shared.nodes[id].get() };
+                Timing { node: id, label: node.instance.plugin().label(), blocks: node.blocks, total: node.time }
+            })
+            .collect()
+    }
+
+    fn run_level(&mut self, level: usize, size: usize) {
+        let shared = &*self.shared;
+        if self.workers.is_empty() || shared.schedule[level].len() < 2 {
+            for &node in shared.schedule[level].iter() {
+                shared.run_node(node, size);
+            }
+            return;
+        }
+        {
+            let mut job = shared.job.lock().unwrap();
+            shared.next.store(0, Ordering::Relaxed);
+            job.generation += 1;
+            job.level = level;
+            job.size = size;
+            job.running = self.workers.len();
+            shared.start.notify_all();
+        }
+        shared.work(level, size);
+        let mut job = shared.job.lock().unwrap();
+        while job.running > 0 {
+            job = shared.done.wait(job).unwrap();
+        }
+    }
+
+    /**
+     * Runs one block. ```inputs``` and ```outputs``` hold one buffer per graph input and output;
+     * the block length is the length of the shortest buffer. Missing inputs are silence.
+     */
+    pub fn run(&mut self, inputs: &[&[Data]], outputs: &mut [&mut [Data]]) {
+        let length = inputs.iter().map(|x| x.len())
+            .chain(outputs.iter().map(|x| x.len()))
+            .min()
+            .unwrap_or(0);
+        let mut pos = 0;
+        while pos < length {
+            let size = (length - pos).min(MAX_BLOCK);
+            for (n, buffer) in self.inputs.iter().enumerate() {
+                if let Some(buffer) = *buffer {
+                    let buffer = unsafe { &mut self.shared.buffer(buffer)[..size] };
+                    match inputs.get(n) {
+                        Some(input) => buffer.copy_from_slice(&input[pos..pos + size]),
+                        None => buffer.fill(0.0),
+                    }
+                }
+            }
+            for level in 0..self.shared.schedule.len() {
+                self.run_level(level, size);
+            }
+            for (output, sources) in outputs.iter_mut().zip(self.outputs.iter_mut()) {
+                let output = &mut output[pos..pos + size];
+                output.fill(0.0);
+                for (source, delay) in sources.iter_mut() {
+                    let source = unsafe { &self.shared.buffer(*source)[..size] };
+                    mix(source, output, delay.as_mut());
+                }
+            }
+            pos += size;
+        }
+    }
+
+    /**
+     * Renders whole signals through the graph in blocks of ```block_size``` samples. Returns one
+     * buffer per graph output of ```length``` samples, without the graph's latency at the start.
+     */
+    pub fn render(&mut self, inputs: &[Vec<Data>], length: usize, block_size: usize) -> Vec<Vec<Data>> {
+        let (outputs, latency) = (self.outputs.len(), self.latency);
+        render_offline(inputs, length, block_size, outputs, latency, None, |x, y| self.run(x, y))
+    }
+
+    /// Like ```render```, followed by the tail of the graph as described for ```Chain::render_tail```.
+    pub fn render_tail(&mut self,
+                       inputs: &[Vec<Data>],
+                       length: usize,
+                       block_size: usize,
+                       threshold: Data)
+                       -> Vec<Vec<Data>> {
+        let (outputs, latency) = (self.outputs.len(), self.latency);
+        let tail = Tail { samples: self.tail_samples(), threshold, sample_rate: self.sample_rate };
+        render_offline(inputs, length, block_size, outputs, latency, Some(tail), |x, y| self.run(x, y))
+    }
+}
+
+impl Drop for Processor {
+    fn drop(&mut self) {
+        self.shared.job.lock().unwrap().quit = true;
+        self.shared.start.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
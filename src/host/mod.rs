@@ -0,0 +1,660 @@
+/*!
+ * Hosting LADSPA plugins through their C interface.
+ *
+ * Everything here drives plugins through ```ladspa_h::Descriptor```, so it works the same for
+ * this library's own plugins, including the wrapper in ```ffi```, and for third-party libraries
+ * opened with ```Library::open```:
+ *
+ * ```rust,ignore
+ * let library = Library::open(&find_library("delay.so").unwrap())?;
+ * let mut instance = library.find("stereo_delay").unwrap().instantiate(48000).unwrap();
+ * instance.activate();
+ * let outputs = instance.render(&inputs, length, std::iter::repeat(512));
+ * ```
+ */
+
+use std::env;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::io;
+use std::mem;
+use std::os::raw::{c_char, c_ulong};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Mutex;
+
+use crate::ffi::{self, ladspa_h};
+use crate::{ControlHint, Data, DefaultValue, Port, PortDescriptor, Properties};
+
+pub mod chain;
+pub mod graph;
+#[cfg(target_os = "linux")]
+pub mod sandbox;
+pub mod scan;
+pub mod wav;
+
+type DescriptorFn = unsafe extern "C" fn(c_ulong) -> *const ladspa_h::Descriptor;
+type TailFn = unsafe extern "C" fn(ladspa_h::Handle) -> c_ulong;
+
+/**
+ * A shared library exporting ```ladspa_descriptor```. Libraries are never unloaded, so the
+ * ```PluginRef```s obtained from them stay valid for the life of the process.
+ */
+pub struct Library {
+    descriptor_fn: DescriptorFn,
+}
+
+impl Library {
+    pub fn open(path: &Path) -> io::Result<Library> {
+        unsafe {
+            let error = || {
+                let msg = libc::dlerror();
+                let msg = if msg.is_null() {
+                    "unknown error".to_string()
+                } else {
+                    CStr::from_ptr(msg).to_string_lossy().into_owned()
+                };
+                io::Error::other(msg)
+            };
+            let path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+            if handle.is_null() {
+                return Err(error());
+            }
+            let symbol = libc::dlsym(handle, c"ladspa_descriptor".as_ptr());
+            if symbol.is_null() {
+                return Err(error());
+            }
+            Ok(Library { descriptor_fn: mem::transmute::<*mut libc::c_void, DescriptorFn>(symbol) })
+        }
+    }
+
+    /// All plugins in the library, in index order.
+    pub fn plugins(&self) -> Vec<PluginRef> {
+        let mut plugins = Vec::new();
+        while let Some(desc) = unsafe { (self.descriptor_fn)(plugins.len() as c_ulong).as_ref() } {
+            plugins.push(PluginRef(desc));
+        }
+        plugins
+    }
+
+    /// The plugin with the given label.
+    pub fn find(&self, label: &str) -> Option<PluginRef> {
+        self.plugins().into_iter().find(|p| p.label() == label)
+    }
+}
+
+/// The directories listed in ```LADSPA_PATH```, or the usual install locations if it is unset.
+pub fn ladspa_path() -> Vec<PathBuf> {
+    match env::var_os("LADSPA_PATH") {
+        Some(path) => env::split_paths(&path).filter(|x| !x.as_os_str().is_empty()).collect(),
+        None => vec![PathBuf::from("/usr/local/lib/ladspa"), PathBuf::from("/usr/lib/ladspa")],
+    }
+}
+
+/**
+ * Resolves the library a host was asked for by name: a path to an existing file is used as is,
+ * anything else is looked up in the directories of ```ladspa_path```.
+ */
+pub fn find_library(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_file() {
+        // dlopen searches the system library path for names without a slash.
+        return Some(if path.components().count() == 1 { Path::new(".").join(path) } else { path.to_path_buf() });
+    }
+    if path.components().count() != 1 {
+        return None;
+    }
+    ladspa_path().into_iter().map(|dir| dir.join(name)).find(|x| x.is_file())
+}
+
+/**
+ * All plugins returned by this library's own ```ladspa_descriptor```. Safe to call from parallel
+ * tests, although ```ladspa_descriptor```, like the hosts calling it, assumes a single thread.
+ */
+pub fn own_plugins() -> Vec<PluginRef> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut plugins = Vec::new();
+    while let Some(desc) = unsafe { ffi::ladspa_descriptor(plugins.len() as c_ulong).as_ref() } {
+        plugins.push(PluginRef(desc));
+    }
+    plugins
+}
+
+// The ladspa_rs_tail_samples exported by the library defining the plugin's instantiate, which
+// only libraries built with this crate have.
+fn find_tail_fn(plugin: PluginRef) -> Option<TailFn> {
+    if ffi::is_own_descriptor(plugin.0) {
+        return Some(ffi::ladspa_rs_tail_samples);
+    }
+    unsafe {
+        let address = plugin.0.instantiate? as *const libc::c_void;
+        let mut info: libc::Dl_info = mem::zeroed();
+        if libc::dladdr(address, &mut info) == 0 || info.dli_fname.is_null() {
+            return None;
+        }
+        // The library is loaded already, so this only takes a reference, which like those of
+        // Library is never released.
+        let handle = libc::dlopen(info.dli_fname, libc::RTLD_NOW | libc::RTLD_NOLOAD);
+        if handle.is_null() {
+            return None;
+        }
+        let symbol = libc::dlsym(handle, c"ladspa_rs_tail_samples".as_ptr());
+        (!symbol.is_null()).then(|| mem::transmute::<*mut libc::c_void, TailFn>(symbol))
+    }
+}
+
+unsafe fn string(s: *const c_char) -> String {
+    unsafe {
+        if s.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(s).to_string_lossy().into_owned()
+        }
+    }
+}
+
+/**
+ * A port as read back from a C descriptor. ```port.name``` is left empty; see ```name```.
+ * ```port.optional``` is only known for this library's own plugins.
+ */
+#[derive(Clone)]
+pub struct PortInfo {
+    pub name: String,
+    pub port: Port,
+    pub hint_descriptor: ladspa_h::PortRangeHintDescriptor,
+}
+
+impl PortInfo {
+    /// The value a host would set the port to by default: its resolved default, else the
+    /// middle of its bounds, else whichever bound exists, else 0.
+    pub fn default_value(&self, sample_rate: u64) -> Data {
+        let lower = self.port.resolved_lower_bound(sample_rate);
+        let upper = self.port.resolved_upper_bound(sample_rate);
+        match (self.port.resolved_default(sample_rate), lower, upper) {
+            (Some(value), _, _) => value,
+            (None, Some(lower), Some(upper)) => (lower + upper) / 2.0,
+            (None, Some(bound), None) | (None, None, Some(bound)) => bound,
+            (None, None, None) => 0.0,
+        }
+    }
+
+    /// Whether this is a latency output, by the LADSPA naming convention.
+    pub fn is_latency(&self) -> bool {
+        self.port.desc == PortDescriptor::ControlOutput && self.name.eq_ignore_ascii_case(crate::LATENCY_PORT_NAME)
+    }
+}
+
+fn default_from_bits(hint: ladspa_h::PortRangeHintDescriptor) -> Option<DefaultValue> {
+    match hint & ladspa_h::HINT_DEFAULT_MASK {
+        ladspa_h::HINT_DEFAULT_MINIMUM => Some(DefaultValue::Minimum),
+        ladspa_h::HINT_DEFAULT_LOW => Some(DefaultValue::Low),
+        ladspa_h::HINT_DEFAULT_MIDDLE => Some(DefaultValue::Middle),
+        ladspa_h::HINT_DEFAULT_HIGH => Some(DefaultValue::High),
+        ladspa_h::HINT_DEFAULT_MAXIMUM => Some(DefaultValue::Maximum),
+        ladspa_h::HINT_DEFAULT_0 => Some(DefaultValue::Value0),
+        ladspa_h::HINT_DEFAULT_1 => Some(DefaultValue::Value1),
+        ladspa_h::HINT_DEFAULT_100 => Some(DefaultValue::Value100),
+        ladspa_h::HINT_DEFAULT_440 => Some(DefaultValue::Value440),
+        _ => None,
+    }
+}
+
+fn port_descriptor(desc: ladspa_h::PortDescriptor) -> PortDescriptor {
+    let input = desc & ladspa_h::PORT_INPUT != 0;
+    let output = desc & ladspa_h::PORT_OUTPUT != 0;
+    let audio = desc & ladspa_h::PORT_AUDIO != 0;
+    let control = desc & ladspa_h::PORT_CONTROL != 0;
+    match (input, output, audio, control) {
+        (true, false, true, false) => PortDescriptor::AudioInput,
+        (false, true, true, false) => PortDescriptor::AudioOutput,
+        (true, false, false, true) => PortDescriptor::ControlInput,
+        (false, true, false, true) => PortDescriptor::ControlOutput,
+        _ => PortDescriptor::Invalid,
+    }
+}
+
+/// A plugin reachable through its C descriptor.
+#[derive(Copy, Clone)]
+pub struct PluginRef(pub &'static ladspa_h::Descriptor);
+
+impl PluginRef {
+    pub fn unique_id(&self) -> u64 {
+        self.0.unique_id
+    }
+
+    pub fn label(&self) -> String {
+        unsafe { string(self.0.label) }
+    }
+
+    pub fn name(&self) -> String {
+        unsafe { string(self.0.name) }
+    }
+
+    pub fn maker(&self) -> String {
+        unsafe { string(self.0.maker) }
+    }
+
+    pub fn properties(&self) -> Properties {
+        Properties::from_bits_truncate(self.0.properties)
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.0.port_count as usize
+    }
+
+    /// The ports of the plugin. Empty if the descriptor's port arrays are missing.
+    pub fn ports(&self) -> Vec<PortInfo> {
+        let desc = self.0;
+        if desc.port_descriptors.is_null() || desc.port_range_hints.is_null() {
+            return Vec::new();
+        }
+        (0..self.port_count())
+            .map(|i| unsafe {
+                let hint = *desc.port_range_hints.add(i);
+                let bits = hint.hint_descriptor;
+                let name = if desc.port_names.is_null() { String::new() } else { string(*desc.port_names.add(i)) };
+                let control_hint = ControlHint::from_bits_truncate(bits);
+                PortInfo {
+                    name,
+                    port: Port {
+                        name: "",
+                        desc: port_descriptor(*desc.port_descriptors.add(i)),
+                        hint: if control_hint.is_empty() { None } else { Some(control_hint) },
+                        default: default_from_bits(bits),
+                        lower_bound: if bits & ladspa_h::HINT_BOUNDED_BELOW != 0 { Some(hint.lower_bound) } else { None },
+                        upper_bound: if bits & ladspa_h::HINT_BOUNDED_ABOVE != 0 { Some(hint.upper_bound) } else { None },
+                        optional: ffi::is_optional_port(desc, i),
+                    },
+                    hint_descriptor: bits,
+                }
+            })
+            .collect()
+    }
+
+    /// The indices of the ports of the given kind, in order.
+    pub fn ports_of(&self, kind: PortDescriptor) -> Vec<usize> {
+        self.ports().iter().enumerate().filter(|(_, p)| p.port.desc == kind).map(|(i, _)| i).collect()
+    }
+
+    pub fn instantiate(&self, sample_rate: u64) -> Option<Instance> {
+        unsafe {
+            let instantiate = self.0.instantiate?;
+            let handle = instantiate(self.0, sample_rate as c_ulong);
+            if handle.is_null() {
+                return None;
+            }
+            let ports = self.ports();
+            let controls = ports.iter().map(|p| p.default_value(sample_rate)).collect();
+            let audio_inputs = self.ports_of(PortDescriptor::AudioInput);
+            let audio_outputs = self.ports_of(PortDescriptor::AudioOutput);
+            let scratch = vec![vec![0.0; DEFAULT_BLOCK]; audio_outputs.len()];
+            let mut instance = Instance {
+                plugin: *self,
+                handle,
+                tail_fn: find_tail_fn(*self),
+                sample_rate,
+                ports,
+                controls,
+                audio_inputs,
+                audio_outputs,
+                silence: vec![0.0; DEFAULT_BLOCK],
+                scratch,
+                active: false,
+            };
+            for i in 0..instance.ports.len() {
+                let kind = instance.ports[i].port.desc;
+                if kind == PortDescriptor::ControlInput || kind == PortDescriptor::ControlOutput {
+                    let location = &mut instance.controls[i] as *mut Data;
+                    instance.connect(i, location);
+                }
+            }
+            Some(instance)
+        }
+    }
+}
+
+/// Why a control could not be set.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ControlError {
+    /// The plugin has only ```ports``` ports.
+    OutOfRange { port: usize, ports: usize },
+    /// The port exists but is not a control input.
+    NotAControlInput { port: usize, desc: PortDescriptor },
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ControlError::OutOfRange { port, ports } => {
+                write!(f, "port {} is out of range, the plugin has {} ports", port, ports)
+            }
+            ControlError::NotAControlInput { port, desc } => write!(f, "port {} is not a control input but {:?}", port, desc),
+        }
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+fn check_control_input(ports: &[PortInfo], port: usize) -> Result<(), ControlError> {
+    match ports.get(port) {
+        None => Err(ControlError::OutOfRange { port, ports: ports.len() }),
+        Some(info) if info.port.desc != PortDescriptor::ControlInput => {
+            Err(ControlError::NotAControlInput { port, desc: info.port.desc })
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// The magnitude below which output counts as silence when rendering a tail, -100 dBFS.
+pub const TAIL_THRESHOLD: Data = 1e-5;
+
+/// The longest tail an offline render runs for, in seconds, also for infinite or unknown tails.
+pub const MAX_TAIL_SECONDS: usize = 60;
+
+// The tail of plugins run one after the other.
+fn tail_sum(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    a.zip(b).map(|(a, b)| a.saturating_add(b))
+}
+
+// The first length samples of each input followed by silence up to total samples.
+fn pad(inputs: &[Vec<Data>], length: usize, total: usize) -> Vec<Vec<Data>> {
+    inputs
+        .iter()
+        .map(|x| {
+            let mut x = x[..length].to_vec();
+            x.resize(total, 0.0);
+            x
+        })
+        .collect()
+}
+
+// What Chain and Processor render after their input, as described for Chain::render_tail.
+struct Tail {
+    samples: Option<usize>,
+    threshold: Data,
+    sample_rate: u64,
+}
+
+// The offline rendering of Chain and Processor: runs inputs and then silence through run in
+// blocks of block_size, and removes latency samples from the start of the outputs.
+fn render_offline<F>(inputs: &[Vec<Data>],
+                     length: usize,
+                     block_size: usize,
+                     outputs: usize,
+                     latency: usize,
+                     tail: Option<Tail>,
+                     mut run: F)
+                     -> Vec<Vec<Data>>
+    where F: FnMut(&[&[Data]], &mut [&mut [Data]])
+{
+    let total = length + latency;
+    let inputs = pad(inputs, length, total);
+    let mut result = vec![vec![0.0; total]; outputs];
+    let block_size = block_size.max(1);
+    let mut pos = 0;
+    while pos < total {
+        let size = block_size.min(total - pos);
+        let block_inputs: Vec<&[Data]> = inputs.iter().map(|x| &x[pos..pos + size]).collect();
+        let mut block_outputs: Vec<&mut [Data]> = result.iter_mut().map(|x| &mut x[pos..pos + size]).collect();
+        run(&block_inputs, &mut block_outputs);
+        pos += size;
+    }
+
+    if let Some(tail) = tail {
+        let max = MAX_TAIL_SECONDS * tail.sample_rate as usize;
+        let (limit, bounded) = match tail.samples {
+            Some(samples) if samples != usize::MAX => (samples.min(max), true),
+            _ => (max, false),
+        };
+        let silence = vec![vec![0.0; block_size]; inputs.len()];
+        let mut block = vec![vec![0.0; block_size]; outputs];
+        let mut rendered = 0;
+        let mut quiet = 0;
+        while rendered < limit && (bounded || quiet < tail.sample_rate as usize) {
+            let size = block_size.min(limit - rendered);
+            let block_inputs: Vec<&[Data]> = silence.iter().map(|x| &x[..size]).collect();
+            let mut block_outputs: Vec<&mut [Data]> = block.iter_mut().map(|x| &mut x[..size]).collect();
+            run(&block_inputs, &mut block_outputs);
+            for (output, x) in result.iter_mut().zip(block.iter()) {
+                output.extend_from_slice(&x[..size]);
+            }
+            let loud = block.iter().any(|x| x[..size].iter().any(|y| y.abs() >= tail.threshold));
+            quiet = if loud { 0 } else { quiet + size };
+            rendered += size;
+        }
+        let end = result
+            .iter()
+            .filter_map(|x| x.iter().rposition(|y| y.abs() >= tail.threshold))
+            .max()
+            .map_or(total, |x| (x + 1).max(total));
+        for output in result.iter_mut() {
+            output.truncate(end);
+        }
+    }
+
+    for output in result.iter_mut() {
+        output.drain(..latency.min(output.len()));
+    }
+    result
+}
+
+/// The block size an ```Instance```'s silence and scratch buffers are allocated for up front.
+pub const DEFAULT_BLOCK: usize = 4096;
+
+/**
+ * An instantiated plugin. Control ports are connected to values owned by the instance, which
+ * start at ```PortInfo::default_value```; audio ports are connected on each call to ```run```.
+ */
+pub struct Instance {
+    plugin: PluginRef,
+    handle: ladspa_h::Handle,
+    tail_fn: Option<TailFn>,
+    sample_rate: u64,
+    ports: Vec<PortInfo>,
+    controls: Box<[Data]>,
+    audio_inputs: Vec<usize>,
+    audio_outputs: Vec<usize>,
+    // Connected to audio ports that ```run``` was given no buffer for.
+    silence: Vec<Data>,
+    scratch: Vec<Vec<Data>>,
+    active: bool,
+}
+
+impl Instance {
+    pub fn plugin(&self) -> PluginRef {
+        self.plugin
+    }
+
+    pub fn sample_rate(&self) -> u64 {
+        self.sample_rate
+    }
+
+    pub fn ports(&self) -> &[PortInfo] {
+        &self.ports
+    }
+
+    /**
+     * The processing cost counters of the instance. Only instances of this library's own plugins
+     * created after ```profile::enable``` have them.
+     */
+    pub fn profile(&self) -> Option<crate::profile::Profile> {
+        if ffi::is_own_descriptor(self.plugin.0) {
+            unsafe { ffi::profile(self.handle) }
+        } else {
+            None
+        }
+    }
+
+    pub fn control(&self, port: usize) -> Data {
+        self.controls[port]
+    }
+
+    /// The latency reported on the plugin's latency output as of the last run, or 0 without one.
+    pub fn latency(&self) -> usize {
+        self.ports.iter().position(|p| p.is_latency()).map_or(0, |port| self.controls[port].max(0.0) as usize)
+    }
+
+    /**
+     * The tail the plugin reports through ```Plugin::tail_samples```, with ```usize::MAX``` for
+     * an infinite one. ```None``` for plugins from libraries not built with this crate, which have
+     * no way to report it.
+     */
+    pub fn tail_samples(&self) -> Option<usize> {
+        self.tail_fn.map(|tail| unsafe { tail(self.handle) as usize })
+    }
+
+    // Plugins only report their latency after a run, so hosts that need it before processing run
+    // one sample of silence and deactivate the plugin again to clear what that left behind. The
+    // audio ports stay connected to buffers of this call until they are connected again.
+    fn probe_latency(&mut self) -> usize {
+        if !self.ports.iter().any(|p| p.is_latency()) {
+            return 0;
+        }
+        let mut output = [0.0];
+        self.activate();
+        self.run(&[], &mut [&mut output]);
+        self.deactivate();
+        self.latency()
+    }
+
+    /// Sets a control input. Fails if ```port``` is not a control input of the plugin.
+    pub fn set_control(&mut self, port: usize, value: Data) -> Result<(), ControlError> {
+        check_control_input(&self.ports, port)?;
+        self.controls[port] = value;
+        Ok(())
+    }
+
+    // Writes any control port, e.g. to see whether the plugin overwrites an output.
+    #[cfg(feature = "testing")]
+    pub(crate) fn write_control(&mut self, port: usize, value: Data) {
+        self.controls[port] = value;
+    }
+
+    /// Connects a port to a location of the caller's choosing.
+    ///
+    /// # Safety
+    /// ```location``` must stay valid for as long as the plugin may access it.
+    pub unsafe fn connect(&mut self, port: usize, location: *mut Data) {
+        unsafe {
+            if let Some(connect_port) = self.plugin.0.connect_port {
+                connect_port(self.handle, port as c_ulong, location);
+            }
+        }
+    }
+
+    /// Runs the plugin for ```sample_count``` samples on whatever is currently connected.
+    ///
+    /// # Safety
+    /// All audio ports must be connected to buffers of at least ```sample_count``` samples.
+    pub unsafe fn run_raw(&mut self, sample_count: usize) {
+        unsafe {
+            if let Some(run) = self.plugin.0.run {
+                run(self.handle, sample_count as c_ulong);
+            }
+        }
+    }
+
+    pub fn activate(&mut self) {
+        unsafe {
+            if !self.active && let Some(activate) = self.plugin.0.activate {
+                activate(self.handle);
+            }
+        }
+        self.active = true;
+    }
+
+    pub fn deactivate(&mut self) {
+        unsafe {
+            if self.active && let Some(deactivate) = self.plugin.0.deactivate {
+                deactivate(self.handle);
+            }
+        }
+        self.active = false;
+    }
+
+    /**
+     * Makes sure ```run``` does not allocate for blocks of up to ```block_size``` samples. Blocks
+     * up to ```DEFAULT_BLOCK``` never allocate.
+     */
+    pub fn reserve(&mut self, block_size: usize) {
+        if self.silence.len() < block_size {
+            self.silence.resize(block_size, 0.0);
+            for buffer in self.scratch.iter_mut() {
+                buffer.resize(block_size, 0.0);
+            }
+        }
+    }
+
+    /**
+     * Runs one block. ```inputs``` and ```outputs``` hold one buffer per audio input and output
+     * port in port order; the block length is the length of the shortest buffer. Audio ports
+     * without a buffer are connected to silence or a scratch buffer, which are only allocated
+     * here for blocks longer than any before and than ```DEFAULT_BLOCK```.
+     */
+    pub fn run(&mut self, inputs: &[&[Data]], outputs: &mut [&mut [Data]]) {
+        let sample_count = inputs.iter().map(|x| x.len())
+            .chain(outputs.iter().map(|x| x.len()))
+            .min()
+            .unwrap_or(0);
+        self.reserve(sample_count);
+        // A plugin may have written to the silence through an output processed in place.
+        self.silence[..sample_count].fill(0.0);
+        let silence = self.silence.as_mut_ptr();
+        unsafe {
+            for n in 0..self.audio_inputs.len() {
+                let location = match inputs.get(n) {
+                    Some(input) => input.as_ptr() as *mut Data,
+                    None => silence,
+                };
+                self.connect(self.audio_inputs[n], location);
+            }
+            for n in 0..self.audio_outputs.len() {
+                let location = match outputs.get_mut(n) {
+                    Some(output) => output.as_mut_ptr(),
+                    None => self.scratch[n].as_mut_ptr(),
+                };
+                self.connect(self.audio_outputs[n], location);
+            }
+            self.run_raw(sample_count);
+        }
+    }
+
+    /**
+     * Renders whole signals through the plugin, splitting them into blocks with the sizes
+     * yielded by ```block_sizes``` (a size of 0 is run as 1). Returns one buffer per audio output.
+     */
+    pub fn render<I>(&mut self, inputs: &[Vec<Data>], length: usize, block_sizes: I) -> Vec<Vec<Data>>
+        where I: IntoIterator<Item = usize>
+    {
+        let mut result = vec![vec![0.0; length]; self.audio_outputs.len()];
+        let mut pos = 0;
+        let mut block_sizes = block_sizes.into_iter();
+        while pos < length {
+            let size = match block_sizes.next() {
+                Some(size) => size.clamp(1, length - pos),
+                None => length - pos,
+            };
+            let block_inputs: Vec<&[Data]> = inputs.iter().map(|x| &x[pos..pos + size]).collect();
+            let mut block_outputs: Vec<&mut [Data]> = result.iter_mut().map(|x| &mut x[pos..pos + size]).collect();
+            self.run(&block_inputs, &mut block_outputs);
+            pos += size;
+        }
+        result
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        self.deactivate();
+        unsafe {
+            if let Some(cleanup) = self.plugin.0.cleanup {
+                cleanup(self.handle);
+            }
+        }
+        self.handle = ptr::null_mut();
+    }
+}
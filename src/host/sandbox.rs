@@ -0,0 +1,384 @@
+/*!
+ * Runs a plugin instance in a child process, so that a plugin crashing or hanging fails the calls
+ * on its ```Instance``` instead of taking the host down.
+ *
+ * ```rust,ignore
+ * for plugin in library.plugins() {
+ *     let mut instance = sandbox::Instance::spawn(plugin, 48000)?;
+ *     instance.activate()?;
+ *     match instance.render(&inputs, length, 512) {
+ *         Ok(outputs) => write_outputs(plugin, outputs),
+ *         Err(error) => eprintln!("skipping {}: {}", plugin.label(), error),
+ *     }
+ * }
+ * ```
+ *
+ * The child is forked from the calling process, so it shares the already loaded library, and
+ * exchanges control values and audio with the parent through an anonymous shared mapping. Each
+ * call is one request, signalled through a futex in the mapping. While waiting for the reply the
+ * parent polls the child with ```waitpid```, so a crash is reported as soon as it happens, and a
+ * child that does not reply within the timeout is killed.
+ *
+ * As with any fork of a multithreaded process, only the forking thread exists in the child. A
+ * plugin that waits for a lock another thread of the host held at the time would hang and be
+ * reported as a timeout.
+ */
+
+use std::error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use super::chain::MAX_BLOCK;
+use super::{check_control_input, ControlError, PluginRef, PortInfo};
+use crate::{Data, PortDescriptor};
+
+/// How long a call may take by default before the child is killed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How often the parent checks on the child while waiting for a reply.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The child process or shared memory could not be created.
+    Spawn(String),
+    /// ```instantiate``` returned NULL in the child.
+    Instantiate(String),
+    /// The child was killed by a signal, e.g. ```SIGSEGV```.
+    Crashed { signal: i32 },
+    /// The child exited, e.g. because the plugin called ```exit```.
+    Exited { status: i32 },
+    /// The child did not reply within the timeout and was killed.
+    Timeout(Duration),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Spawn(ref msg) => write!(f, "cannot start the sandbox: {}", msg),
+            Error::Instantiate(ref plugin) => write!(f, "cannot instantiate {}", plugin),
+            Error::Crashed { signal } => write!(f, "the plugin crashed with signal {}", signal),
+            Error::Exited { status } => write!(f, "the plugin exited with status {}", status),
+            Error::Timeout(timeout) => write!(f, "the plugin did not return within {:?}", timeout),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+// Values of Header::state, which is 0 while the child starts up.
+const REQUEST: u32 = 1;
+const DONE: u32 = 2;
+const FAILED: u32 = 3;
+
+// Values of Header::command.
+const ACTIVATE: u32 = 0;
+const DEACTIVATE: u32 = 1;
+const RUN: u32 = 2;
+const QUIT: u32 = 3;
+
+// The start of the shared mapping, followed by one control value per port and MAX_BLOCK samples
+// per audio port.
+#[repr(C)]
+struct Header {
+    state: AtomicU32,
+    command: u32,
+    sample_count: u32,
+}
+
+const DATA_OFFSET: usize = 64;
+
+fn futex_wait(word: &AtomicU32, expected: u32, timeout: Duration) {
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAIT, expected, &timeout as *const libc::timespec);
+    }
+}
+
+fn futex_wake(word: &AtomicU32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, i32::MAX);
+    }
+}
+
+/// A plugin instance running in a child process.
+pub struct Instance {
+    plugin: PluginRef,
+    ports: Vec<PortInfo>,
+    // The shared mapping, and where each audio port's buffer starts in it.
+    memory: *mut u8,
+    size: usize,
+    audio: Vec<Option<usize>>,
+    pid: libc::pid_t,
+    timeout: Duration,
+    error: Option<Error>,
+}
+
+impl Instance {
+    /// Forks a child process and instantiates ```plugin``` in it.
+    pub fn spawn(plugin: PluginRef, sample_rate: u64) -> Result<Instance, Error> {
+        let ports = plugin.ports();
+        let mut audio = Vec::with_capacity(ports.len());
+        let mut offset = DATA_OFFSET + ports.len() * size_of::<Data>();
+        for port in ports.iter() {
+            if port.port.desc == PortDescriptor::AudioInput || port.port.desc == PortDescriptor::AudioOutput {
+                audio.push(Some(offset));
+                offset += MAX_BLOCK * size_of::<Data>();
+            } else {
+                audio.push(None);
+            }
+        }
+
+        unsafe {
+            let memory = libc::mmap(ptr::null_mut(), offset, libc::PROT_READ | libc::PROT_WRITE,
+                                    libc::MAP_SHARED | libc::MAP_ANONYMOUS, -1, 0);
+            if memory == libc::MAP_FAILED {
+                return Err(Error::Spawn(std::io::Error::last_os_error().to_string()));
+            }
+            let mut instance = Instance {
+                plugin,
+                ports,
+                memory: memory as *mut u8,
+                size: offset,
+                audio,
+                pid: 0,
+                timeout: DEFAULT_TIMEOUT,
+                error: None,
+            };
+            for (port, info) in instance.ports.iter().enumerate() {
+                *instance.control_ptr(port) = info.default_value(sample_rate);
+            }
+
+            match libc::fork() {
+                -1 => Err(Error::Spawn(std::io::Error::last_os_error().to_string())),
+                0 => {
+                    let status = panic::catch_unwind(AssertUnwindSafe(|| instance.serve(sample_rate)));
+                    libc::_exit(if status.is_ok() { 0 } else { 101 });
+                }
+                pid => {
+                    instance.pid = pid;
+                    instance.wait()?;
+                    if instance.header().state.load(Ordering::Acquire) == FAILED {
+                        return Err(Error::Instantiate(plugin.label()));
+                    }
+                    Ok(instance)
+                }
+            }
+        }
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.memory as *const Header) }
+    }
+
+    fn control_ptr(&self, port: usize) -> *mut Data {
+        assert!(port < self.ports.len(), "port {} out of range", port);
+        unsafe { (self.memory.add(DATA_OFFSET) as *mut Data).add(port) }
+    }
+
+    fn audio_ptr(&self, port: usize) -> *mut Data {
+        unsafe { self.memory.add(self.audio[port].unwrap()) as *mut Data }
+    }
+
+    // The child's side: instantiates the plugin on the shared memory and serves requests until
+    // told to quit.
+    fn serve(&self, sample_rate: u64) {
+        let header = self.header();
+        let Some(mut instance) = self.plugin.instantiate(sample_rate) else {
+            header.state.store(FAILED, Ordering::Release);
+            futex_wake(&header.state);
+            return;
+        };
+        unsafe {
+            for port in 0..self.ports.len() {
+                match self.audio[port] {
+                    Some(_) => instance.connect(port, self.audio_ptr(port)),
+                    None => instance.connect(port, self.control_ptr(port)),
+                }
+            }
+        }
+        header.state.store(DONE, Ordering::Release);
+        futex_wake(&header.state);
+        loop {
+            let state = header.state.load(Ordering::Acquire);
+            if state != REQUEST {
+                futex_wait(&header.state, state, Duration::from_secs(1));
+                continue;
+            }
+            match header.command {
+                ACTIVATE => instance.activate(),
+                DEACTIVATE => instance.deactivate(),
+                RUN => unsafe { instance.run_raw(header.sample_count as usize) },
+                _ => return,
+            }
+            header.state.store(DONE, Ordering::Release);
+            futex_wake(&header.state);
+        }
+    }
+
+    // Waits for the child to finish the current request, reaping it if it died.
+    fn wait(&mut self) -> Result<(), Error> {
+        if let Some(ref error) = self.error {
+            return Err(error.clone());
+        }
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let state = self.header().state.load(Ordering::Acquire);
+            if state == DONE || state == FAILED {
+                return Ok(());
+            }
+            let mut status = 0;
+            let error = if unsafe { libc::waitpid(self.pid, &mut status, libc::WNOHANG) } == self.pid {
+                self.pid = 0;
+                if libc::WIFSIGNALED(status) {
+                    Some(Error::Crashed { signal: libc::WTERMSIG(status) })
+                } else {
+                    Some(Error::Exited { status: libc::WEXITSTATUS(status) })
+                }
+            } else if Instant::now() >= deadline {
+                self.kill();
+                Some(Error::Timeout(self.timeout))
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                self.error = Some(error.clone());
+                return Err(error);
+            }
+            futex_wait(&self.header().state, state, POLL_INTERVAL);
+        }
+    }
+
+    fn request(&mut self, command: u32, sample_count: usize) -> Result<(), Error> {
+        if let Some(ref error) = self.error {
+            return Err(error.clone());
+        }
+        unsafe {
+            let header = self.memory as *mut Header;
+            (*header).command = command;
+            (*header).sample_count = sample_count as u32;
+            (*header).state.store(REQUEST, Ordering::Release);
+            futex_wake(&(*header).state);
+        }
+        self.wait()
+    }
+
+    fn kill(&mut self) {
+        if self.pid != 0 {
+            unsafe {
+                libc::kill(self.pid, libc::SIGKILL);
+                libc::waitpid(self.pid, ptr::null_mut(), 0);
+            }
+            self.pid = 0;
+        }
+    }
+
+    pub fn plugin(&self) -> PluginRef {
+        self.plugin
+    }
+
+    pub fn ports(&self) -> &[PortInfo] {
+        &self.ports
+    }
+
+    /// How long a call may take before the child is killed and the call fails.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The error that ended the child, after which every call fails with it.
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    pub fn control(&self, port: usize) -> Data {
+        unsafe { *self.control_ptr(port) }
+    }
+
+    /// Sets a control input. Fails if ```port``` is not a control input of the plugin.
+    pub fn set_control(&mut self, port: usize, value: Data) -> Result<(), ControlError> {
+        check_control_input(&self.ports, port)?;
+        unsafe { *self.control_ptr(port) = value };
+        Ok(())
+    }
+
+    pub fn activate(&mut self) -> Result<(), Error> {
+        self.request(ACTIVATE, 0)
+    }
+
+    pub fn deactivate(&mut self) -> Result<(), Error> {
+        self.request(DEACTIVATE, 0)
+    }
+
+    /**
+     * Runs one block. ```inputs``` and ```outputs``` hold one buffer per audio input and output
+     * port in port order; the block length is the length of the shortest buffer. Missing inputs
+     * are silence.
+     */
+    pub fn run(&mut self, inputs: &[&[Data]], outputs: &mut [&mut [Data]]) -> Result<(), Error> {
+        let length = inputs.iter().map(|x| x.len())
+            .chain(outputs.iter().map(|x| x.len()))
+            .min()
+            .unwrap_or(0);
+        let input_ports = self.plugin.ports_of(PortDescriptor::AudioInput);
+        let output_ports = self.plugin.ports_of(PortDescriptor::AudioOutput);
+        let mut pos = 0;
+        while pos < length {
+            let size = (length - pos).min(MAX_BLOCK);
+            for (n, &port) in input_ports.iter().enumerate() {
+                let buffer = unsafe { slice::from_raw_parts_mut(self.audio_ptr(port), size) };
+                match inputs.get(n) {
+                    Some(input) => buffer.copy_from_slice(&input[pos..pos + size]),
+                    None => buffer.fill(0.0),
+                }
+            }
+            self.request(RUN, size)?;
+            for (output, &port) in outputs.iter_mut().zip(output_ports.iter()) {
+                let buffer = unsafe { slice::from_raw_parts(self.audio_ptr(port), size) };
+                output[pos..pos + size].copy_from_slice(buffer);
+            }
+            pos += size;
+        }
+        Ok(())
+    }
+
+    /**
+     * Renders whole signals through the plugin in blocks of ```block_size``` samples. Returns one
+     * buffer per audio output.
+     */
+    pub fn render(&mut self, inputs: &[Vec<Data>], length: usize, block_size: usize) -> Result<Vec<Vec<Data>>, Error> {
+        let mut result = vec![vec![0.0; length]; self.plugin.ports_of(PortDescriptor::AudioOutput).len()];
+        let block_size = block_size.max(1);
+        let mut pos = 0;
+        while pos < length {
+            let size = block_size.min(length - pos);
+            let block_inputs: Vec<&[Data]> = inputs.iter().map(|x| &x[pos..pos + size]).collect();
+            let mut block_outputs: Vec<&mut [Data]> = result.iter_mut().map(|x| &mut x[pos..pos + size]).collect();
+            self.run(&block_inputs, &mut block_outputs)?;
+            pos += size;
+        }
+        Ok(result)
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        if self.pid != 0 && self.error.is_none() {
+            // The child exits without replying, so this only fails once it has been reaped, or
+            // killed if cleaning up the plugin hangs.
+            self.timeout = self.timeout.min(Duration::from_secs(1));
+            let _ = self.request(QUIT, 0);
+        }
+        self.kill();
+        unsafe {
+            libc::munmap(self.memory as *mut libc::c_void, self.size);
+        }
+    }
+}
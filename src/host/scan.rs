@@ -0,0 +1,365 @@
+/*!
+ * Discovers the plugins installed in LADSPA library directories and remembers them in a cache.
+ *
+ * ```rust,ignore
+ * let mut cache = ScanCache::load(&cache_path).unwrap_or_default();
+ * cache.scan(&ladspa_path(), Mode::Subprocess { timeout: DEFAULT_TIMEOUT });
+ * cache.save(&cache_path)?;
+ * for (path, plugin) in cache.plugins() {
+ *     println!("{}: {} ({})", path.display(), plugin.label, plugin.name);
+ * }
+ * ```
+ *
+ * Loading a library runs its constructors and ```ladspa_descriptor```, which crash or hang in
+ * some broken libraries. With ```Mode::Subprocess``` every library is loaded in a short-lived
+ * child process that writes the descriptors back to the parent, so such a library is marked bad
+ * in the cache, with the reason, instead of taking the scan down. The parent itself never loads
+ * the libraries; use ```Library::open``` on the ones it wants to instantiate.
+ *
+ * A library is scanned again only when its modification time changes, so a cache loaded from a
+ * previous run skips the libraries that were bad then.
+ *
+ * The cache file is line-based, one record per line with tab-separated fields: a ```library```
+ * line with the path and modification time, followed by either a ```bad``` line with the reason
+ * or a ```plugin``` line per plugin, each followed by a ```port``` line per port. Tabs, newlines
+ * and backslashes in fields are escaped with backslashes.
+ */
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use super::Library;
+use crate::PortDescriptor;
+
+/// How long a library may take to load by default before it is marked bad.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+const HEADER: &str = "ladspa.rs scan cache 1";
+
+/// How libraries are loaded while scanning.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    /// In the calling process; a crashing library crashes the caller.
+    InProcess,
+    /// In a child process per library, killed if it does not finish within ```timeout```.
+    Subprocess { timeout: Duration },
+}
+
+/// A plugin as described by its library.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PluginInfo {
+    pub unique_id: u64,
+    pub label: String,
+    pub name: String,
+    pub maker: String,
+    pub ports: Vec<(PortDescriptor, String)>,
+}
+
+/// What a scan found in one library.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scanned {
+    /// The modification time of the library when it was scanned, in seconds since the epoch.
+    pub modified: u64,
+    /// The plugins of the library, or why it is bad.
+    pub result: Result<Vec<PluginInfo>, String>,
+}
+
+fn port_kind(desc: PortDescriptor) -> &'static str {
+    match desc {
+        PortDescriptor::AudioInput => "audio-in",
+        PortDescriptor::AudioOutput => "audio-out",
+        PortDescriptor::ControlInput => "control-in",
+        PortDescriptor::ControlOutput => "control-out",
+        PortDescriptor::Invalid => "invalid",
+    }
+}
+
+fn parse_port_kind(kind: &str) -> PortDescriptor {
+    match kind {
+        "audio-in" => PortDescriptor::AudioInput,
+        "audio-out" => PortDescriptor::AudioOutput,
+        "control-in" => PortDescriptor::ControlInput,
+        "control-out" => PortDescriptor::ControlOutput,
+        _ => PortDescriptor::Invalid,
+    }
+}
+
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+    result
+}
+
+fn record(fields: &[&str]) -> String {
+    let mut line = fields.iter().map(|x| escape(x)).collect::<Vec<_>>().join("\t");
+    line.push('\n');
+    line
+}
+
+fn write_plugins(out: &mut String, plugins: &[PluginInfo]) {
+    for plugin in plugins {
+        let id = plugin.unique_id.to_string();
+        out.push_str(&record(&["plugin", &id, &plugin.label, &plugin.name, &plugin.maker]));
+        for (desc, name) in plugin.ports.iter() {
+            out.push_str(&record(&["port", port_kind(*desc), name]));
+        }
+    }
+}
+
+fn invalid(record: usize, msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("record {}: {}", record, msg))
+}
+
+// Parses the plugin, port and bad records following a library record, as written by the cache
+// and by the scanning child, and returns the index of the first record after them.
+fn read_plugins(records: &[Vec<String>], mut pos: usize) -> io::Result<(Result<Vec<PluginInfo>, String>, usize)> {
+    let mut plugins: Vec<PluginInfo> = Vec::new();
+    while let Some(fields) = records.get(pos) {
+        match fields[0].as_str() {
+            "bad" if plugins.is_empty() => {
+                return Ok((Err(fields.get(1).cloned().unwrap_or_default()), pos + 1));
+            }
+            "plugin" if fields.len() == 5 => plugins.push(PluginInfo {
+                unique_id: fields[1].parse().map_err(|_| invalid(pos + 1, "bad unique id"))?,
+                label: fields[2].clone(),
+                name: fields[3].clone(),
+                maker: fields[4].clone(),
+                ports: Vec::new(),
+            }),
+            "port" if fields.len() == 3 => plugins
+                .last_mut()
+                .ok_or_else(|| invalid(pos + 1, "port outside a plugin"))?
+                .ports
+                .push((parse_port_kind(&fields[1]), fields[2].clone())),
+            "library" => break,
+            _ => return Err(invalid(pos + 1, "unexpected record")),
+        }
+        pos += 1;
+    }
+    Ok((Ok(plugins), pos))
+}
+
+fn split_records(text: &str) -> Vec<Vec<String>> {
+    text.lines().filter(|x| !x.is_empty()).map(|x| x.split('\t').map(unescape).collect()).collect()
+}
+
+fn describe(library: &Library) -> Vec<PluginInfo> {
+    library
+        .plugins()
+        .into_iter()
+        .map(|plugin| PluginInfo {
+            unique_id: plugin.unique_id(),
+            label: plugin.label(),
+            name: plugin.name(),
+            maker: plugin.maker(),
+            ports: plugin.ports().into_iter().map(|x| (x.port.desc, x.name)).collect(),
+        })
+        .collect()
+}
+
+fn scan_in_process(path: &Path) -> Result<Vec<PluginInfo>, String> {
+    Library::open(path).map(|x| describe(&x)).map_err(|e| e.to_string())
+}
+
+fn scan_in_child(path: &Path, timeout: Duration) -> Result<Vec<PluginInfo>, String> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(format!("cannot create a pipe: {}", io::Error::last_os_error()));
+    }
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        unsafe {
+            libc::close(fds[0]);
+            let mut pipe = File::from_raw_fd(fds[1]);
+            let status = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut out = String::new();
+                match scan_in_process(path) {
+                    Ok(plugins) => write_plugins(&mut out, &plugins),
+                    Err(error) => out.push_str(&record(&["bad", &error])),
+                }
+                pipe.write_all(out.as_bytes()).is_ok()
+            }));
+            libc::_exit(if matches!(status, Ok(true)) { 0 } else { 1 });
+        }
+    }
+    let mut pipe = unsafe {
+        libc::close(fds[1]);
+        File::from_raw_fd(fds[0])
+    };
+    if pid == -1 {
+        return Err(format!("cannot fork: {}", io::Error::last_os_error()));
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut output = Vec::new();
+    let mut buffer = [0; 4096];
+    let mut timed_out = false;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now()).as_millis().min(i32::MAX as u128);
+        let mut poll = libc::pollfd { fd: fds[0], events: libc::POLLIN, revents: 0 };
+        match unsafe { libc::poll(&mut poll, 1, remaining as i32) } {
+            0 => {
+                timed_out = true;
+                break;
+            }
+            -1 => continue,
+            _ => {}
+        }
+        match pipe.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => output.extend_from_slice(&buffer[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+
+    // The child exits right after closing the pipe, unless a destructor hangs.
+    let mut status = 0;
+    while !timed_out && unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } == 0 {
+        timed_out = Instant::now() >= deadline;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    if timed_out {
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+            libc::waitpid(pid, &mut status, 0);
+        }
+        return Err(format!("timed out after {:?}", timeout));
+    }
+    if libc::WIFSIGNALED(status) {
+        return Err(format!("crashed with signal {}", libc::WTERMSIG(status)));
+    }
+    if libc::WEXITSTATUS(status) != 0 {
+        return Err(format!("exited with status {}", libc::WEXITSTATUS(status)));
+    }
+    let records = split_records(&String::from_utf8_lossy(&output));
+    match read_plugins(&records, 0) {
+        Ok((result, end)) if end == records.len() => result,
+        _ => Err("unreadable descriptors".to_string()),
+    }
+}
+
+/// Scans one library and describes its plugins, or returns why it is bad.
+pub fn scan_library(path: &Path, mode: Mode) -> Result<Vec<PluginInfo>, String> {
+    match mode {
+        Mode::InProcess => scan_in_process(path),
+        Mode::Subprocess { timeout } => scan_in_child(path, timeout),
+    }
+}
+
+fn modified(path: &Path) -> Option<u64> {
+    let time = fs::metadata(path).and_then(|x| x.modified()).ok()?;
+    Some(time.duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0))
+}
+
+/// The results of scanning libraries, by path.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScanCache {
+    libraries: BTreeMap<PathBuf, Scanned>,
+}
+
+impl ScanCache {
+    pub fn new() -> ScanCache {
+        ScanCache::default()
+    }
+
+    /**
+     * Scans the ```.so``` files in ```dirs``` that are not in the cache or changed since they were
+     * scanned, and forgets the libraries in ```dirs``` that no longer exist.
+     */
+    pub fn scan(&mut self, dirs: &[PathBuf], mode: Mode) {
+        let mut found = Vec::new();
+        for dir in dirs {
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            found.extend(entries
+                .filter_map(|x| x.ok().map(|x| x.path()))
+                .filter(|x| x.extension().is_some_and(|x| x == "so") && x.is_file()));
+        }
+        found.sort();
+        self.libraries.retain(|path, _| !dirs.iter().any(|x| path.parent() == Some(x.as_path())) || found.contains(path));
+        for path in found {
+            let Some(modified) = modified(&path) else { continue };
+            if self.libraries.get(&path).is_some_and(|x| x.modified == modified) {
+                continue;
+            }
+            let result = scan_library(&path, mode);
+            self.libraries.insert(path, Scanned { modified, result });
+        }
+    }
+
+    /// What the last scan found in the library at ```path```.
+    pub fn get(&self, path: &Path) -> Option<&Scanned> {
+        self.libraries.get(path)
+    }
+
+    /// Every scanned library, in path order.
+    pub fn libraries(&self) -> impl Iterator<Item = (&Path, &Scanned)> {
+        self.libraries.iter().map(|(path, scanned)| (path.as_path(), scanned))
+    }
+
+    /// The plugins of the good libraries, with the path of their library.
+    pub fn plugins(&self) -> impl Iterator<Item = (&Path, &PluginInfo)> {
+        self.libraries().flat_map(|(path, scanned)| {
+            scanned.result.iter().flatten().map(move |plugin| (path, plugin))
+        })
+    }
+
+    /// The bad libraries, with the reason.
+    pub fn bad(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.libraries().filter_map(|(path, scanned)| scanned.result.as_ref().err().map(|x| (path, x.as_str())))
+    }
+
+    /// Reads a cache written by ```save```.
+    pub fn load(path: &Path) -> io::Result<ScanCache> {
+        let text = fs::read_to_string(path)?;
+        if text.lines().next() != Some(HEADER) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a scan cache"));
+        }
+        let records = split_records(&text[HEADER.len()..]);
+        let mut cache = ScanCache::new();
+        let mut pos = 0;
+        while let Some(fields) = records.get(pos) {
+            if fields.len() != 3 || fields[0] != "library" {
+                return Err(invalid(pos + 1, "expected a library record"));
+            }
+            let modified = fields[2].parse().map_err(|_| invalid(pos + 1, "bad modification time"))?;
+            let (result, next) = read_plugins(&records, pos + 1)?;
+            cache.libraries.insert(PathBuf::from(&fields[1]), Scanned { modified, result });
+            pos = next;
+        }
+        Ok(cache)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = format!("{}\n", HEADER);
+        for (library, scanned) in self.libraries() {
+            out.push_str(&record(&["library", &library.to_string_lossy(), &scanned.modified.to_string()]));
+            match scanned.result {
+                Ok(ref plugins) => write_plugins(&mut out, plugins),
+                Err(ref reason) => out.push_str(&record(&["bad", reason])),
+            }
+        }
+        fs::write(path, out)
+    }
+}
@@ -0,0 +1,120 @@
+/*!
+ * Reading and writing of 32-bit float WAV files, for keeping rendered audio next to the tests
+ * that check it.
+ */
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::Data;
+
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Audio with one buffer per channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<Data>>,
+}
+
+impl Wav {
+    /// The number of samples per channel.
+    pub fn frames(&self) -> usize {
+        self.channels.first().map_or(0, |x| x.len())
+    }
+
+    /// Encodes the audio as a WAV file. Channels are cut to the shortest one.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let channels = self.channels.len();
+        let frames = self.channels.iter().map(|x| x.len()).min().unwrap_or(0);
+        let data_size = (channels * frames * 4) as u32;
+        let block_align = (channels * 4) as u16;
+
+        let mut out = Vec::with_capacity(58 + data_size as usize);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(50 + data_size).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&18u32.to_le_bytes());
+        out.extend_from_slice(&FORMAT_IEEE_FLOAT.to_le_bytes());
+        out.extend_from_slice(&(channels as u16).to_le_bytes());
+        out.extend_from_slice(&self.sample_rate.to_le_bytes());
+        out.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        out.extend_from_slice(&block_align.to_le_bytes());
+        out.extend_from_slice(&32u16.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        // Non-PCM formats must give the number of frames in a fact chunk.
+        out.extend_from_slice(b"fact");
+        out.extend_from_slice(&4u32.to_le_bytes());
+        out.extend_from_slice(&(frames as u32).to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_size.to_le_bytes());
+        for frame in 0..frames {
+            for channel in self.channels.iter() {
+                out.extend_from_slice(&channel[frame].to_le_bytes());
+            }
+        }
+        out
+    }
+
+    /// Decodes a WAV file holding 32-bit float samples.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Wav> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(invalid("not a WAV file"));
+        }
+        let u16_at = |pos: usize| u16::from_le_bytes([bytes[pos], bytes[pos + 1]]);
+        let u32_at = |pos: usize| u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+
+        let mut format = None;
+        let mut data = None;
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let id = &bytes[pos..pos + 4];
+            let size = u32_at(pos + 4) as usize;
+            let body = pos + 8;
+            let end = body.checked_add(size).filter(|&end| end <= bytes.len()).ok_or_else(|| invalid("truncated chunk"))?;
+            match id {
+                b"fmt " if size >= 16 => {
+                    let mut tag = u16_at(body);
+                    // The extensible format keeps the real format tag at the start of its GUID.
+                    if tag == FORMAT_EXTENSIBLE && size >= 26 {
+                        tag = u16_at(body + 24);
+                    }
+                    format = Some((tag, u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+                }
+                b"data" => data = Some(&bytes[body..end]),
+                _ => {}
+            }
+            // Chunks are padded to an even size.
+            pos = end + (size & 1);
+        }
+
+        let (tag, channels, sample_rate, bits) = format.ok_or_else(|| invalid("no fmt chunk"))?;
+        let data = data.ok_or_else(|| invalid("no data chunk"))?;
+        if tag != FORMAT_IEEE_FLOAT || bits != 32 {
+            return Err(invalid("not 32-bit float samples"));
+        }
+        let channels = channels as usize;
+        let frames = if channels == 0 { 0 } else { data.len() / (channels * 4) };
+        let mut result = vec![Vec::with_capacity(frames); channels];
+        for (i, sample) in data.chunks_exact(4).take(frames * channels).enumerate() {
+            result[i % channels].push(Data::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]));
+        }
+        Ok(Wav { sample_rate, channels: result })
+    }
+
+    pub fn read(path: &Path) -> io::Result<Wav> {
+        Wav::from_bytes(&fs::read(path)?)
+    }
+
+    /// Writes the file, creating missing parent directories.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_bytes())
+    }
+}
@@ -9,8 +9,27 @@ extern crate vec_map;
 use bitflags::bitflags;
 
 #[doc(hidden)]
+#[macro_use]
 pub mod ffi;
 
+pub mod dssi;
+pub mod pipewire;
+pub mod profile;
+pub mod rdf;
+pub mod trace;
+
+#[cfg(feature = "lv2")]
+pub mod lv2;
+
+#[cfg(feature = "clap")]
+pub mod clap;
+
+#[cfg(feature = "host")]
+pub mod host;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
 use crate::ffi::ladspa_h;
 
 #[doc(hidden)]
@@ -41,10 +60,129 @@ pub struct PluginDescriptor {
     pub name: &'static str,
     pub maker: &'static str,
     pub copyright: &'static str,
+    pub category: Option<Category>,
     pub ports: Vec<Port>,
     pub new: fn(desc: &PluginDescriptor, sample_rate: u64) -> Box<dyn Plugin + Send>,
 }
 
+/**
+ * A plugin class from the LADSPA/LRDF class hierarchy (```ladspa.rdfs```). Hosts use it to sort
+ * plugins into menus; LADSPA itself has no slot for it, so it is only published through metadata:
+ * the LRDF files written by ```rdf::write_rdf``` and, with the matching features, the LV2 and CLAP
+ * descriptions.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Category {
+    Utility,
+    Generator,
+    Oscillator,
+    Simulator,
+    Reverb,
+    Time,
+    Delay,
+    Phaser,
+    Flanger,
+    Chorus,
+    Frequency,
+    FrequencyMeter,
+    Pitch,
+    Filter,
+    Lowpass,
+    Highpass,
+    Bandpass,
+    Comb,
+    Allpass,
+    EQ,
+    ParaEQ,
+    MultiEQ,
+    Amplitude,
+    Amplifier,
+    Distortion,
+    Waveshaper,
+    Modulator,
+    Dynamics,
+    Compressor,
+    Expander,
+    Limiter,
+    Gate,
+}
+
+/// The namespace of the LADSPA class hierarchy.
+pub const LADSPA_ONTOLOGY: &str = "http://ladspa.org/ontology#";
+
+impl Category {
+    /// The class name within the LADSPA ontology, e.g. ```"DelayPlugin"```.
+    pub fn class_name(self) -> &'static str {
+        match self {
+            Category::Utility => "UtilityPlugin",
+            Category::Generator => "GeneratorPlugin",
+            Category::Oscillator => "OscillatorPlugin",
+            Category::Simulator => "SimulatorPlugin",
+            Category::Reverb => "ReverbPlugin",
+            Category::Time => "TimePlugin",
+            Category::Delay => "DelayPlugin",
+            Category::Phaser => "PhaserPlugin",
+            Category::Flanger => "FlangerPlugin",
+            Category::Chorus => "ChorusPlugin",
+            Category::Frequency => "FrequencyPlugin",
+            Category::FrequencyMeter => "FrequencyMeterPlugin",
+            Category::Pitch => "PitchPlugin",
+            Category::Filter => "FilterPlugin",
+            Category::Lowpass => "LowpassPlugin",
+            Category::Highpass => "HighpassPlugin",
+            Category::Bandpass => "BandpassPlugin",
+            Category::Comb => "CombPlugin",
+            Category::Allpass => "AllpassPlugin",
+            Category::EQ => "EQPlugin",
+            Category::ParaEQ => "ParaEQPlugin",
+            Category::MultiEQ => "MultiEQPlugin",
+            Category::Amplitude => "AmplitudePlugin",
+            Category::Amplifier => "AmplifierPlugin",
+            Category::Distortion => "DistortionPlugin",
+            Category::Waveshaper => "WaveshaperPlugin",
+            Category::Modulator => "ModulatorPlugin",
+            Category::Dynamics => "DynamicsPlugin",
+            Category::Compressor => "CompressorPlugin",
+            Category::Expander => "ExpanderPlugin",
+            Category::Limiter => "LimiterPlugin",
+            Category::Gate => "GatePlugin",
+        }
+    }
+
+    /// The full RDF URI of the class, e.g. ```"http://ladspa.org/ontology#DelayPlugin"```.
+    pub fn uri(self) -> String {
+        format!("{}{}", LADSPA_ONTOLOGY, self.class_name())
+    }
+
+    /// The direct superclass, or ```None``` if this class derives from the root ```Plugin```.
+    pub fn parent(self) -> Option<Category> {
+        match self {
+            Category::Utility | Category::Generator | Category::Simulator | Category::Time |
+            Category::Frequency | Category::Amplitude => None,
+            Category::Oscillator => Some(Category::Generator),
+            Category::Reverb | Category::Delay | Category::Phaser | Category::Flanger |
+            Category::Chorus => Some(Category::Time),
+            Category::FrequencyMeter | Category::Pitch | Category::Filter => {
+                Some(Category::Frequency)
+            }
+            Category::Lowpass | Category::Highpass | Category::Bandpass | Category::Comb |
+            Category::Allpass | Category::EQ => Some(Category::Filter),
+            Category::ParaEQ | Category::MultiEQ => Some(Category::EQ),
+            Category::Amplifier | Category::Distortion | Category::Modulator |
+            Category::Dynamics => Some(Category::Amplitude),
+            Category::Waveshaper => Some(Category::Distortion),
+            Category::Compressor | Category::Expander | Category::Limiter | Category::Gate => {
+                Some(Category::Dynamics)
+            }
+        }
+    }
+
+    /// Iterates from this class up through its ancestors, not including the root ```Plugin```.
+    pub fn ancestors(self) -> impl Iterator<Item = Category> {
+        std::iter::successors(Some(self), |c| c.parent())
+    }
+}
+
 #[derive(Copy, Clone, Default)]
 pub struct Port {
     pub name: &'static str,
@@ -53,6 +191,89 @@ pub struct Port {
     pub default: Option<DefaultValue>,
     pub lower_bound: Option<Data>,
     pub upper_bound: Option<Data>,
+    /**
+     * Whether hosts may leave the port unconnected or connect it to NULL, as they commonly do
+     * with meters. The plugin then gets a scratch buffer, holding silence for audio inputs and
+     * the default for control inputs, and ```PortConnection::is_connected``` returns false.
+     */
+    pub optional: bool,
+}
+
+/// The name that hosts look for on the control output reporting a plugin's latency.
+pub const LATENCY_PORT_NAME: &str = "latency";
+
+impl Port {
+    /**
+     * A control output named ```"latency"```, the LADSPA convention for reporting latency in
+     * samples. The wrapper writes ```Plugin::latency``` to it after every block.
+     */
+    pub fn latency() -> Port {
+        Port {
+            name: LATENCY_PORT_NAME,
+            desc: PortDescriptor::ControlOutput,
+            ..Default::default()
+        }
+    }
+
+    /// Whether this is a latency output as created by ```Port::latency```.
+    pub fn is_latency(&self) -> bool {
+        self.desc == PortDescriptor::ControlOutput && self.name.eq_ignore_ascii_case(LATENCY_PORT_NAME)
+    }
+
+    fn scale(&self, bound: Data, sample_rate: u64) -> Data {
+        match self.hint {
+            Some(hint) if hint.contains(ControlHint::HINT_SAMPLE_RATE) => {
+                bound * sample_rate as Data
+            }
+            _ => bound,
+        }
+    }
+
+    /// The lower bound, multiplied by ```sample_rate``` if the port has ```HINT_SAMPLE_RATE```.
+    pub fn resolved_lower_bound(&self, sample_rate: u64) -> Option<Data> {
+        self.lower_bound.map(|x| self.scale(x, sample_rate))
+    }
+
+    /// The upper bound, multiplied by ```sample_rate``` if the port has ```HINT_SAMPLE_RATE```.
+    pub fn resolved_upper_bound(&self, sample_rate: u64) -> Option<Data> {
+        self.upper_bound.map(|x| self.scale(x, sample_rate))
+    }
+
+    /**
+     * Computes the concrete default value of the port the way ```ladspa.h``` prescribes: bound
+     * relative defaults interpolate between the resolved bounds (geometrically for
+     * ```HINT_LOGARITHMIC``` ports) and are rounded for ```HINT_INTEGER``` ports. Returns ```None```
+     * if the port has no default or the default refers to a missing bound.
+     */
+    pub fn resolved_default(&self, sample_rate: u64) -> Option<Data> {
+        let hint = self.hint.unwrap_or(ControlHint::empty());
+        let lower = self.resolved_lower_bound(sample_rate);
+        let upper = self.resolved_upper_bound(sample_rate);
+        let interpolate = |weight: Data| -> Option<Data> {
+            let (lower, upper) = (lower?, upper?);
+            if hint.contains(ControlHint::HINT_LOGARITHMIC) && lower > 0.0 && upper > 0.0 {
+                Some((lower.ln() * (1.0 - weight) + upper.ln() * weight).exp())
+            } else {
+                Some(lower * (1.0 - weight) + upper * weight)
+            }
+        };
+        let value = match self.default? {
+            DefaultValue::Minimum => lower?,
+            DefaultValue::Low => interpolate(0.25)?,
+            DefaultValue::Middle => interpolate(0.5)?,
+            DefaultValue::High => interpolate(0.75)?,
+            DefaultValue::Maximum => upper?,
+            DefaultValue::Value0 => 0.0,
+            DefaultValue::Value1 => 1.0,
+            DefaultValue::Value100 => 100.0,
+            DefaultValue::Value440 => 440.0,
+        };
+        if hint.contains(ControlHint::HINT_INTEGER) {
+            Some(value.round())
+        } else {
+            Some(value)
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
@@ -91,6 +312,7 @@ pub enum DefaultValue {
 pub struct PortConnection<'a> {
     pub port: Port,
     pub data: PortData<'a>,
+    connected: bool,
 }
 
 pub enum PortData<'a> {
@@ -103,6 +325,11 @@ pub enum PortData<'a> {
 unsafe impl<'a> Sync for PortData<'a> { }
 
 impl<'a> PortConnection<'a> {
+    /// Whether the host connected the port. Optional ports may be left on a scratch buffer.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
     pub fn unwrap_audio(&'a self) -> &'a [Data] {
         if let PortData::AudioInput(data) = self.data {
             data
@@ -156,4 +383,18 @@ pub trait Plugin {
     fn activate(&mut self) { }
     fn run<'a>(&mut self, sample_count: usize, ports: &[&'a PortConnection<'a>]);
     fn deactivate(&mut self) { }
+
+    /// The latency the plugin introduces, in samples. Reported to hosts through ```Port::latency```.
+    fn latency(&self) -> usize { 0 }
+
+    /**
+     * How many samples of output the plugin may still produce after its input becomes silent,
+     * such as a reverb or delay tail. Offline hosts keep running the plugin for this long;
+     * ```usize::MAX``` means the tail may be infinite. LADSPA hosts read it through
+     * ```ffi::ladspa_rs_tail_samples```.
+     */
+    fn tail_samples(&self) -> usize { 0 }
+
+    /// Plugins implementing ```dssi::SynthPlugin``` return themselves here to receive MIDI.
+    fn as_synth(&mut self) -> Option<&mut dyn dssi::SynthPlugin> { None }
 }
@@ -0,0 +1,351 @@
+/*!
+ * Exposes the plugins returned by ```get_ladspa_descriptor``` as LV2 plugins.
+ *
+ * The exported ```lv2_descriptor``` reuses the same ```Plugin``` instances as the LADSPA wrapper.
+ * LV2 hosts discover plugins through Turtle files rather than by calling into the library, so a
+ * bundle also needs a ```manifest.ttl``` and one ```.ttl``` file per plugin; ```write_bundle```
+ * generates them from the same ```PluginDescriptor```s. Each plugin is published under the URI
+ * returned by ```plugin_uri```.
+ */
+
+use std::ffi::CString;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::os::raw::{c_char, c_ulong, c_void};
+use std::path::Path;
+use std::ptr;
+
+use crate::ffi::{self, ladspa_h};
+use crate::{get_ladspa_descriptor, Category, ControlHint, Data, PluginDescriptor, PortDescriptor,
+            Properties};
+
+// Prevent lv2_descriptor from being stripped during release builds
+#[used]
+static EXPORT_KEEPER: unsafe extern "C" fn(u32) -> *const lv2_h::Descriptor = lv2_descriptor;
+
+pub mod lv2_h {
+    use std::os::raw::{c_char, c_void};
+
+    pub type Handle = *mut c_void;
+
+    #[repr(C)]
+    pub struct Feature {
+        pub uri: *const c_char,
+        pub data: *mut c_void,
+    }
+
+    #[repr(C)]
+    #[allow(missing_copy_implementations)]
+    pub struct Descriptor {
+        pub uri: *const c_char,
+        pub instantiate: Option<unsafe extern "C" fn(descriptor: *const Descriptor,
+                                                     sample_rate: f64,
+                                                     bundle_path: *const c_char,
+                                                     features: *const *const Feature)
+                                                     -> Handle>,
+        pub connect_port: Option<unsafe extern "C" fn(instance: Handle, port: u32, data_location: *mut c_void)>,
+        pub activate: Option<unsafe extern "C" fn(instance: Handle)>,
+        pub run: Option<unsafe extern "C" fn(instance: Handle, sample_count: u32)>,
+        pub deactivate: Option<unsafe extern "C" fn(instance: Handle)>,
+        pub cleanup: Option<unsafe extern "C" fn(instance: Handle)>,
+        pub extension_data: Option<unsafe extern "C" fn(uri: *const c_char) -> *const c_void>,
+    }
+}
+
+const LV2_CORE: &str = "http://lv2plug.in/ns/lv2core#";
+const LV2_PORT_PROPS: &str = "http://lv2plug.in/ns/ext/port-props#";
+
+const NOMINAL_SAMPLE_RATE: u64 = 48000;
+
+// The C descriptor must stay the first field so a descriptor pointer can be cast back to the
+// whole struct in instantiate.
+#[repr(C)]
+struct Lv2Plugin {
+    descriptor: lv2_h::Descriptor,
+    plugin: PluginDescriptor,
+}
+
+static mut DESCRIPTORS: *mut Vec<*mut Lv2Plugin> = ptr::null_mut();
+
+extern "C" fn global_destruct() {
+    unsafe {
+        if !DESCRIPTORS.is_null() {
+            let descriptors = Box::from_raw(DESCRIPTORS);
+            for &descriptor in descriptors.iter() {
+                let plugin = Box::from_raw(descriptor);
+                let _ = CString::from_raw(plugin.descriptor.uri as *mut c_char);
+            }
+            DESCRIPTORS = ptr::null_mut();
+        }
+    }
+}
+
+#[doc(hidden)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lv2_descriptor(index: u32) -> *const lv2_h::Descriptor {
+    unsafe {
+        if DESCRIPTORS.is_null() {
+            libc::atexit(global_destruct);
+            DESCRIPTORS = Box::into_raw(Box::new(Vec::<*mut Lv2Plugin>::new()));
+        }
+
+        let descriptors = &*DESCRIPTORS;
+
+        if (index as usize) < descriptors.len() {
+            return &(*descriptors[index as usize]).descriptor;
+        }
+
+        let descriptor = call_user_code!(get_ladspa_descriptor(index as u64),
+                                         "get_ladspa_descriptor");
+
+        match descriptor {
+            None => ptr::null(),
+            Some(plugin) => {
+                let desc = Box::into_raw(Box::new(Lv2Plugin {
+                    descriptor: lv2_h::Descriptor {
+                        uri: CString::new(plugin_uri(&plugin)).unwrap().into_raw(),
+                        instantiate: Some(instantiate),
+                        connect_port: Some(connect_port),
+                        activate: Some(ffi::activate),
+                        run: Some(run),
+                        deactivate: Some(ffi::deactivate),
+                        cleanup: Some(ffi::cleanup),
+                        extension_data: Some(extension_data),
+                    },
+                    plugin,
+                }));
+
+                (*DESCRIPTORS).push(desc);
+                &(*desc).descriptor
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn instantiate(descriptor: *const lv2_h::Descriptor,
+                                 sample_rate: f64,
+                                 _bundle_path: *const c_char,
+                                 _features: *const *const lv2_h::Feature)
+                                 -> lv2_h::Handle {
+    unsafe {
+        let plugin = &*(descriptor as *const Lv2Plugin);
+        ffi::new_handle(&plugin.plugin, sample_rate as c_ulong)
+    }
+}
+
+unsafe extern "C" fn connect_port(instance: lv2_h::Handle, port: u32, data_location: *mut c_void) {
+    unsafe {
+        ffi::connect_port(instance, port as c_ulong, data_location as *mut ladspa_h::Data);
+    }
+}
+
+unsafe extern "C" fn run(instance: lv2_h::Handle, sample_count: u32) {
+    unsafe {
+        ffi::run(instance, sample_count as c_ulong);
+    }
+}
+
+unsafe extern "C" fn extension_data(_uri: *const c_char) -> *const c_void {
+    ptr::null()
+}
+
+/// The URI under which a plugin is published to LV2 hosts, derived from its LADSPA unique id.
+pub fn plugin_uri(desc: &PluginDescriptor) -> String {
+    format!("urn:ladspa:{}", desc.unique_id)
+}
+
+/// The LV2 class of a category. LADSPA classes without an LV2 counterpart map to their closest
+/// LV2 ancestor.
+pub fn plugin_class(category: Category) -> Option<&'static str> {
+    category.ancestors().find_map(|c| match c {
+        Category::Time | Category::Frequency | Category::Amplitude => None,
+        Category::FrequencyMeter => Some("AnalyserPlugin"),
+        c => Some(c.class_name()),
+    })
+}
+
+/// Turns port names into unique ```lv2:symbol```s, which must be valid C identifiers.
+pub fn port_symbols(desc: &PluginDescriptor) -> Vec<String> {
+    let mut symbols: Vec<String> = Vec::with_capacity(desc.ports.len());
+    for port in desc.ports.iter() {
+        let mut symbol: String = port.name
+            .trim()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect();
+        if symbol.is_empty() || symbol.starts_with(|c: char| c.is_ascii_digit()) {
+            symbol.insert(0, '_');
+        }
+        let base = symbol.clone();
+        let mut n = 1;
+        while symbols.contains(&symbol) {
+            n += 1;
+            symbol = format!("{}_{}", base, n);
+        }
+        symbols.push(symbol);
+    }
+    symbols
+}
+
+// Licenses commonly given as a bare SPDX identifier, which is the only form of the free-form
+// copyright string that can be turned into a license URI.
+const SPDX_LICENSES: &[&str] = &[
+    "0BSD", "AGPL-3.0-only", "AGPL-3.0-or-later", "Apache-2.0", "BSD-2-Clause", "BSD-3-Clause",
+    "CC0-1.0", "GPL-2.0-only", "GPL-2.0-or-later", "GPL-3.0-only", "GPL-3.0-or-later", "ISC",
+    "LGPL-2.1-only", "LGPL-2.1-or-later", "LGPL-3.0-only", "LGPL-3.0-or-later", "MIT", "MPL-2.0",
+    "Unlicense", "Zlib",
+];
+
+/**
+ * The ```doap:license``` of a plugin: its copyright string if that is a URI, or the SPDX license
+ * page if it is a known SPDX identifier. Anything else is only published as ```dc:rights```.
+ */
+pub fn license_uri(desc: &PluginDescriptor) -> Option<String> {
+    let copyright = desc.copyright.trim();
+    if copyright.starts_with("http://") || copyright.starts_with("https://") {
+        return Some(copyright.to_string());
+    }
+    SPDX_LICENSES
+        .iter()
+        .find(|id| id.eq_ignore_ascii_case(copyright))
+        .map(|id| format!("http://spdx.org/licenses/{}", id))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Generates the ```manifest.ttl``` of a bundle containing the given plugins, whose shared
+/// library is ```binary``` (relative to the bundle directory).
+pub fn manifest_ttl(plugins: &[PluginDescriptor], binary: &str) -> String {
+    let mut ttl = String::new();
+    writeln!(ttl, "@prefix lv2: <{}> .", LV2_CORE).unwrap();
+    writeln!(ttl, "@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .").unwrap();
+    for desc in plugins {
+        writeln!(ttl).unwrap();
+        writeln!(ttl, "<{}>", plugin_uri(desc)).unwrap();
+        writeln!(ttl, "    a lv2:Plugin ;").unwrap();
+        writeln!(ttl, "    lv2:binary <{}> ;", binary).unwrap();
+        writeln!(ttl, "    rdfs:seeAlso <{}.ttl> .", desc.label).unwrap();
+    }
+    ttl
+}
+
+/// Generates the Turtle description of a single plugin, mapping its ports, bounds, defaults and
+/// hints to LV2 port properties.
+pub fn plugin_ttl(desc: &PluginDescriptor) -> String {
+    let mut ttl = String::new();
+    writeln!(ttl, "@prefix dc: <http://purl.org/dc/terms/> .").unwrap();
+    writeln!(ttl, "@prefix doap: <http://usefulinc.com/ns/doap#> .").unwrap();
+    writeln!(ttl, "@prefix foaf: <http://xmlns.com/foaf/0.1/> .").unwrap();
+    writeln!(ttl, "@prefix lv2: <{}> .", LV2_CORE).unwrap();
+    writeln!(ttl, "@prefix pprops: <{}> .", LV2_PORT_PROPS).unwrap();
+    writeln!(ttl).unwrap();
+    writeln!(ttl, "<{}>", plugin_uri(desc)).unwrap();
+    match desc.category.and_then(plugin_class) {
+        Some(class) => writeln!(ttl, "    a lv2:Plugin, lv2:{} ;", class).unwrap(),
+        None => writeln!(ttl, "    a lv2:Plugin ;").unwrap(),
+    }
+    writeln!(ttl, "    doap:name \"{}\" ;", escape(desc.name)).unwrap();
+    writeln!(ttl, "    doap:maker [ foaf:name \"{}\" ] ;", escape(desc.maker)).unwrap();
+    writeln!(ttl, "    dc:rights \"{}\" ;", escape(desc.copyright)).unwrap();
+    if let Some(license) = license_uri(desc) {
+        writeln!(ttl, "    doap:license <{}> ;", license).unwrap();
+    }
+    if desc.properties.contains(Properties::PROP_HARD_REALTIME_CAPABLE) {
+        writeln!(ttl, "    lv2:optionalFeature lv2:hardRTCapable ;").unwrap();
+    }
+    if desc.properties.contains(Properties::PROP_INPLACE_BROKEN) {
+        writeln!(ttl, "    lv2:requiredFeature lv2:inPlaceBroken ;").unwrap();
+    }
+
+    let symbols = port_symbols(desc);
+    let mut ports = Vec::new();
+    for (index, (port, symbol)) in desc.ports.iter().zip(symbols.iter()).enumerate() {
+        let mut p = String::new();
+        let (direction, kind) = match port.desc {
+            PortDescriptor::AudioInput => ("InputPort", "AudioPort"),
+            PortDescriptor::AudioOutput => ("OutputPort", "AudioPort"),
+            PortDescriptor::ControlInput => ("InputPort", "ControlPort"),
+            PortDescriptor::ControlOutput => ("OutputPort", "ControlPort"),
+            PortDescriptor::Invalid => panic!("Invalid port descriptor!"),
+        };
+        writeln!(p, "[").unwrap();
+        writeln!(p, "        a lv2:{}, lv2:{} ;", direction, kind).unwrap();
+        writeln!(p, "        lv2:index {} ;", index).unwrap();
+        writeln!(p, "        lv2:symbol \"{}\" ;", symbol).unwrap();
+        write!(p, "        lv2:name \"{}\"", escape(port.name)).unwrap();
+
+        let mut props = Vec::new();
+        if port.optional {
+            props.push("lv2:connectionOptional");
+        }
+        if kind == "ControlPort" {
+            let hint = port.hint.unwrap_or(ControlHint::empty());
+            // Bounds stay relative to the sample rate; lv2:sampleRate tells the host to scale them.
+            if let Some(lower) = port.resolved_lower_bound(1) {
+                write!(p, " ;\n        lv2:minimum {:?}", lower).unwrap();
+            }
+            if let Some(upper) = port.resolved_upper_bound(1) {
+                write!(p, " ;\n        lv2:maximum {:?}", upper).unwrap();
+            }
+            // Absolute defaults of such ports are made relative assuming a typical sample rate.
+            let default = if hint.contains(ControlHint::HINT_SAMPLE_RATE) {
+                port.resolved_default(NOMINAL_SAMPLE_RATE)
+                    .map(|x| x / NOMINAL_SAMPLE_RATE as Data)
+            } else {
+                port.resolved_default(1)
+            };
+            if let Some(default) = default {
+                write!(p, " ;\n        lv2:default {:?}", default).unwrap();
+            }
+            if hint.contains(ControlHint::HINT_TOGGLED) {
+                props.push("lv2:toggled");
+            }
+            if hint.contains(ControlHint::HINT_INTEGER) {
+                props.push("lv2:integer");
+            }
+            if hint.contains(ControlHint::HINT_SAMPLE_RATE) {
+                props.push("lv2:sampleRate");
+            }
+            if hint.contains(ControlHint::HINT_LOGARITHMIC) {
+                props.push("pprops:logarithmic");
+            }
+            if port.is_latency() {
+                props.push("lv2:reportsLatency");
+                write!(p, " ;\n        lv2:designation lv2:latency").unwrap();
+            }
+        }
+        if !props.is_empty() {
+            write!(p, " ;\n        lv2:portProperty {}", props.join(", ")).unwrap();
+        }
+        write!(p, "\n    ]").unwrap();
+        ports.push(p);
+    }
+    if ports.is_empty() {
+        writeln!(ttl, "    lv2:port [] .").unwrap();
+    } else {
+        writeln!(ttl, "    lv2:port {} .", ports.join(" , ")).unwrap();
+    }
+    ttl
+}
+
+/**
+ * Writes ```manifest.ttl``` and a ```<label>.ttl``` per plugin for every plugin returned by
+ * ```get_ladspa_descriptor``` into ```dir```, which becomes an LV2 bundle once the shared library
+ * named ```binary``` is copied next to them.
+ */
+pub fn write_bundle(dir: &Path, binary: &str) -> io::Result<()> {
+    let mut plugins = Vec::new();
+    while let Some(desc) = unsafe { get_ladspa_descriptor(plugins.len() as u64) } {
+        plugins.push(desc);
+    }
+
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("manifest.ttl"), manifest_ttl(&plugins, binary))?;
+    for desc in plugins.iter() {
+        fs::write(dir.join(format!("{}.ttl", desc.label)), plugin_ttl(desc))?;
+    }
+    Ok(())
+}
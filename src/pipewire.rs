@@ -0,0 +1,258 @@
+/*!
+ * Generates configuration for PipeWire's ```libpipewire-module-filter-chain```, which runs LADSPA
+ * plugins as a virtual sink on modern Linux desktops.
+ *
+ * ```rust,ignore
+ * let conf = FilterChain::new("Stereo Delay")
+ *     .plugin("/usr/lib/ladspa/librustdelay.so", &get_ladspa_descriptor(0).unwrap())
+ *     .control("Left Delay (seconds)", 0.3)
+ *     .to_conf()?;
+ * ```
+ *
+ * Node ports are named after ```Port::name``` and every control input with a default gets its
+ * resolved default value. Consecutive plugins are linked output to input in port order; a mono
+ * output feeds every input of the next plugin. When the chain has fewer channels than requested
+ * with ```channels```, PipeWire runs one copy of the graph per channel.
+ */
+
+use std::error;
+use std::fmt::{self, Write as FmtWrite};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{Data, PluginDescriptor, PortDescriptor};
+
+/// The sample rate used to resolve defaults unless one is given with ```sample_rate```.
+pub const DEFAULT_SAMPLE_RATE: u64 = 48000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The chain contains no plugins.
+    Empty,
+    /// A plugin's audio outputs cannot be linked to the next plugin's audio inputs.
+    ChannelMismatch { from: String, to: String, outputs: usize, inputs: usize },
+    /// ```control``` named a port that is not a control input of the plugin.
+    UnknownControl { node: String, port: String },
+    /// A control value is NaN or infinite, which SPA-JSON cannot represent.
+    NonFiniteControl { node: String, port: String, value: Data },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Empty => write!(f, "the filter chain contains no plugins"),
+            Error::ChannelMismatch { ref from, ref to, outputs, inputs } => {
+                write!(f, "cannot link {} audio outputs of {} to {} audio inputs of {}",
+                       outputs, from, inputs, to)
+            }
+            Error::UnknownControl { ref node, ref port } => {
+                write!(f, "{} has no control input named {:?}", node, port)
+            }
+            Error::NonFiniteControl { ref node, ref port, value } => {
+                write!(f, "control {:?} of {} is {}, which is not a finite number", port, node, value)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+struct Node {
+    name: String,
+    library: String,
+    label: &'static str,
+    inputs: Vec<&'static str>,
+    outputs: Vec<&'static str>,
+    control_inputs: Vec<&'static str>,
+    controls: Vec<(&'static str, Data)>,
+    overrides: Vec<(String, Data)>,
+}
+
+/// A serial chain of LADSPA plugins to be run by the filter-chain module.
+pub struct FilterChain {
+    description: String,
+    sample_rate: u64,
+    channels: Option<usize>,
+    nodes: Vec<Node>,
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl FilterChain {
+    pub fn new(description: &str) -> FilterChain {
+        FilterChain {
+            description: description.to_string(),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            channels: None,
+            nodes: Vec::new(),
+        }
+    }
+
+    /// The sample rate that ```HINT_SAMPLE_RATE``` defaults are resolved for.
+    pub fn sample_rate(mut self, sample_rate: u64) -> FilterChain {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// The number of channels of the virtual sink, if different from the chain's own.
+    pub fn channels(mut self, channels: usize) -> FilterChain {
+        self.channels = Some(channels);
+        self
+    }
+
+    /// Appends the plugin described by ```desc```, loaded from the library at ```library```.
+    pub fn plugin(mut self, library: &str, desc: &PluginDescriptor) -> FilterChain {
+        let mut name = desc.label.to_string();
+        let mut n = 1;
+        while self.nodes.iter().any(|node| node.name == name) {
+            n += 1;
+            name = format!("{}_{}", desc.label, n);
+        }
+        let ports = |kind: PortDescriptor| {
+            desc.ports.iter().filter(|p| p.desc == kind).map(|p| p.name).collect::<Vec<_>>()
+        };
+
+        self.nodes.push(Node {
+            name,
+            library: library.to_string(),
+            label: desc.label,
+            inputs: ports(PortDescriptor::AudioInput),
+            outputs: ports(PortDescriptor::AudioOutput),
+            control_inputs: ports(PortDescriptor::ControlInput),
+            controls: desc.ports
+                .iter()
+                .filter(|p| p.desc == PortDescriptor::ControlInput)
+                .filter_map(|p| p.resolved_default(self.sample_rate).map(|x| (p.name, x)))
+                .collect(),
+            overrides: Vec::new(),
+        });
+        self
+    }
+
+    /// Sets a control input of the most recently added plugin, overriding its default.
+    pub fn control(mut self, port: &str, value: Data) -> FilterChain {
+        if let Some(node) = self.nodes.last_mut() {
+            node.overrides.push((port.to_string(), value));
+        }
+        self
+    }
+
+    fn links(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut links = Vec::new();
+        for pair in self.nodes.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            let port = |node: &Node, name: &str| quote(&format!("{}:{}", node.name, name));
+            if from.outputs.len() == to.inputs.len() {
+                for (output, input) in from.outputs.iter().zip(to.inputs.iter()) {
+                    links.push((port(from, output), port(to, input)));
+                }
+            } else if from.outputs.len() == 1 {
+                for input in to.inputs.iter() {
+                    links.push((port(from, from.outputs[0]), port(to, input)));
+                }
+            } else {
+                return Err(Error::ChannelMismatch {
+                    from: from.name.clone(),
+                    to: to.name.clone(),
+                    outputs: from.outputs.len(),
+                    inputs: to.inputs.len(),
+                });
+            }
+        }
+        Ok(links)
+    }
+
+    /// Generates the contents of a ```.conf``` file for ```~/.config/pipewire/filter-chain.conf.d```.
+    pub fn to_conf(&self) -> Result<String, Error> {
+        let (first, last) = match (self.nodes.first(), self.nodes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(Error::Empty),
+        };
+        for node in self.nodes.iter() {
+            if let Some((port, _)) = node.overrides.iter().find(|(p, _)| !node.control_inputs.contains(&p.as_str())) {
+                return Err(Error::UnknownControl { node: node.name.clone(), port: port.clone() });
+            }
+            let overrides = node.overrides.iter().map(|(p, v)| (p.as_str(), *v));
+            for (port, value) in node.controls.iter().cloned().chain(overrides) {
+                if !value.is_finite() {
+                    return Err(Error::NonFiniteControl { node: node.name.clone(), port: port.to_string(), value });
+                }
+            }
+        }
+        let links = self.links()?;
+        let channels = self.channels.unwrap_or(first.inputs.len().max(last.outputs.len()).max(1));
+        let id = self.nodes.iter().map(|n| n.name.as_str()).collect::<Vec<_>>().join("_");
+
+        let mut conf = String::new();
+        writeln!(conf, "context.modules = [").unwrap();
+        writeln!(conf, "    {{ name = libpipewire-module-filter-chain").unwrap();
+        writeln!(conf, "        args = {{").unwrap();
+        writeln!(conf, "            node.description = {}", quote(&self.description)).unwrap();
+        writeln!(conf, "            media.name = {}", quote(&self.description)).unwrap();
+        writeln!(conf, "            filter.graph = {{").unwrap();
+        writeln!(conf, "                nodes = [").unwrap();
+        for node in self.nodes.iter() {
+            writeln!(conf, "                    {{").unwrap();
+            writeln!(conf, "                        type = ladspa").unwrap();
+            writeln!(conf, "                        name = {}", quote(&node.name)).unwrap();
+            writeln!(conf, "                        plugin = {}", quote(&node.library)).unwrap();
+            writeln!(conf, "                        label = {}", quote(node.label)).unwrap();
+            writeln!(conf, "                        control = {{").unwrap();
+            for &(port, default) in node.controls.iter() {
+                let value = node.overrides
+                    .iter()
+                    .rev()
+                    .find(|(p, _)| p == port)
+                    .map(|&(_, v)| v)
+                    .unwrap_or(default);
+                writeln!(conf, "                            {} = {:?}", quote(port), value).unwrap();
+            }
+            for (port, value) in node.overrides.iter() {
+                if !node.controls.iter().any(|(p, _)| p == port) {
+                    writeln!(conf, "                            {} = {:?}", quote(port), value).unwrap();
+                }
+            }
+            writeln!(conf, "                        }}").unwrap();
+            writeln!(conf, "                    }}").unwrap();
+        }
+        writeln!(conf, "                ]").unwrap();
+        writeln!(conf, "                links = [").unwrap();
+        for (output, input) in links.iter() {
+            writeln!(conf, "                    {{ output = {} input = {} }}", output, input).unwrap();
+        }
+        writeln!(conf, "                ]").unwrap();
+        let ports = |node: &Node, names: &[&str]| {
+            names.iter().map(|p| quote(&format!("{}:{}", node.name, p))).collect::<Vec<_>>().join(" ")
+        };
+        writeln!(conf, "                inputs = [ {} ]", ports(first, &first.inputs)).unwrap();
+        writeln!(conf, "                outputs = [ {} ]", ports(last, &last.outputs)).unwrap();
+        writeln!(conf, "            }}").unwrap();
+        writeln!(conf, "            audio.channels = {}", channels).unwrap();
+        match channels {
+            1 => writeln!(conf, "            audio.position = [ MONO ]").unwrap(),
+            2 => writeln!(conf, "            audio.position = [ FL FR ]").unwrap(),
+            _ => {}
+        }
+        writeln!(conf, "            capture.props = {{").unwrap();
+        writeln!(conf, "                node.name = {}", quote(&format!("effect_input.{}", id))).unwrap();
+        writeln!(conf, "                media.class = Audio/Sink").unwrap();
+        writeln!(conf, "            }}").unwrap();
+        writeln!(conf, "            playback.props = {{").unwrap();
+        writeln!(conf, "                node.name = {}", quote(&format!("effect_output.{}", id))).unwrap();
+        writeln!(conf, "                node.passive = true").unwrap();
+        writeln!(conf, "            }}").unwrap();
+        writeln!(conf, "        }}").unwrap();
+        writeln!(conf, "    }}").unwrap();
+        writeln!(conf, "]").unwrap();
+        Ok(conf)
+    }
+
+    /// Writes the configuration generated by ```to_conf``` to ```path```.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let conf = self.to_conf().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        fs::write(path, conf)
+    }
+}
@@ -0,0 +1,146 @@
+/*!
+ * Per-instance processing cost counters, for comparing DSP cost between releases inside any host.
+ *
+ * Counting is off by default. Setting ```LADSPA_RS_PROFILE``` turns it on for every instance and
+ * makes each instance print its ```Profile``` when it is cleaned up: to standard error if the
+ * variable is ```1```, otherwise appended to the file it names. Tests can turn counting on with
+ * ```enable``` and read the counters with ```host::Instance::profile```.
+ */
+
+use std::env;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The environment variable that turns profiling on.
+pub const PROFILE_VAR: &str = "LADSPA_RS_PROFILE";
+
+/// The number of most recent blocks that percentiles are taken over.
+pub const RECENT_BLOCKS: usize = 4096;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static FROM_ENV: OnceLock<Option<String>> = OnceLock::new();
+
+fn env_target() -> Option<&'static str> {
+    FROM_ENV.get_or_init(|| env::var(PROFILE_VAR).ok().filter(|x| !x.is_empty())).as_deref()
+}
+
+/// Turns on counting for instances created from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether instances created now get counters.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed) || env_target().is_some()
+}
+
+/// The cost of processing of one instance. Times are nanoseconds per sample, measured per block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub label: &'static str,
+    pub runs: u64,
+    pub samples: u64,
+    pub panics: u64,
+    pub min: f64,
+    /// The mean over all samples, so longer blocks weigh more.
+    pub mean: f64,
+    pub max: f64,
+    /// The median of the most recent ```RECENT_BLOCKS``` blocks.
+    pub p50: f64,
+    /// The 99th percentile of the most recent ```RECENT_BLOCKS``` blocks.
+    pub p99: f64,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} runs, {} samples, {} panics, ns/sample min {:.2} mean {:.2} p50 {:.2} p99 {:.2} max {:.2}",
+               self.label, self.runs, self.samples, self.panics,
+               self.min, self.mean, self.p50, self.p99, self.max)
+    }
+}
+
+// The counters kept by a handle. Recording never allocates.
+pub(crate) struct Counters {
+    runs: u64,
+    samples: u64,
+    panics: u64,
+    nanos: u128,
+    min: f64,
+    max: f64,
+    recent: Box<[f32]>,
+    recent_count: usize,
+}
+
+impl Counters {
+    pub(crate) fn new() -> Counters {
+        Counters {
+            runs: 0,
+            samples: 0,
+            panics: 0,
+            nanos: 0,
+            min: f64::INFINITY,
+            max: 0.0,
+            recent: vec![0.0; RECENT_BLOCKS].into_boxed_slice(),
+            recent_count: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, sample_count: usize, elapsed: Duration, panicked: bool) {
+        self.runs += 1;
+        if panicked {
+            self.panics += 1;
+        }
+        if sample_count == 0 {
+            return;
+        }
+        let per_sample = elapsed.as_nanos() as f64 / sample_count as f64;
+        self.samples += sample_count as u64;
+        self.nanos += elapsed.as_nanos();
+        self.min = self.min.min(per_sample);
+        self.max = self.max.max(per_sample);
+        self.recent[self.recent_count % RECENT_BLOCKS] = per_sample as f32;
+        self.recent_count += 1;
+    }
+
+    pub(crate) fn profile(&self, label: &'static str) -> Profile {
+        let mut recent = self.recent[..self.recent_count.min(RECENT_BLOCKS)].to_vec();
+        recent.sort_by(f32::total_cmp);
+        let percentile = |p: f64| match recent.len() {
+            0 => 0.0,
+            n => recent[((n - 1) as f64 * p).round() as usize] as f64,
+        };
+        Profile {
+            label,
+            runs: self.runs,
+            samples: self.samples,
+            panics: self.panics,
+            min: if self.samples == 0 { 0.0 } else { self.min },
+            mean: if self.samples == 0 { 0.0 } else { self.nanos as f64 / self.samples as f64 },
+            max: self.max,
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+// Writes the profile of an instance being cleaned up, if LADSPA_RS_PROFILE asks for it.
+pub(crate) fn dump(profile: &Profile) {
+    match env_target() {
+        None => {}
+        Some("1") => eprintln!("LADSPA profile: {}", profile),
+        Some(path) => {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", profile));
+            if let Err(e) = written {
+                eprintln!("LADSPA profile: cannot write {}: {}", path, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+/*!
+ * Generates LRDF descriptions, the RDF/XML files LADSPA hosts read from ```/usr/share/ladspa/rdf```
+ * to learn what the plugin ABI has no slot for, most importantly the ```Category``` of a plugin.
+ *
+ * ```rust,ignore
+ * ladspa::rdf::write_rdf(Path::new("/usr/share/ladspa/rdf/rustdelay.rdf"))?;
+ * ```
+ *
+ * Each plugin is described as ```ladspa:<unique_id>```, an instance of the class of its category
+ * or of ```ladspa:Plugin``` without one, and each port as ```ladspa:<unique_id>.<index>```.
+ */
+
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{get_ladspa_descriptor, PluginDescriptor, PortDescriptor, LADSPA_ONTOLOGY};
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The class of a plugin, relative to ```LADSPA_ONTOLOGY```.
+pub fn plugin_class(desc: &PluginDescriptor) -> &'static str {
+    desc.category.map_or("Plugin", |x| x.class_name())
+}
+
+/// Generates the ```<rdf:RDF>``` document describing the given plugins.
+pub fn rdf(plugins: &[PluginDescriptor]) -> String {
+    let mut rdf = String::new();
+    writeln!(rdf, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(rdf, "<!DOCTYPE rdf:RDF [").unwrap();
+    writeln!(rdf, "  <!ENTITY rdf \"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">").unwrap();
+    writeln!(rdf, "  <!ENTITY rdfs \"http://www.w3.org/2000/01/rdf-schema#\">").unwrap();
+    writeln!(rdf, "  <!ENTITY dc \"http://purl.org/dc/elements/1.1/\">").unwrap();
+    writeln!(rdf, "  <!ENTITY ladspa \"{}\">", LADSPA_ONTOLOGY).unwrap();
+    writeln!(rdf, "]>").unwrap();
+    writeln!(rdf, "<rdf:RDF xmlns:rdf=\"&rdf;\" xmlns:rdfs=\"&rdfs;\" xmlns:dc=\"&dc;\" xmlns:ladspa=\"&ladspa;\">").unwrap();
+    for desc in plugins {
+        let class = plugin_class(desc);
+        writeln!(rdf, "  <ladspa:{} rdf:about=\"&ladspa;{}\">", class, desc.unique_id).unwrap();
+        writeln!(rdf, "    <dc:title>{}</dc:title>", escape(desc.name)).unwrap();
+        writeln!(rdf, "    <dc:creator>{}</dc:creator>", escape(desc.maker)).unwrap();
+        writeln!(rdf, "    <dc:rights>{}</dc:rights>", escape(desc.copyright)).unwrap();
+        for (index, port) in desc.ports.iter().enumerate() {
+            let port_class = match port.desc {
+                PortDescriptor::AudioInput => "InputAudioPort",
+                PortDescriptor::AudioOutput => "OutputAudioPort",
+                PortDescriptor::ControlInput => "InputControlPort",
+                PortDescriptor::ControlOutput => "OutputControlPort",
+                PortDescriptor::Invalid => panic!("Invalid port descriptor!"),
+            };
+            writeln!(rdf, "    <ladspa:hasPort>").unwrap();
+            writeln!(rdf, "      <ladspa:{} rdf:about=\"&ladspa;{}.{}\">", port_class, desc.unique_id, index).unwrap();
+            writeln!(rdf, "        <dc:title>{}</dc:title>", escape(port.name)).unwrap();
+            writeln!(rdf, "      </ladspa:{}>", port_class).unwrap();
+            writeln!(rdf, "    </ladspa:hasPort>").unwrap();
+        }
+        writeln!(rdf, "  </ladspa:{}>", class).unwrap();
+    }
+    writeln!(rdf, "</rdf:RDF>").unwrap();
+    rdf
+}
+
+/// Writes the description of every plugin returned by ```get_ladspa_descriptor``` to ```path```.
+pub fn write_rdf(path: &Path) -> io::Result<()> {
+    let mut plugins = Vec::new();
+    while let Some(desc) = unsafe { get_ladspa_descriptor(plugins.len() as u64) } {
+        plugins.push(desc);
+    }
+    fs::write(path, rdf(&plugins))
+}
@@ -0,0 +1,404 @@
+/*!
+ * Measures how fast a plugin processes audio, and compares the results against a stored baseline.
+ *
+ * ```rust,ignore
+ * let results = Bench::new(plugin)
+ *     .block_sizes(&[64, 512])
+ *     .sample_rates(&[48000])
+ *     .preset("wet", &[(6, 1.0), (7, 1.0)])
+ *     .run();
+ * match bench::load_baseline(path) {
+ *     Ok(baseline) => assert!(bench::compare(&baseline, &results, 0.1).is_empty()),
+ *     Err(_) => bench::save_baseline(path, &results)?,
+ * }
+ * ```
+ *
+ * Every configuration is timed over the same amount of audio, with white noise on the audio
+ * inputs. Baselines are JSON files holding an array with one object per configuration.
+ */
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::noise_inputs;
+use crate::host::PluginRef;
+use crate::{Data, PortDescriptor};
+
+/// The configuration and result of one measurement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Measurement {
+    pub block_size: usize,
+    pub sample_rate: u64,
+    pub preset: String,
+    pub samples: u64,
+    pub ns_per_sample: f64,
+    /// Seconds of audio processed per second of processing time.
+    pub realtime_factor: f64,
+    /// Time stamp counter cycles per sample, on x86_64.
+    pub cycles_per_sample: Option<f64>,
+}
+
+impl Measurement {
+    fn key(&self) -> (usize, u64, &str) {
+        (self.block_size, self.sample_rate, &self.preset)
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Hz, {} samples/block, {}: {:.2} ns/sample, {:.1}x realtime",
+               self.sample_rate, self.block_size, self.preset, self.ns_per_sample, self.realtime_factor)?;
+        if let Some(cycles) = self.cycles_per_sample {
+            write!(f, ", {:.1} cycles/sample", cycles)?;
+        }
+        Ok(())
+    }
+}
+
+/// A benchmark of one plugin over every combination of block size, sample rate and preset.
+pub struct Bench {
+    plugin: PluginRef,
+    block_sizes: Vec<usize>,
+    sample_rates: Vec<u64>,
+    presets: Vec<(String, Vec<(usize, Data)>)>,
+    seconds: f64,
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cycles() -> Option<u64> {
+    Some(unsafe { std::arch::x86_64::_rdtsc() })
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn cycles() -> Option<u64> {
+    None
+}
+
+impl Bench {
+    /// A benchmark at 64, 256 and 1024 samples per block and 48 kHz, with default controls.
+    pub fn new(plugin: PluginRef) -> Bench {
+        Bench {
+            plugin,
+            block_sizes: vec![64, 256, 1024],
+            sample_rates: vec![48000],
+            presets: Vec::new(),
+            seconds: 10.0,
+        }
+    }
+
+    pub fn block_sizes(mut self, block_sizes: &[usize]) -> Bench {
+        self.block_sizes = block_sizes.to_vec();
+        self
+    }
+
+    pub fn sample_rates(mut self, sample_rates: &[u64]) -> Bench {
+        self.sample_rates = sample_rates.to_vec();
+        self
+    }
+
+    /// Adds a control setting to measure, as values for control input ports. Without presets,
+    /// only the defaults are measured.
+    pub fn preset(mut self, name: &str, controls: &[(usize, Data)]) -> Bench {
+        self.presets.push((name.to_string(), controls.to_vec()));
+        self
+    }
+
+    /// The amount of audio processed per configuration, in seconds.
+    pub fn seconds(mut self, seconds: f64) -> Bench {
+        self.seconds = seconds;
+        self
+    }
+
+    /**
+     * Runs every configuration.
+     *
+     * # Panics
+     * Panics if the plugin cannot be instantiated or a preset sets a port that is not a control input.
+     */
+    pub fn run(&self) -> Vec<Measurement> {
+        let default = vec![("default".to_string(), Vec::new())];
+        let presets = if self.presets.is_empty() { &default } else { &self.presets };
+        let mut results = Vec::new();
+        for &sample_rate in self.sample_rates.iter() {
+            for &block_size in self.block_sizes.iter() {
+                for (name, controls) in presets.iter() {
+                    results.push(self.measure(sample_rate, block_size.max(1), name, controls));
+                }
+            }
+        }
+        results
+    }
+
+    fn measure(&self, sample_rate: u64, block_size: usize, preset: &str, controls: &[(usize, Data)]) -> Measurement {
+        let plugin = self.plugin;
+        let mut instance = plugin.instantiate(sample_rate).expect("instantiate returned NULL");
+        for &(port, value) in controls.iter() {
+            instance.set_control(port, value).unwrap_or_else(|e| panic!("preset: {}", e));
+        }
+        instance.activate();
+
+        let mut inputs = noise_inputs(plugin, 1, block_size);
+        let mut outputs = vec![vec![0.0; block_size]; plugin.ports_of(PortDescriptor::AudioOutput).len()];
+        unsafe {
+            for (buffer, port) in inputs.iter_mut().zip(plugin.ports_of(PortDescriptor::AudioInput)) {
+                instance.connect(port, buffer.as_mut_ptr());
+            }
+            for (buffer, port) in outputs.iter_mut().zip(plugin.ports_of(PortDescriptor::AudioOutput)) {
+                instance.connect(port, buffer.as_mut_ptr());
+            }
+            // Warm up caches and any lazily allocated state.
+            instance.run_raw(block_size);
+        }
+
+        let blocks = ((self.seconds * sample_rate as f64) / block_size as f64).ceil().max(1.0) as u64;
+        let start_cycles = cycles();
+        let start = Instant::now();
+        for _ in 0..blocks {
+            unsafe { instance.run_raw(block_size) };
+        }
+        let elapsed = start.elapsed().max(Duration::from_nanos(1));
+        let end_cycles = cycles();
+
+        let samples = blocks * block_size as u64;
+        Measurement {
+            block_size,
+            sample_rate,
+            preset: preset.to_string(),
+            samples,
+            ns_per_sample: elapsed.as_nanos() as f64 / samples as f64,
+            realtime_factor: samples as f64 / sample_rate as f64 / elapsed.as_secs_f64(),
+            cycles_per_sample: match (start_cycles, end_cycles) {
+                (Some(start), Some(end)) => Some(end.wrapping_sub(start) as f64 / samples as f64),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// A configuration that got slower than the baseline by more than the threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Regression {
+    pub baseline: Measurement,
+    pub current: Measurement,
+}
+
+impl Regression {
+    /// The relative increase in time per sample.
+    pub fn change(&self) -> f64 {
+        self.current.ns_per_sample / self.baseline.ns_per_sample - 1.0
+    }
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Hz, {} samples/block, {}: {:.2} ns/sample, was {:.2} (+{:.1}%)",
+               self.current.sample_rate, self.current.block_size, self.current.preset,
+               self.current.ns_per_sample, self.baseline.ns_per_sample, self.change() * 100.0)
+    }
+}
+
+/**
+ * The configurations measured in both ```baseline``` and ```current``` whose time per sample grew
+ * by more than ```threshold```, e.g. 0.1 for 10%.
+ */
+pub fn compare(baseline: &[Measurement], current: &[Measurement], threshold: f64) -> Vec<Regression> {
+    current
+        .iter()
+        .filter_map(|current| {
+            let baseline = baseline.iter().find(|b| b.key() == current.key())?;
+            if current.ns_per_sample > baseline.ns_per_sample * (1.0 + threshold) {
+                Some(Regression { baseline: baseline.clone(), current: current.clone() })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Serialises measurements as a JSON array of objects.
+pub fn to_json(measurements: &[Measurement]) -> String {
+    let objects: Vec<String> = measurements
+        .iter()
+        .map(|m| {
+            format!("  {{\"block_size\": {}, \"sample_rate\": {}, \"preset\": {}, \"samples\": {}, \
+                     \"ns_per_sample\": {:?}, \"realtime_factor\": {:?}, \"cycles_per_sample\": {}}}",
+                    m.block_size, m.sample_rate, json_string(&m.preset), m.samples,
+                    m.ns_per_sample, m.realtime_factor,
+                    m.cycles_per_sample.map(|x| format!("{:?}", x)).unwrap_or_else(|| "null".to_string()))
+        })
+        .collect();
+    format!("[\n{}\n]\n", objects.join(",\n"))
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{} at byte {}", msg, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        if self.s.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> io::Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c as char)))
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        loop {
+            let rest = std::str::from_utf8(&self.s[self.pos..]).map_err(|_| self.error("invalid UTF-8"))?;
+            let mut chars = rest.chars();
+            match chars.next() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some('\\') => {
+                    let escaped = chars.next().ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 2;
+                    match escaped {
+                        'u' => {
+                            let hex = rest.get(2..6).ok_or_else(|| self.error("bad escape"))?;
+                            let code = u32::from_str_radix(hex, 16).map_err(|_| self.error("bad escape"))?;
+                            out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                            self.pos += 4;
+                        }
+                        'n' => out.push('\n'),
+                        't' => out.push('\t'),
+                        c => out.push(c),
+                    }
+                }
+                Some(c) => {
+                    out.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    // A number, or None for null.
+    fn number(&mut self) -> io::Result<Option<f64>> {
+        self.skip_whitespace();
+        if self.s[self.pos..].starts_with(b"null") {
+            self.pos += 4;
+            return Ok(None);
+        }
+        let start = self.pos;
+        while self.pos < self.s.len() && b"+-0123456789.eE".contains(&self.s[self.pos]) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.pos])
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .map(Some)
+            .ok_or_else(|| self.error("expected a number"))
+    }
+
+    fn required_number(&mut self) -> io::Result<f64> {
+        self.number()?.ok_or_else(|| self.error("expected a number"))
+    }
+
+    fn measurement(&mut self) -> io::Result<Measurement> {
+        self.expect(b'{')?;
+        let mut m = Measurement {
+            block_size: 0,
+            sample_rate: 0,
+            preset: String::new(),
+            samples: 0,
+            ns_per_sample: 0.0,
+            realtime_factor: 0.0,
+            cycles_per_sample: None,
+        };
+        if self.eat(b'}') {
+            return Ok(m);
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(b':')?;
+            match key.as_str() {
+                "preset" => m.preset = self.string()?,
+                "block_size" => m.block_size = self.required_number()? as usize,
+                "sample_rate" => m.sample_rate = self.required_number()? as u64,
+                "samples" => m.samples = self.required_number()? as u64,
+                "ns_per_sample" => m.ns_per_sample = self.required_number()?,
+                "realtime_factor" => m.realtime_factor = self.required_number()?,
+                "cycles_per_sample" => m.cycles_per_sample = self.number()?,
+                _ => return Err(self.error(&format!("unknown key {:?}", key))),
+            }
+            if !self.eat(b',') {
+                break;
+            }
+        }
+        self.expect(b'}')?;
+        Ok(m)
+    }
+}
+
+/// Parses measurements written by ```to_json```.
+pub fn from_json(json: &str) -> io::Result<Vec<Measurement>> {
+    let mut parser = Parser { s: json.as_bytes(), pos: 0 };
+    let mut measurements = Vec::new();
+    parser.expect(b'[')?;
+    if !parser.eat(b']') {
+        loop {
+            measurements.push(parser.measurement()?);
+            if !parser.eat(b',') {
+                break;
+            }
+        }
+        parser.expect(b']')?;
+    }
+    parser.skip_whitespace();
+    if parser.pos != parser.s.len() {
+        return Err(parser.error("trailing data"));
+    }
+    Ok(measurements)
+}
+
+pub fn save_baseline(path: &Path, measurements: &[Measurement]) -> io::Result<()> {
+    fs::write(path, to_json(measurements))
+}
+
+pub fn load_baseline(path: &Path) -> io::Result<Vec<Measurement>> {
+    from_json(&fs::read_to_string(path)?)
+}
@@ -0,0 +1,86 @@
+/*!
+ * Checks that a plugin's output does not depend on how the host splits the signal into blocks.
+ *
+ * The signal is rendered once as a single block and then through fresh instances with each of
+ * ```BLOCK_SIZES``` and with randomly varying block sizes; every render must match the first.
+ */
+
+use std::fmt;
+
+use super::{first_difference, noise_inputs, Difference, Rng};
+use crate::host::PluginRef;
+use crate::Data;
+
+/// The fixed block sizes that are checked.
+pub const BLOCK_SIZES: [usize; 4] = [1, 7, 64, 4096];
+
+/// The number of renders with randomly varying block sizes.
+pub const RANDOM_RUNS: u64 = 4;
+
+/// The largest block size used by the random renders.
+pub const MAX_RANDOM_BLOCK_SIZE: usize = 1024;
+
+/// Where a render with different block sizes first diverged from the single-block render.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// A description of the block sizes that were used.
+    pub block_sizes: String,
+    pub difference: Difference,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "with {} blocks, {}", self.block_sizes, self.difference)
+    }
+}
+
+/// The block sizes for the random render with the given seed.
+pub fn random_block_sizes(seed: u64, length: usize) -> Vec<usize> {
+    let mut rng = Rng::new(seed);
+    let mut sizes = Vec::new();
+    let mut total = 0;
+    while total < length {
+        let size = 1 + rng.below(MAX_RANDOM_BLOCK_SIZE);
+        sizes.push(size);
+        total += size;
+    }
+    sizes
+}
+
+/**
+ * Renders ```length``` samples of ```inputs```, one buffer per audio input, with every block size
+ * configuration and reports the first sample that differs from the single-block render by more
+ * than ```tolerance```.
+ *
+ * # Panics
+ * Panics if the plugin cannot be instantiated.
+ */
+pub fn check(plugin: PluginRef,
+             sample_rate: u64,
+             inputs: &[Vec<Data>],
+             length: usize,
+             tolerance: Data)
+             -> Result<(), Divergence> {
+    let render = |sizes: Vec<usize>| {
+        let mut instance = plugin.instantiate(sample_rate).expect("instantiate returned NULL");
+        instance.activate();
+        instance.render(inputs, length, sizes)
+    };
+    let expected = render(vec![length]);
+
+    let fixed = BLOCK_SIZES.iter().map(|&size| (format!("{}-sample", size), vec![size; length.div_ceil(size)]));
+    let random = (1..=RANDOM_RUNS).map(|seed| {
+        (format!("random (seed {})", seed), random_block_sizes(seed, length))
+    });
+    for (block_sizes, sizes) in fixed.chain(random) {
+        if let Some(difference) = first_difference(&expected, &render(sizes), tolerance) {
+            return Err(Divergence { block_sizes, difference });
+        }
+    }
+    Ok(())
+}
+
+/// Runs ```check``` with white noise on every audio input.
+pub fn check_noise(plugin: PluginRef, sample_rate: u64, length: usize, tolerance: Data) -> Result<(), Divergence> {
+    check(plugin, sample_rate, &noise_inputs(plugin, 1, length), length, tolerance)
+}
@@ -0,0 +1,274 @@
+/*!
+ * Null tests between two plugins, such as a port and the original it was ported from, or two
+ * versions of the same library.
+ *
+ * Both plugins render the same input with the same control settings. The output of ```b``` is
+ * then shifted to line up with ```a```, either by their declared latency or by cross-correlation,
+ * and the difference is measured:
+ *
+ * ```rust,ignore
+ * let original = Library::open(Path::new("/usr/lib/ladspa/cmt.so"))?.find("delay_5s").unwrap();
+ * let port = ladspa::host::own_plugins()[0];
+ * let report = Comparison::new(original, port)
+ *     .controls(&[(0, 0.25), (1, 0.5)])
+ *     .alignment(Alignment::CrossCorrelation { max_lag: 4096 })
+ *     .run();
+ * assert!(report.nulls(-90.0), "{}", report);
+ * ```
+ *
+ * Ports are matched by index, so both plugins need the same port layout.
+ */
+
+use std::fmt;
+
+use super::fft::{self, fft, ifft};
+use super::noise_inputs;
+use crate::host::{Instance, PluginRef};
+use crate::Data;
+
+/// How the output of ```b``` is lined up with the output of ```a```.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    None,
+    /// By the values of the plugins' latency outputs.
+    Latency,
+    /// By the peak of the cross-correlation of the first outputs, within ```max_lag``` samples.
+    CrossCorrelation { max_lag: usize },
+}
+
+/// The largest frame the spectra are averaged over.
+pub const SPECTRUM_FRAME: usize = 2048;
+
+/// How far below the peak of the spectrum of ```a``` a bin may be and still count towards the
+/// spectral difference, in dB.
+pub const SPECTRUM_RANGE_DB: f64 = 60.0;
+
+/// The difference between one pair of outputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputReport {
+    pub output: usize,
+    /// The RMS of the difference relative to the RMS of ```a```, in dB.
+    pub residual_db: f64,
+    /// The largest absolute difference, and the sample of ```a``` it is at.
+    pub peak_difference: Data,
+    pub peak_sample: usize,
+    /// The largest difference between the average magnitude spectra, in dB, and its frequency.
+    pub spectral_db: f64,
+    pub spectral_frequency: f64,
+}
+
+/// The result of a ```Comparison```.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// How many samples later the output of ```b``` is than that of ```a```.
+    pub lag: isize,
+    pub outputs: Vec<OutputReport>,
+}
+
+impl Report {
+    /// Whether every residual is at or below ```threshold_db```.
+    pub fn nulls(&self, threshold_db: f64) -> bool {
+        self.outputs.iter().all(|x| x.residual_db <= threshold_db)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "lag {} samples", self.lag)?;
+        for output in self.outputs.iter() {
+            write!(f, "\noutput {}: residual {:.1} dB, peak difference {:e} at sample {}, spectra differ by up to {:.2} dB at {:.0} Hz",
+                   output.output, output.residual_db, output.peak_difference, output.peak_sample,
+                   output.spectral_db, output.spectral_frequency)?;
+        }
+        Ok(())
+    }
+}
+
+/// A null test of two plugins.
+pub struct Comparison {
+    a: PluginRef,
+    b: PluginRef,
+    sample_rate: u64,
+    length: usize,
+    controls: Vec<(usize, Data)>,
+    inputs: Option<Vec<Vec<Data>>>,
+    alignment: Alignment,
+}
+
+fn latency(instance: &Instance) -> Data {
+    instance.ports().iter().position(|x| x.is_latency()).map_or(0.0, |port| instance.control(port))
+}
+
+fn power(x: &[Data]) -> f64 {
+    x.iter().map(|&x| (x as f64).powi(2)).sum()
+}
+
+// The lag of b against a that correlates best, within max_lag.
+fn correlation_lag(a: &[Data], b: &[Data], max_lag: usize) -> isize {
+    if a.is_empty() || b.is_empty() {
+        return 0;
+    }
+    let n = (a.len() + b.len()).next_power_of_two();
+    let mut a_re: Vec<f64> = a.iter().map(|&x| x as f64).chain(std::iter::repeat(0.0)).take(n).collect();
+    let mut b_re: Vec<f64> = b.iter().map(|&x| x as f64).chain(std::iter::repeat(0.0)).take(n).collect();
+    let mut a_im = vec![0.0; n];
+    let mut b_im = vec![0.0; n];
+    fft(&mut a_re, &mut a_im);
+    fft(&mut b_re, &mut b_im);
+    // conj(A) * B, whose inverse holds sum(a[i] * b[i + lag]) at index lag modulo n.
+    for i in 0..n {
+        let re = a_re[i] * b_re[i] + a_im[i] * b_im[i];
+        let im = a_re[i] * b_im[i] - a_im[i] * b_re[i];
+        a_re[i] = re;
+        a_im[i] = im;
+    }
+    ifft(&mut a_re, &mut a_im);
+
+    let max_lag = max_lag.min(n / 2 - 1) as isize;
+    (-max_lag..=max_lag)
+        .max_by(|&x, &y| {
+            let at = |lag: isize| a_re[lag.rem_euclid(n as isize) as usize];
+            // Prefer the smaller lag on ties, so silence aligns at 0.
+            at(x).total_cmp(&at(y)).then(y.abs().cmp(&x.abs()))
+        })
+        .unwrap_or(0)
+}
+
+// The average power spectrum of x over Hann windowed frames overlapping by half.
+fn spectrum(x: &[f64], frame: usize) -> Vec<f64> {
+    let window = fft::hann(frame);
+    let mut result = vec![0.0; frame / 2 + 1];
+    let mut start = 0;
+    while start + frame <= x.len() {
+        let mut re: Vec<f64> = x[start..start + frame].iter().zip(window.iter()).map(|(x, w)| x * w).collect();
+        let mut im = vec![0.0; frame];
+        fft(&mut re, &mut im);
+        for (bin, power) in result.iter_mut().enumerate() {
+            *power += re[bin] * re[bin] + im[bin] * im[bin];
+        }
+        start += frame / 2;
+    }
+    result
+}
+
+impl Comparison {
+    /// A comparison at 48 kHz over 65536 samples of white noise, with default controls and no
+    /// alignment.
+    pub fn new(a: PluginRef, b: PluginRef) -> Comparison {
+        Comparison {
+            a,
+            b,
+            sample_rate: 48000,
+            length: 65536,
+            controls: Vec::new(),
+            inputs: None,
+            alignment: Alignment::None,
+        }
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u64) -> Comparison {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// The number of samples rendered. Ignored if ```inputs``` is given.
+    pub fn length(mut self, length: usize) -> Comparison {
+        self.length = length;
+        self
+    }
+
+    /// Sets control inputs of both plugins.
+    pub fn controls(mut self, controls: &[(usize, Data)]) -> Comparison {
+        self.controls = controls.to_vec();
+        self
+    }
+
+    /// Renders these signals, one per audio input, instead of white noise.
+    pub fn inputs(mut self, inputs: Vec<Vec<Data>>) -> Comparison {
+        self.inputs = Some(inputs);
+        self
+    }
+
+    pub fn alignment(mut self, alignment: Alignment) -> Comparison {
+        self.alignment = alignment;
+        self
+    }
+
+    fn render(&self, plugin: PluginRef, inputs: &[Vec<Data>], length: usize) -> (Vec<Vec<Data>>, Data) {
+        let mut instance = plugin.instantiate(self.sample_rate).expect("instantiate returned NULL");
+        for &(port, value) in self.controls.iter() {
+            instance.set_control(port, value).unwrap_or_else(|e| panic!("preset: {}", e));
+        }
+        instance.activate();
+        let outputs = instance.render(inputs, length, []);
+        (outputs, latency(&instance))
+    }
+
+    /**
+     * Renders both plugins and compares their outputs. Outputs without a counterpart are
+     * ignored.
+     *
+     * # Panics
+     * Panics if either plugin cannot be instantiated or a preset sets a port that is not a control
+     * input.
+     */
+    pub fn run(&self) -> Report {
+        let inputs = match self.inputs {
+            Some(ref inputs) => inputs.clone(),
+            None => noise_inputs(self.a, 1, self.length),
+        };
+        let length = inputs.iter().map(|x| x.len()).min().unwrap_or(self.length);
+        let (a, a_latency) = self.render(self.a, &inputs, length);
+        let (b, b_latency) = self.render(self.b, &inputs, length);
+
+        let lag = match self.alignment {
+            Alignment::None => 0,
+            Alignment::Latency => (b_latency - a_latency).round() as isize,
+            Alignment::CrossCorrelation { max_lag } => match (a.first(), b.first()) {
+                (Some(a), Some(b)) => correlation_lag(a, b, max_lag),
+                _ => 0,
+            },
+        }.clamp(-(length as isize), length as isize);
+
+        // The samples of a that have a counterpart in b once b is shifted back by lag.
+        let start = (-lag).max(0) as usize;
+        let end = (length as isize - lag.max(0)).max(start as isize) as usize;
+        let outputs = a
+            .iter()
+            .zip(b.iter())
+            .enumerate()
+            .map(|(output, (a, b))| {
+                let a = &a[start..end];
+                let b = &b[(start as isize + lag) as usize..(end as isize + lag) as usize];
+                let difference: Vec<f64> = a.iter().zip(b.iter()).map(|(&a, &b)| b as f64 - a as f64).collect();
+                let (peak_sample, peak_difference) = difference
+                    .iter()
+                    .map(|x| x.abs())
+                    .enumerate()
+                    .fold((0, 0.0), |max, (i, x)| if x > max.1 || x.is_nan() && !max.1.is_nan() { (i, x) } else { max });
+
+                let frame = SPECTRUM_FRAME.min(1 << a.len().max(1).ilog2());
+                let a_spectrum = spectrum(&a.iter().map(|&x| x as f64).collect::<Vec<_>>(), frame);
+                let b_spectrum = spectrum(&b.iter().map(|&x| x as f64).collect::<Vec<_>>(), frame);
+                let floor = a_spectrum.iter().cloned().fold(0.0, f64::max) * 10f64.powf(-SPECTRUM_RANGE_DB / 10.0);
+                let (spectral_bin, spectral_db) = a_spectrum
+                    .iter()
+                    .zip(b_spectrum.iter())
+                    .enumerate()
+                    .filter(|&(_, (&a, _))| a > floor && a > 0.0)
+                    .map(|(bin, (&a, &b))| (bin, 10.0 * (b / a).log10()))
+                    .fold((0, 0.0), |max: (usize, f64), x| if x.1.abs() > max.1.abs() { x } else { max });
+
+                OutputReport {
+                    output,
+                    residual_db: 10.0 * (difference.iter().map(|x| x * x).sum::<f64>() / power(a)).log10(),
+                    peak_difference: peak_difference as Data,
+                    peak_sample: start + peak_sample,
+                    spectral_db,
+                    spectral_frequency: spectral_bin as f64 * self.sample_rate as f64 / frame as f64,
+                }
+            })
+            .collect();
+        Report { lag, outputs }
+    }
+}
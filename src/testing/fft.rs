@@ -0,0 +1,64 @@
+/*!
+ * A small radix-2 FFT for the analysis tools, so they need no extra dependencies.
+ */
+
+use std::f64::consts::PI;
+
+/// Transforms ```re``` and ```im``` in place. Their length must be a power of two.
+pub fn fft(re: &mut [f64], im: &mut [f64]) {
+    transform(re, im, false);
+}
+
+/// The inverse of ```fft```, including the scaling by 1/n.
+pub fn ifft(re: &mut [f64], im: &mut [f64]) {
+    transform(re, im, true);
+    let n = re.len() as f64;
+    for x in re.iter_mut().chain(im.iter_mut()) {
+        *x /= n;
+    }
+}
+
+fn transform(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n, "FFT length must be a power of two");
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// A Hann window of the given length.
+pub fn hann(length: usize) -> Vec<f64> {
+    (0..length)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f64 / length as f64).cos())
+        .collect()
+}
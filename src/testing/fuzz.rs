@@ -0,0 +1,328 @@
+/*!
+ * Randomised runs of a plugin, for use with ```cargo fuzz``` or as a property test.
+ *
+ * Each run instantiates the plugin and renders a number of blocks. Before every block, control
+ * inputs are set to random values within their resolved bounds, including the bounds themselves,
+ * and each audio input gets silence, DC, a full-scale signal, an impulse or noise. The block size
+ * varies from 0 up to ```MAX_BLOCK_SIZE```. A run fails if user code panics, if an audio output
+ * is NaN or infinite, or if a control output is outside its bounds.
+ *
+ * The choices are read from a byte string, so a fuzzer can steer them, or drawn from a seed:
+ *
+ * ```rust,ignore
+ * fuzz_target!(|data: &[u8]| {
+ *     let plugin = ladspa::host::own_plugins()[0];
+ *     if let Err(failure) = ladspa::testing::fuzz::run(plugin, 44100, data) {
+ *         panic!("{}", failure);
+ *     }
+ * });
+ *
+ * #[test]
+ * fn fuzz() {
+ *     for plugin in ladspa::host::own_plugins() {
+ *         for seed in 0..100 {
+ *             if let Err(failure) = ladspa::testing::fuzz::run_seed(plugin, 44100, seed) {
+ *                 panic!("{} with seed {}: {}", plugin.label(), seed, failure);
+ *             }
+ *         }
+ *     }
+ * }
+ * ```
+ *
+ * Panics are only detected in plugins built from this crate; other libraries are still checked
+ * for their output.
+ *
+ * ```call_sequence``` instead drives the exported C functions in arbitrary order, including
+ * orders the LADSPA specification forbids, to check that host misuse is caught by the wrapper in
+ * ```ffi``` rather than reaching plugin code. Ports are connected to buffers or to NULL, mostly
+ * optional ports for the latter, between runs of varying length. Only use it on plugins built from
+ * this crate; other libraries are under no obligation to survive it.
+ *
+ * The ```fuzz``` directory of this repository holds ```cargo fuzz``` targets for both, run on the
+ * example delay plugin.
+ */
+
+use std::fmt;
+use std::ptr;
+
+use super::{signal, Rng};
+use crate::ffi::{self, ladspa_h};
+use crate::host::{PluginRef, PortInfo};
+use crate::{ControlHint, Data, PortDescriptor};
+
+/// The largest number of blocks rendered in one run.
+pub const MAX_BLOCKS: usize = 16;
+
+/// The largest block size used.
+pub const MAX_BLOCK_SIZE: usize = 8192;
+
+/// The largest number of calls made by ```call_sequence```.
+pub const MAX_CALLS: usize = 64;
+
+/// The size of the buffers ports are connected to by ```call_sequence```, and the largest sample
+/// count it runs.
+pub const CALL_BUFFER_SIZE: usize = 1024;
+
+/// How far below or above its default an unbounded control input may be set.
+pub const UNBOUNDED_RANGE: Data = 1000.0;
+
+/// Why a run failed. ```block``` is the index of the block being rendered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure {
+    /// ```instantiate``` returned NULL.
+    Instantiate,
+    /// User code panicked.
+    Panic { block: usize },
+    /// User code panicked during the given call of ```call_sequence```.
+    PanicInCall { call: usize },
+    /// An audio output was NaN or infinite.
+    NonFinite { block: usize, output: usize, sample: usize, value: Data },
+    /// A control output was outside its bounds, or not a number.
+    ControlOutOfBounds { block: usize, port: usize, name: String, value: Data },
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Instantiate => write!(f, "instantiate returned NULL"),
+            Failure::Panic { block } => write!(f, "the plugin panicked in block {}", block),
+            Failure::PanicInCall { call } => write!(f, "the plugin panicked in call {}", call),
+            Failure::NonFinite { block, output, sample, value } => {
+                write!(f, "output {} is {} at sample {} of block {}", output, value, sample, block)
+            }
+            Failure::ControlOutOfBounds { block, port, ref name, value } => {
+                write!(f, "control output {} ({:?}) is out of bounds in block {}: {}", port, name, block, value)
+            }
+        }
+    }
+}
+
+enum Source<'a> {
+    Bytes(&'a [u8]),
+    Rng(Rng),
+}
+
+impl<'a> Source<'a> {
+    fn exhausted(&self) -> bool {
+        match *self {
+            Source::Bytes(data) => data.is_empty(),
+            Source::Rng(_) => false,
+        }
+    }
+
+    /// The next four bytes, little endian; missing bytes read as 0.
+    fn next_u32(&mut self) -> u32 {
+        match *self {
+            Source::Bytes(ref mut data) => {
+                let n = data.len().min(4);
+                let mut bytes = [0; 4];
+                bytes[..n].copy_from_slice(&data[..n]);
+                *data = &data[n..];
+                u32::from_le_bytes(bytes)
+            }
+            Source::Rng(ref mut rng) => rng.next_u64() as u32,
+        }
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.next_u32() as usize % n
+    }
+
+    fn unit(&mut self) -> Data {
+        (self.next_u32() as f64 / u32::MAX as f64) as Data
+    }
+}
+
+fn control_value(source: &mut Source, port: &PortInfo, sample_rate: u64) -> Data {
+    let hint = port.port.hint.unwrap_or(ControlHint::empty());
+    let default = port.default_value(sample_rate);
+    if hint.contains(ControlHint::HINT_TOGGLED) {
+        return source.below(2) as Data;
+    }
+    let lower = port.port.resolved_lower_bound(sample_rate).unwrap_or(default - UNBOUNDED_RANGE);
+    let upper = port.port.resolved_upper_bound(sample_rate).unwrap_or(default + UNBOUNDED_RANGE);
+    let value = match source.below(4) {
+        0 => lower,
+        1 => upper,
+        2 => default,
+        _ if hint.contains(ControlHint::HINT_LOGARITHMIC) && lower > 0.0 && upper > 0.0 => {
+            lower * (upper / lower).powf(source.unit())
+        }
+        _ => lower + (upper - lower) * source.unit(),
+    };
+    if hint.contains(ControlHint::HINT_INTEGER) {
+        value.round()
+    } else {
+        value
+    }
+}
+
+fn audio_input(source: &mut Source, length: usize, sample_rate: u64) -> Vec<Data> {
+    match source.below(6) {
+        0 => signal::silence(length),
+        1 => signal::dc(length, 2.0 * source.unit() - 1.0),
+        2 => signal::nyquist(length),
+        3 => signal::impulse(length),
+        4 => signal::sine(length, source.unit() as f64 * sample_rate as f64 / 2.0, sample_rate, 1.0),
+        _ => signal::noise(length, source.next_u32() as u64, 1.0),
+    }
+}
+
+fn block_size(source: &mut Source) -> usize {
+    if source.below(4) == 0 {
+        source.below(17)
+    } else {
+        source.below(MAX_BLOCK_SIZE + 1)
+    }
+}
+
+fn fuzz(plugin: PluginRef, sample_rate: u64, mut source: Source) -> Result<(), Failure> {
+    let panics = ffi::panic_count();
+    let mut instance = plugin.instantiate(sample_rate).ok_or(Failure::Instantiate)?;
+    instance.activate();
+    if ffi::panic_count() != panics {
+        return Err(Failure::Panic { block: 0 });
+    }
+
+    let ports = instance.ports().to_vec();
+    let inputs = plugin.ports_of(PortDescriptor::AudioInput).len();
+    let outputs = plugin.ports_of(PortDescriptor::AudioOutput).len();
+    let blocks = 1 + source.below(MAX_BLOCKS);
+    for block in 0..blocks {
+        if block > 0 && source.exhausted() {
+            break;
+        }
+        for (i, port) in ports.iter().enumerate() {
+            if port.port.desc == PortDescriptor::ControlInput {
+                let value = control_value(&mut source, port, sample_rate);
+                instance.write_control(i, value);
+            }
+        }
+        let length = block_size(&mut source);
+        let input_buffers: Vec<Vec<Data>> = (0..inputs).map(|_| audio_input(&mut source, length, sample_rate)).collect();
+        let mut output_buffers = vec![vec![0.0; length]; outputs];
+
+        let panics = ffi::panic_count();
+        let input_refs: Vec<&[Data]> = input_buffers.iter().map(|x| &x[..]).collect();
+        let mut output_refs: Vec<&mut [Data]> = output_buffers.iter_mut().map(|x| &mut x[..]).collect();
+        instance.run(&input_refs, &mut output_refs);
+        if ffi::panic_count() != panics {
+            return Err(Failure::Panic { block });
+        }
+
+        for (output, buffer) in output_buffers.iter().enumerate() {
+            if let Some((sample, &value)) = buffer.iter().enumerate().find(|(_, x)| !x.is_finite()) {
+                return Err(Failure::NonFinite { block, output, sample, value });
+            }
+        }
+        for (i, port) in ports.iter().enumerate() {
+            if port.port.desc != PortDescriptor::ControlOutput {
+                continue;
+            }
+            let value = instance.control(i);
+            let lower = port.port.resolved_lower_bound(sample_rate).unwrap_or(Data::NEG_INFINITY);
+            let upper = port.port.resolved_upper_bound(sample_rate).unwrap_or(Data::INFINITY);
+            if !(lower..=upper).contains(&value) {
+                return Err(Failure::ControlOutOfBounds { block, port: i, name: port.name.clone(), value });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the plugin with the choices read from ```data```.
+pub fn run(plugin: PluginRef, sample_rate: u64, data: &[u8]) -> Result<(), Failure> {
+    fuzz(plugin, sample_rate, Source::Bytes(data))
+}
+
+/// Runs the plugin with the choices drawn from ```seed```.
+pub fn run_seed(plugin: PluginRef, sample_rate: u64, seed: u64) -> Result<(), Failure> {
+    fuzz(plugin, sample_rate, Source::Rng(Rng::new(seed)))
+}
+
+// A run length for call_sequence, often short so that blocks of varying size follow each other.
+fn call_run_size(source: &mut Source) -> usize {
+    if source.below(2) == 0 {
+        source.below(17)
+    } else {
+        source.below(CALL_BUFFER_SIZE + 1)
+    }
+}
+
+fn calls(plugin: PluginRef, sample_rate: u64, mut source: Source) -> Result<(), Failure> {
+    let desc = plugin.0;
+    let port_count = plugin.port_count();
+    let optional: Vec<usize> = (0..port_count).filter(|&x| plugin.ports()[x].port.optional).collect();
+    // One buffer per port and one for out of range port numbers, so no two ports alias.
+    let mut buffers = vec![vec![0.0; CALL_BUFFER_SIZE]; port_count + 1];
+    let mut handle: ladspa_h::Handle = ptr::null_mut();
+    let panics = ffi::panic_count();
+    let calls = 1 + source.below(MAX_CALLS);
+    for call in 0..calls {
+        if call > 0 && source.exhausted() {
+            break;
+        }
+        unsafe {
+            if handle.is_null() {
+                handle = desc.instantiate.ok_or(Failure::Instantiate)?(desc, sample_rate as _);
+                if handle.is_null() {
+                    return Err(Failure::Instantiate);
+                }
+                continue;
+            }
+            match source.below(9) {
+                0 | 1 => if let Some(connect_port) = desc.connect_port {
+                    let port = source.below(port_count + 2);
+                    connect_port(handle, port as _, buffers[port.min(port_count)].as_mut_ptr());
+                },
+                // Usually an optional port, but NULL for any other port must be caught too.
+                2 => if let Some(connect_port) = desc.connect_port && port_count > 0 {
+                    let port = match source.below(4) {
+                        0 => source.below(port_count),
+                        _ if optional.is_empty() => source.below(port_count),
+                        _ => optional[source.below(optional.len())],
+                    };
+                    connect_port(handle, port as _, ptr::null_mut());
+                },
+                3 => if let Some(activate) = desc.activate {
+                    activate(handle);
+                },
+                4 => if let Some(deactivate) = desc.deactivate {
+                    deactivate(handle);
+                },
+                5 => if let Some(run) = desc.run {
+                    run(handle, call_run_size(&mut source) as _);
+                },
+                6 => if let Some(run_adding) = desc.run_adding {
+                    run_adding(handle, call_run_size(&mut source) as _);
+                },
+                7 => if let Some(set_run_adding_gain) = desc.set_run_adding_gain {
+                    set_run_adding_gain(handle, source.unit());
+                },
+                _ => {
+                    if let Some(cleanup) = desc.cleanup {
+                        cleanup(handle);
+                    }
+                    handle = ptr::null_mut();
+                }
+            }
+        }
+        if ffi::panic_count() != panics {
+            return Err(Failure::PanicInCall { call });
+        }
+    }
+    if !handle.is_null() && let Some(cleanup) = desc.cleanup {
+        unsafe { cleanup(handle) };
+    }
+    Ok(())
+}
+
+/// Drives the plugin's C functions with the call sequence read from ```data```.
+pub fn call_sequence(plugin: PluginRef, sample_rate: u64, data: &[u8]) -> Result<(), Failure> {
+    calls(plugin, sample_rate, Source::Bytes(data))
+}
+
+/// Drives the plugin's C functions with a call sequence drawn from ```seed```.
+pub fn call_sequence_seed(plugin: PluginRef, sample_rate: u64, seed: u64) -> Result<(), Failure> {
+    calls(plugin, sample_rate, Source::Rng(Rng::new(seed)))
+}
@@ -0,0 +1,249 @@
+/*!
+ * Golden-output regression tests: renders a plugin with a fixed set of inputs and compares the
+ * output with reference WAV files kept in the plugin crate.
+ *
+ * ```rust,ignore
+ * #[test]
+ * fn golden() {
+ *     let plugin = ladspa::host::own_plugins()[0];
+ *     let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+ *     let result = Golden::new(plugin, &dir).preset("wet", &[(6, 1.0)]).tolerance(1e-6).check();
+ *     if let Err(failure) = result {
+ *         panic!("{}", failure);
+ *     }
+ * }
+ * ```
+ *
+ * The references are ```<label>-<preset>-<input>.wav``` for every input. A missing reference
+ * fails the check; run the tests once with ```LADSPA_RS_UPDATE_GOLDEN``` set to write them, and
+ * commit them with the crate. Setting it again rewrites them after an intended change in output.
+ */
+
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{first_difference, signal, Difference};
+use crate::host::wav::Wav;
+use crate::host::PluginRef;
+use crate::{Data, PortDescriptor};
+
+/// The environment variable that makes ```Golden::check``` rewrite the references.
+pub const UPDATE_VAR: &str = "LADSPA_RS_UPDATE_GOLDEN";
+
+/// The block size the inputs are rendered with.
+pub const BLOCK_SIZE: usize = 256;
+
+/// The seed of the noise input.
+pub const NOISE_SEED: u64 = 1;
+
+/// A fixed input, fed to every audio input of the plugin.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Impulse,
+    /// An exponential sine sweep from 20 Hz to just below Nyquist, at -6 dBFS.
+    Sweep,
+    /// Seeded white noise at -6 dBFS.
+    Noise,
+}
+
+impl Input {
+    pub const ALL: [Input; 3] = [Input::Impulse, Input::Sweep, Input::Noise];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Input::Impulse => "impulse",
+            Input::Sweep => "sweep",
+            Input::Noise => "noise",
+        }
+    }
+
+    pub fn signal(&self, length: usize, sample_rate: u64) -> Vec<Data> {
+        match *self {
+            Input::Impulse => signal::impulse(length),
+            Input::Sweep => signal::sweep(length, 20.0, 0.45 * sample_rate as f64, sample_rate, 0.5),
+            Input::Noise => signal::noise(length, NOISE_SEED, 0.5),
+        }
+    }
+}
+
+/// How the output for one input differs from its reference.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diff {
+    pub input: Input,
+    pub reference: PathBuf,
+    /// The largest absolute difference over all outputs.
+    pub max_error: Data,
+    /// The first sample differing by more than the tolerance.
+    pub first: Difference,
+    /// The RMS of the difference relative to the RMS of the reference, in dB; infinite if the
+    /// reference is silent.
+    pub error_db: f64,
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} differs from {}: max error {:e}, error {:.1} dB, first: {}",
+               self.input.name(), self.reference.display(), self.max_error, self.error_db, self.first)
+    }
+}
+
+/// Why ```Golden::check``` failed.
+#[derive(Debug)]
+pub enum Failure {
+    /// A reference could not be read or written.
+    Io { reference: PathBuf, error: io::Error },
+    /// A reference does not exist and ```UPDATE_VAR``` is not set.
+    Missing { reference: PathBuf },
+    /// A reference has a different number of outputs, samples or sample rate than the render.
+    Shape { reference: PathBuf, expected: String, actual: String },
+    Mismatch(Diff),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Failure::Io { ref reference, ref error } => write!(f, "{}: {}", reference.display(), error),
+            Failure::Missing { ref reference } => {
+                write!(f, "{} does not exist; set {} to write it", reference.display(), UPDATE_VAR)
+            }
+            Failure::Shape { ref reference, ref expected, ref actual } => {
+                write!(f, "{} holds {}, but the render is {}", reference.display(), expected, actual)
+            }
+            Failure::Mismatch(ref diff) => diff.fmt(f),
+        }
+    }
+}
+
+fn shape(wav: &Wav) -> String {
+    format!("{} outputs of {} samples at {} Hz", wav.channels.len(), wav.frames(), wav.sample_rate)
+}
+
+fn error_db(expected: &[Vec<Data>], actual: &[Vec<Data>]) -> f64 {
+    let mut error = 0.0;
+    let mut signal = 0.0;
+    for (a, b) in expected.iter().zip(actual.iter()) {
+        for (&a, &b) in a.iter().zip(b.iter()) {
+            error += ((a - b) as f64).powi(2);
+            signal += (a as f64).powi(2);
+        }
+    }
+    if signal == 0.0 {
+        return if error == 0.0 { f64::NEG_INFINITY } else { f64::INFINITY };
+    }
+    10.0 * (error / signal).log10()
+}
+
+/// A golden-output test of one plugin with one control preset.
+pub struct Golden {
+    plugin: PluginRef,
+    dir: PathBuf,
+    sample_rate: u64,
+    length: usize,
+    preset: String,
+    controls: Vec<(usize, Data)>,
+    tolerance: Data,
+}
+
+impl Golden {
+    /// A test at 48 kHz over 16384 samples with default controls, matching exactly, whose
+    /// references are kept in ```dir```.
+    pub fn new(plugin: PluginRef, dir: &Path) -> Golden {
+        Golden {
+            plugin,
+            dir: dir.to_path_buf(),
+            sample_rate: 48000,
+            length: 16384,
+            preset: "default".to_string(),
+            controls: Vec::new(),
+            tolerance: 0.0,
+        }
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u64) -> Golden {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// The number of samples rendered per input.
+    pub fn length(mut self, length: usize) -> Golden {
+        self.length = length;
+        self
+    }
+
+    /// Sets control inputs before rendering. The name is part of the reference file names.
+    pub fn preset(mut self, name: &str, controls: &[(usize, Data)]) -> Golden {
+        self.preset = name.to_string();
+        self.controls = controls.to_vec();
+        self
+    }
+
+    /// The largest difference per sample that is still a match.
+    pub fn tolerance(mut self, tolerance: Data) -> Golden {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// The reference file for an input.
+    pub fn reference(&self, input: Input) -> PathBuf {
+        self.dir.join(format!("{}-{}-{}.wav", self.plugin.label(), self.preset, input.name()))
+    }
+
+    /**
+     * Renders an input with a fresh instance, one buffer per audio output.
+     *
+     * # Panics
+     * Panics if the plugin cannot be instantiated or a preset sets a port that is not a control input.
+     */
+    pub fn render(&self, input: Input) -> Vec<Vec<Data>> {
+        let mut instance = self.plugin.instantiate(self.sample_rate).expect("instantiate returned NULL");
+        for &(port, value) in self.controls.iter() {
+            instance.set_control(port, value).unwrap_or_else(|e| panic!("preset: {}", e));
+        }
+        instance.activate();
+        let signal = input.signal(self.length, self.sample_rate);
+        let inputs = vec![signal; self.plugin.ports_of(PortDescriptor::AudioInput).len()];
+        instance.render(&inputs, self.length, std::iter::repeat(BLOCK_SIZE))
+    }
+
+    /**
+     * Renders every input and compares it with its reference, or writes all of them if
+     * ```LADSPA_RS_UPDATE_GOLDEN``` is set. Stops at the first failure.
+     */
+    pub fn check(&self) -> Result<(), Failure> {
+        let update = env::var_os(UPDATE_VAR).is_some_and(|x| !x.is_empty());
+        for input in Input::ALL {
+            let reference = self.reference(input);
+            let actual = Wav { sample_rate: self.sample_rate as u32, channels: self.render(input) };
+            if update {
+                actual.write(&reference).map_err(|error| Failure::Io { reference: reference.clone(), error })?;
+                continue;
+            }
+            if !reference.exists() {
+                return Err(Failure::Missing { reference });
+            }
+
+            let expected = Wav::read(&reference).map_err(|error| Failure::Io { reference: reference.clone(), error })?;
+            if expected.sample_rate != actual.sample_rate
+                || expected.channels.len() != actual.channels.len()
+                || expected.frames() != actual.frames() {
+                return Err(Failure::Shape { reference, expected: shape(&expected), actual: shape(&actual) });
+            }
+            if let Some(first) = first_difference(&expected.channels, &actual.channels, self.tolerance) {
+                let max_error = expected.channels.iter().flatten()
+                    .zip(actual.channels.iter().flatten())
+                    .map(|(a, b)| if a.is_nan() || b.is_nan() { Data::NAN } else { (a - b).abs() })
+                    .fold(0.0, |max: Data, x| if x.is_nan() || max.is_nan() { Data::NAN } else { max.max(x) });
+                return Err(Failure::Mismatch(Diff {
+                    input,
+                    reference,
+                    max_error,
+                    first,
+                    error_db: error_db(&expected.channels, &actual.channels),
+                }));
+            }
+        }
+        Ok(())
+    }
+}
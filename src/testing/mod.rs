@@ -0,0 +1,165 @@
+/*!
+ * Tools for testing LADSPA plugins through their C interface.
+ *
+ * The checks run plugins through ```host```, so they apply equally to this library's own plugins,
+ * including the wrapper in ```ffi```, and to third-party libraries opened with
+ * ```host::Library::open```. A plugin crate typically calls them from its tests:
+ *
+ * ```rust,ignore
+ * #[test]
+ * fn conformance() {
+ *     for report in ladspa::testing::validate::validate_own(44100) {
+ *         assert!(report.passed(), "{}", report);
+ *     }
+ * }
+ * ```
+ */
+
+use std::fmt;
+
+use crate::ffi;
+use crate::host::PluginRef;
+use crate::{Data, PluginDescriptor, PortDescriptor};
+
+pub mod bench;
+pub mod blocksize;
+pub mod compare;
+pub mod fft;
+pub mod fuzz;
+pub mod golden;
+pub mod realtime;
+pub mod reset;
+pub mod response;
+pub mod validate;
+
+/**
+ * A C descriptor for a plugin that ```get_ladspa_descriptor``` does not return, so that tests
+ * can run plugins written for them through ```host``` like the exported ones. The descriptor is
+ * never freed.
+ */
+pub fn plugin_ref(plugin: PluginDescriptor) -> PluginRef {
+    PluginRef(unsafe { &*ffi::new_descriptor(plugin) })
+}
+
+/// Independent white noise at half scale for every audio input of ```plugin```.
+pub fn noise_inputs(plugin: PluginRef, seed: u64, length: usize) -> Vec<Vec<Data>> {
+    (0..plugin.ports_of(PortDescriptor::AudioInput).len())
+        .map(|i| signal::noise(length, seed.wrapping_add(i as u64), 0.5))
+        .collect()
+}
+
+/// The first sample at which two renders differ.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub output: usize,
+    pub sample: usize,
+    pub expected: Data,
+    pub actual: Data,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "output {} differs at sample {}: expected {:?}, got {:?}",
+               self.output, self.sample, self.expected, self.actual)
+    }
+}
+
+/**
+ * Finds the first sample where two renders, one buffer per output, differ by more than
+ * ```tolerance```. A NaN only matches the identical NaN.
+ */
+pub fn first_difference(expected: &[Vec<Data>], actual: &[Vec<Data>], tolerance: Data) -> Option<Difference> {
+    for (output, (a, b)) in expected.iter().zip(actual.iter()).enumerate() {
+        for (sample, (&expected, &actual)) in a.iter().zip(b.iter()).enumerate() {
+            if expected.to_bits() != actual.to_bits() && ((expected - actual).abs() > tolerance || expected.is_nan() || actual.is_nan()) {
+                return Some(Difference { output, sample, expected, actual });
+            }
+        }
+    }
+    None
+}
+
+/// A small deterministic pseudo random generator, so test signals are reproducible without
+/// extra dependencies.
+#[derive(Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// A uniformly distributed value in ```[0, 1)```.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A uniformly distributed value in ```[0, n)```.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+}
+
+/// Test signals.
+pub mod signal {
+    use super::Rng;
+    use crate::Data;
+
+    pub fn silence(length: usize) -> Vec<Data> {
+        vec![0.0; length]
+    }
+
+    pub fn dc(length: usize, value: Data) -> Vec<Data> {
+        vec![value; length]
+    }
+
+    /// A unit impulse at the first sample.
+    pub fn impulse(length: usize) -> Vec<Data> {
+        let mut signal = silence(length);
+        if let Some(first) = signal.first_mut() {
+            *first = 1.0;
+        }
+        signal
+    }
+
+    pub fn sine(length: usize, frequency: f64, sample_rate: u64, amplitude: Data) -> Vec<Data> {
+        (0..length)
+            .map(|i| {
+                let phase = 2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate as f64;
+                amplitude * phase.sin() as Data
+            })
+            .collect()
+    }
+
+    /// An exponential sine sweep from ```start``` to ```end``` Hz over the whole length.
+    pub fn sweep(length: usize, start: f64, end: f64, sample_rate: u64, amplitude: Data) -> Vec<Data> {
+        let duration = length as f64 / sample_rate as f64;
+        let rate = (end / start).ln() / duration;
+        (0..length)
+            .map(|i| {
+                let t = i as f64 / sample_rate as f64;
+                let phase = 2.0 * std::f64::consts::PI * start * ((rate * t).exp() - 1.0) / rate;
+                amplitude * phase.sin() as Data
+            })
+            .collect()
+    }
+
+    /// Uniform white noise in ```[-amplitude, amplitude)```.
+    pub fn noise(length: usize, seed: u64, amplitude: Data) -> Vec<Data> {
+        let mut rng = Rng::new(seed);
+        (0..length).map(|_| amplitude * (2.0 * rng.next_f64() - 1.0) as Data).collect()
+    }
+
+    /// Full-scale samples alternating between 1 and -1, the highest frequency there is.
+    pub fn nyquist(length: usize) -> Vec<Data> {
+        (0..length).map(|i| if i % 2 == 0 { 1.0 } else { -1.0 }).collect()
+    }
+}
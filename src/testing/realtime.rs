@@ -0,0 +1,162 @@
+/*!
+ * Detects memory allocation in realtime callbacks.
+ *
+ * With the ```testing``` feature, the wrapper in ```ffi``` marks the thread as being in a realtime
+ * callback while plugin code runs for ```run```, ```run_adding``` and ```run_synth```. When
+ * ```CheckedAlloc``` is the global allocator, every allocation, reallocation or deallocation made
+ * while the mark is set is recorded with a backtrace:
+ *
+ * ```rust,ignore
+ * #[global_allocator]
+ * static ALLOC: ladspa::testing::realtime::CheckedAlloc = ladspa::testing::realtime::CheckedAlloc::new();
+ *
+ * #[test]
+ * fn no_allocation() {
+ *     let plugin = ladspa::host::own_plugins()[0];
+ *     let mut instance = plugin.instantiate(44100).unwrap();
+ *     instance.activate();
+ *     ladspa::testing::realtime::assert_no_allocations(|| {
+ *         instance.render(&[ladspa::testing::signal::noise(4096, 1, 0.5)], 4096, [256; 16])
+ *     });
+ * }
+ * ```
+ *
+ * Only plugins linked into the test binary are checked, since libraries loaded with
+ * ```Library::open``` neither set the mark nor use this allocator. Blocking on locks is not
+ * detected.
+ */
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+thread_local! {
+    static REALTIME: Cell<bool> = const { Cell::new(false) };
+    static VIOLATIONS: RefCell<Vec<Violation>> = const { RefCell::new(Vec::new()) };
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Alloc,
+    Realloc,
+    Dealloc,
+}
+
+/// A memory operation made in a realtime callback.
+#[derive(Debug)]
+pub struct Violation {
+    pub operation: Operation,
+    pub size: usize,
+    pub backtrace: Backtrace,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operation = match self.operation {
+            Operation::Alloc => "allocation",
+            Operation::Realloc => "reallocation",
+            Operation::Dealloc => "deallocation",
+        };
+        write!(f, "{} of {} bytes in a realtime callback\n{}", operation, self.size, self.backtrace)
+    }
+}
+
+/// Marks the current thread as being in a realtime callback until dropped.
+pub(crate) struct Realtime {
+    previous: bool,
+}
+
+impl Realtime {
+    pub(crate) fn enter() -> Realtime {
+        Realtime { previous: REALTIME.with(|flag| flag.replace(true)) }
+    }
+}
+
+impl Drop for Realtime {
+    fn drop(&mut self) {
+        REALTIME.with(|flag| flag.set(self.previous));
+    }
+}
+
+fn note(operation: Operation, size: usize) {
+    INSTALLED.store(true, Ordering::Relaxed);
+    let _ = REALTIME.try_with(|flag| {
+        if flag.get() {
+            // Recording allocates too, so leave realtime mode while doing it.
+            flag.set(false);
+            let violation = Violation { operation, size, backtrace: Backtrace::force_capture() };
+            let _ = VIOLATIONS.try_with(|violations| violations.borrow_mut().push(violation));
+            flag.set(true);
+        }
+    });
+}
+
+/// A global allocator that records memory operations made in realtime callbacks.
+pub struct CheckedAlloc;
+
+impl CheckedAlloc {
+    pub const fn new() -> CheckedAlloc {
+        CheckedAlloc
+    }
+}
+
+impl Default for CheckedAlloc {
+    fn default() -> CheckedAlloc {
+        CheckedAlloc::new()
+    }
+}
+
+unsafe impl GlobalAlloc for CheckedAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        note(Operation::Alloc, layout.size());
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        note(Operation::Alloc, layout.size());
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        note(Operation::Realloc, new_size);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        note(Operation::Dealloc, layout.size());
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Whether ```CheckedAlloc``` is the global allocator, so that violations can be detected.
+pub fn installed() -> bool {
+    // Make sure at least one allocation has gone through the allocator.
+    drop(std::hint::black_box(Box::new(0u8)));
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+/// Returns and clears the violations recorded on the current thread.
+pub fn take_violations() -> Vec<Violation> {
+    VIOLATIONS.with(|violations| violations.take())
+}
+
+/**
+ * Runs ```f``` and panics, showing the backtrace, if a realtime callback allocated or freed
+ * memory on the current thread meanwhile.
+ *
+ * # Panics
+ * Panics if ```CheckedAlloc``` is not the global allocator.
+ */
+pub fn assert_no_allocations<R, F: FnOnce() -> R>(f: F) -> R {
+    assert!(installed(), "CheckedAlloc is not the global allocator");
+    take_violations();
+    let result = f();
+    if let Some(violation) = take_violations().first() {
+        panic!("{}", violation);
+    }
+    result
+}
@@ -0,0 +1,48 @@
+/*!
+ * Checks that ```deactivate``` followed by ```activate``` resets a plugin.
+ *
+ * Hosts reset plugins this way, for example when the transport stops, and expect no audio from
+ * before the reset to reach the output afterwards. A plugin that keeps state across the reset,
+ * typically because ```Plugin::activate``` does not clear it, renders differently from a freshly
+ * instantiated one.
+ */
+
+use super::{first_difference, noise_inputs, Difference};
+use crate::host::PluginRef;
+use crate::Data;
+
+/**
+ * Renders ```length``` samples of ```inputs```, one buffer per audio input, resets the instance
+ * and renders them again. Reports the first sample of the second render that differs by more
+ * than ```tolerance``` from the render of a fresh instance.
+ *
+ * # Panics
+ * Panics if the plugin cannot be instantiated.
+ */
+pub fn check(plugin: PluginRef,
+             sample_rate: u64,
+             inputs: &[Vec<Data>],
+             length: usize,
+             tolerance: Data)
+             -> Result<(), Difference> {
+    let mut fresh = plugin.instantiate(sample_rate).expect("instantiate returned NULL");
+    fresh.activate();
+    let expected = fresh.render(inputs, length, []);
+
+    let mut reset = plugin.instantiate(sample_rate).expect("instantiate returned NULL");
+    reset.activate();
+    reset.render(inputs, length, []);
+    reset.deactivate();
+    reset.activate();
+    let actual = reset.render(inputs, length, []);
+
+    match first_difference(&expected, &actual, tolerance) {
+        Some(difference) => Err(difference),
+        None => Ok(()),
+    }
+}
+
+/// Runs ```check``` with white noise on every audio input.
+pub fn check_noise(plugin: PluginRef, sample_rate: u64, length: usize, tolerance: Data) -> Result<(), Difference> {
+    check(plugin, sample_rate, &noise_inputs(plugin, 1, length), length, tolerance)
+}
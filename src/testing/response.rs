@@ -0,0 +1,392 @@
+/*!
+ * Measures the frequency, phase and impulse response of a plugin, and its harmonic distortion at
+ * chosen frequencies, for checking the curves of equalisers and filters.
+ *
+ * ```rust,ignore
+ * let plugin = ladspa::host::own_plugins()[0];
+ * let response = Analysis::new(plugin)
+ *     .excitation(Excitation::Sweep)
+ *     .controls(&[(2, 1000.0)])
+ *     .thd_at(&[100.0, 1000.0])
+ *     .run();
+ * assert!((response.at(1000.0).magnitude_db + 3.0).abs() < 0.1);
+ * response.write_csv(Path::new("target/lowpass.csv"))?;
+ * ```
+ *
+ * Every audio input receives the excitation and one audio output is analysed. The response is
+ * the output spectrum divided by the excitation spectrum, so it includes the plugin's latency,
+ * and is only meaningful for plugins that are close to linear. A sweep keeps more energy away
+ * from the noise floor than an impulse, but only covers ```SWEEP_START``` to ```SWEEP_END```
+ * times the sample rate.
+ */
+
+use std::f64::consts::PI;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::fft::{self, fft, ifft};
+use super::signal;
+use crate::host::PluginRef;
+use crate::{ControlHint, Data, PortDescriptor};
+
+/// The lowest frequency of the sweep, in Hz.
+pub const SWEEP_START: f64 = 20.0;
+
+/// The highest frequency of the sweep, as a fraction of the sample rate.
+pub const SWEEP_END: f64 = 0.45;
+
+/// The amplitude of the sweep and of the sines THD is measured with.
+pub const AMPLITUDE: Data = 0.5;
+
+/// The highest harmonic included in THD.
+pub const MAX_HARMONIC: usize = 10;
+
+// How many bins on either side of a harmonic count towards it, given the Hann window.
+const HARMONIC_WIDTH: usize = 3;
+
+// Bins where the excitation is this far below its peak power are not divided by.
+const REGULARISATION: f64 = 1e-8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Excitation {
+    Impulse,
+    /// An exponential sine sweep, deconvolved from the output.
+    Sweep,
+}
+
+/// The response at one frequency.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Point {
+    pub frequency: f64,
+    pub magnitude_db: f64,
+    /// The unwrapped phase in radians.
+    pub phase: f64,
+    /// The group delay in seconds.
+    pub group_delay: f64,
+}
+
+/// Total harmonic distortion at one frequency.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Thd {
+    pub frequency: f64,
+    /// The RMS of harmonics 2 to ```MAX_HARMONIC``` relative to the fundamental; 0 if no
+    /// harmonic is below Nyquist.
+    pub ratio: f64,
+}
+
+impl Thd {
+    pub fn db(&self) -> f64 {
+        20.0 * self.ratio.log10()
+    }
+}
+
+/// The result of an ```Analysis```.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub sample_rate: u64,
+    /// The response at every analysed frequency, in ascending order.
+    pub points: Vec<Point>,
+    pub impulse: Vec<Data>,
+    pub thd: Vec<Thd>,
+}
+
+impl Response {
+    /**
+     * The response at ```frequency```, interpolated linearly between the nearest points.
+     *
+     * # Panics
+     * Panics if there are no points.
+     */
+    pub fn at(&self, frequency: f64) -> Point {
+        let i = self.points.partition_point(|x| x.frequency < frequency);
+        if i == 0 || i == self.points.len() {
+            return self.points[i.min(self.points.len() - 1)];
+        }
+        let (a, b) = (self.points[i - 1], self.points[i]);
+        let t = (frequency - a.frequency) / (b.frequency - a.frequency);
+        let mix = |x: f64, y: f64| x + (y - x) * t;
+        Point {
+            frequency,
+            magnitude_db: mix(a.magnitude_db, b.magnitude_db),
+            phase: mix(a.phase, b.phase),
+            group_delay: mix(a.group_delay, b.group_delay),
+        }
+    }
+
+    /// The frequency response as CSV, one line per point.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frequency,magnitude_db,phase,group_delay\n");
+        for point in self.points.iter() {
+            writeln!(csv, "{},{},{},{}", point.frequency, point.magnitude_db, point.phase, point.group_delay).unwrap();
+        }
+        csv
+    }
+
+    /// The harmonic distortion as CSV, one line per frequency.
+    pub fn thd_csv(&self) -> String {
+        let mut csv = String::from("frequency,thd,thd_db\n");
+        for thd in self.thd.iter() {
+            writeln!(csv, "{},{},{}", thd.frequency, thd.ratio, thd.db()).unwrap();
+        }
+        csv
+    }
+
+    /// Writes ```to_csv``` to ```path```, and ```thd_csv``` next to it with ```-thd``` added to
+    /// the file name if distortion was measured.
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_csv())?;
+        if !self.thd.is_empty() {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            fs::write(path.with_file_name(format!("{}-thd.csv", stem)), self.thd_csv())?;
+        }
+        Ok(())
+    }
+}
+
+/// The responses of ```Analysis::sweep_control``` as CSV, with the control value first.
+pub fn sweep_csv(responses: &[(Data, Response)]) -> String {
+    let mut csv = String::from("control,frequency,magnitude_db,phase,group_delay\n");
+    for (value, response) in responses.iter() {
+        for point in response.points.iter() {
+            writeln!(csv, "{},{},{},{},{}",
+                     value, point.frequency, point.magnitude_db, point.phase, point.group_delay).unwrap();
+        }
+    }
+    csv
+}
+
+/// A measurement of one audio output of a plugin.
+pub struct Analysis {
+    plugin: PluginRef,
+    sample_rate: u64,
+    length: usize,
+    output: usize,
+    controls: Vec<(usize, Data)>,
+    excitation: Excitation,
+    thd_frequencies: Vec<f64>,
+}
+
+fn spectrum(signal: &[Data], n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut re: Vec<f64> = signal.iter().map(|&x| x as f64).chain(std::iter::repeat(0.0)).take(n).collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    (re, im)
+}
+
+impl Analysis {
+    /// An analysis of the first output at 48 kHz by impulse, with a response of 65536 samples.
+    pub fn new(plugin: PluginRef) -> Analysis {
+        Analysis {
+            plugin,
+            sample_rate: 48000,
+            length: 65536,
+            output: 0,
+            controls: Vec::new(),
+            excitation: Excitation::Impulse,
+            thd_frequencies: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u64) -> Analysis {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// The length of the excitation and the impulse response, rounded up to a power of two.
+    pub fn length(mut self, length: usize) -> Analysis {
+        self.length = length.max(2).next_power_of_two();
+        self
+    }
+
+    /// The index of the audio output to analyse, counting audio outputs only.
+    pub fn output(mut self, output: usize) -> Analysis {
+        self.output = output;
+        self
+    }
+
+    pub fn controls(mut self, controls: &[(usize, Data)]) -> Analysis {
+        self.controls = controls.to_vec();
+        self
+    }
+
+    pub fn excitation(mut self, excitation: Excitation) -> Analysis {
+        self.excitation = excitation;
+        self
+    }
+
+    /// Measures THD with sines at these frequencies. Frequencies that are not between 0 and
+    /// Nyquist are skipped.
+    pub fn thd_at(mut self, frequencies: &[f64]) -> Analysis {
+        self.thd_frequencies = frequencies.to_vec();
+        self
+    }
+
+    // Renders the signal, followed by as much silence, through a fresh instance.
+    fn render(&self, controls: &[(usize, Data)], signal: &[Data]) -> Vec<Data> {
+        let mut instance = self.plugin.instantiate(self.sample_rate).expect("instantiate returned NULL");
+        for &(port, value) in controls.iter() {
+            instance.set_control(port, value).unwrap_or_else(|e| panic!("controls: {}", e));
+        }
+        instance.activate();
+        let length = 2 * signal.len();
+        let mut input = signal.to_vec();
+        input.resize(length, 0.0);
+        let inputs = vec![input; self.plugin.ports_of(PortDescriptor::AudioInput).len()];
+        instance.render(&inputs, length, []).swap_remove(self.output)
+    }
+
+    fn frequency_response(&self, controls: &[(usize, Data)]) -> (Vec<Point>, Vec<Data>) {
+        let sample_rate = self.sample_rate as f64;
+        let excitation = match self.excitation {
+            Excitation::Impulse => signal::impulse(self.length),
+            Excitation::Sweep => signal::sweep(self.length, SWEEP_START, SWEEP_END * sample_rate, self.sample_rate, AMPLITUDE),
+        };
+        let output = self.render(controls, &excitation);
+        let n = output.len();
+        let (x_re, x_im) = spectrum(&excitation, n);
+        let (y_re, y_im) = spectrum(&output, n);
+
+        // Y / X, regularised where X has next to no energy.
+        let x_power: Vec<f64> = x_re.iter().zip(x_im.iter()).map(|(re, im)| re * re + im * im).collect();
+        let floor = x_power.iter().cloned().fold(0.0, f64::max) * REGULARISATION;
+        let mut h_re = vec![0.0; n];
+        let mut h_im = vec![0.0; n];
+        for i in 0..n {
+            let power = x_power[i] + floor;
+            h_re[i] = (y_re[i] * x_re[i] + y_im[i] * x_im[i]) / power;
+            h_im[i] = (y_im[i] * x_re[i] - y_re[i] * x_im[i]) / power;
+        }
+
+        let (lowest, highest) = match self.excitation {
+            Excitation::Impulse => (0.0, sample_rate / 2.0),
+            Excitation::Sweep => (SWEEP_START, SWEEP_END * sample_rate),
+        };
+        let mut points: Vec<Point> = Vec::new();
+        let mut previous_phase = 0.0;
+        let mut unwrap = 0.0;
+        for bin in 1..=n / 2 {
+            let phase = h_im[bin].atan2(h_re[bin]);
+            let step = phase - previous_phase;
+            unwrap -= 2.0 * PI * (step / (2.0 * PI)).round();
+            previous_phase = phase;
+            points.push(Point {
+                frequency: bin as f64 * sample_rate / n as f64,
+                magnitude_db: 10.0 * (h_re[bin] * h_re[bin] + h_im[bin] * h_im[bin]).log10(),
+                phase: phase + unwrap,
+                group_delay: 0.0,
+            });
+        }
+        // The negative derivative of phase against angular frequency, by central differences.
+        for i in 0..points.len() {
+            let (a, b) = (points[i.saturating_sub(1)], points[(i + 1).min(points.len() - 1)]);
+            if b.frequency > a.frequency {
+                points[i].group_delay = -(b.phase - a.phase) / (2.0 * PI * (b.frequency - a.frequency));
+            }
+        }
+        points.retain(|x| x.frequency >= lowest && x.frequency <= highest);
+
+        ifft(&mut h_re, &mut h_im);
+        let impulse = h_re[..self.length].iter().map(|&x| x as Data).collect();
+        (points, impulse)
+    }
+
+    fn thd(&self, controls: &[(usize, Data)], frequency: f64) -> Thd {
+        let sine = signal::sine(self.length, frequency, self.sample_rate, AMPLITUDE);
+        let output = self.render(controls, &sine);
+        // Skip the first half, so the plugin has settled.
+        let frame = self.length / 2;
+        let window = fft::hann(frame);
+        let mut re: Vec<f64> = output[frame..self.length].iter().zip(window.iter()).map(|(&x, w)| x as f64 * w).collect();
+        let mut im = vec![0.0; frame];
+        fft(&mut re, &mut im);
+
+        let power_near = |f: f64| {
+            let bin = (f * frame as f64 / self.sample_rate as f64).round() as usize;
+            (bin.saturating_sub(HARMONIC_WIDTH)..=(bin + HARMONIC_WIDTH).min(frame / 2))
+                .map(|i| re[i] * re[i] + im[i] * im[i])
+                .sum::<f64>()
+        };
+        let fundamental = power_near(frequency);
+        let harmonics: f64 = (2..=MAX_HARMONIC)
+            .map(|k| k as f64 * frequency)
+            .take_while(|&f| f < self.sample_rate as f64 / 2.0)
+            .map(power_near)
+            .fold(0.0, |sum, x| sum + x);
+        Thd { frequency, ratio: (harmonics / fundamental).sqrt() }
+    }
+
+    fn measure(&self, controls: &[(usize, Data)]) -> Response {
+        let (points, impulse) = self.frequency_response(controls);
+        let nyquist = self.sample_rate as f64 / 2.0;
+        let thd = self
+            .thd_frequencies
+            .iter()
+            .filter(|&&f| f > 0.0 && f < nyquist)
+            .map(|&f| self.thd(controls, f))
+            .collect();
+        Response { sample_rate: self.sample_rate, points, impulse, thd }
+    }
+
+    /**
+     * Measures the response with the configured controls.
+     *
+     * # Panics
+     * Panics if the plugin cannot be instantiated, does not have the output or a control is not a
+     * control input.
+     */
+    pub fn run(&self) -> Response {
+        self.measure(&self.controls)
+    }
+
+    /**
+     * ```count``` values for ```sweep_control``` spanning the bounds of the control input
+     * ```port```, spaced geometrically for ```HINT_LOGARITHMIC``` ports with positive bounds and
+     * rounded for ```HINT_INTEGER``` ports, without repeats. ```HINT_TOGGLED``` ports give 0 and 1.
+     * Returns ```None``` if the port is not a control input or lacks a bound.
+     */
+    pub fn sweep_values(&self, port: usize, count: usize) -> Option<Vec<Data>> {
+        let ports = self.plugin.ports();
+        let port = &ports.get(port).filter(|x| x.port.desc == PortDescriptor::ControlInput)?.port;
+        let hint = port.hint.unwrap_or(ControlHint::empty());
+        if hint.contains(ControlHint::HINT_TOGGLED) {
+            return Some(vec![0.0, 1.0]);
+        }
+        let lower = port.resolved_lower_bound(self.sample_rate)? as f64;
+        let upper = port.resolved_upper_bound(self.sample_rate)? as f64;
+        let logarithmic = hint.contains(ControlHint::HINT_LOGARITHMIC) && lower > 0.0 && upper > 0.0;
+        let mut values: Vec<Data> = (0..count)
+            .map(|i| {
+                let weight = if count > 1 { i as f64 / (count - 1) as f64 } else { 0.0 };
+                let value = if logarithmic {
+                    lower * (upper / lower).powf(weight)
+                } else {
+                    lower + (upper - lower) * weight
+                };
+                if hint.contains(ControlHint::HINT_INTEGER) { value.round() as Data } else { value as Data }
+            })
+            .collect();
+        values.dedup();
+        Some(values)
+    }
+
+    /**
+     * Measures the response with the control input ```port``` set to each of ```values``` in
+     * turn, on top of the configured controls.
+     *
+     * # Panics
+     * Panics if the plugin cannot be instantiated, does not have the output or a control is not a
+     * control input.
+     */
+    pub fn sweep_control(&self, port: usize, values: &[Data]) -> Vec<(Data, Response)> {
+        values
+            .iter()
+            .map(|&value| {
+                let mut controls = self.controls.clone();
+                controls.push((port, value));
+                (value, self.measure(&controls))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,331 @@
+/*!
+ * Checks plugins against the LADSPA specification and for numeric hygiene, producing a pass/fail
+ * report per plugin.
+ *
+ * Plugins declaring ```PROP_HARD_REALTIME_CAPABLE``` are also checked not to allocate in ```run```
+ * when ```realtime::CheckedAlloc``` is the global allocator.
+ */
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use super::{first_difference, noise_inputs, realtime, signal};
+use crate::ffi::ladspa_h;
+use crate::host::{own_plugins, Instance, Library, PluginRef, PortInfo};
+use crate::{ControlHint, Data, DefaultValue, PortDescriptor, Properties};
+
+const BLOCK_SIZE: usize = 256;
+const BLOCKS: usize = 8;
+
+/// The outcome of one check.
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<(), String>,
+}
+
+/// The outcome of all checks for one plugin.
+pub struct Report {
+    pub unique_id: u64,
+    pub label: String,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.result.is_ok())
+    }
+
+    fn check(&mut self, name: &'static str, result: Result<(), String>) {
+        self.checks.push(Check { name, result });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ({}): {}", self.label, self.unique_id,
+                 if self.passed() { "PASS" } else { "FAIL" })?;
+        for check in self.checks.iter() {
+            match check.result {
+                Ok(()) => writeln!(f, "  PASS {}", check.name)?,
+                Err(ref msg) => writeln!(f, "  FAIL {}: {}", check.name, msg)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validates every plugin of a shared library.
+pub fn validate_library(path: &Path, sample_rate: u64) -> io::Result<Vec<Report>> {
+    Ok(Library::open(path)?.plugins().into_iter().map(|p| validate(p, sample_rate)).collect())
+}
+
+/// Validates every plugin exported by this library.
+pub fn validate_own(sample_rate: u64) -> Vec<Report> {
+    own_plugins().into_iter().map(|p| validate(p, sample_rate)).collect()
+}
+
+/// Runs all checks on one plugin. Rendering checks are skipped if the descriptor is unusable.
+pub fn validate(plugin: PluginRef, sample_rate: u64) -> Report {
+    let mut report = Report {
+        unique_id: plugin.unique_id(),
+        label: plugin.label(),
+        checks: Vec::new(),
+    };
+    report.check("callbacks", check_callbacks(plugin));
+    report.check("ports", check_ports(plugin));
+    report.check("hints", check_hints(plugin));
+    if !report.passed() {
+        return report;
+    }
+    report.check("instantiate", plugin.instantiate(sample_rate).map(|_| ()).ok_or_else(|| {
+        "instantiate returned NULL".to_string()
+    }));
+    if !report.passed() {
+        return report;
+    }
+    report.check("zero-length run", check_zero_length(plugin, sample_rate));
+    report.check("deterministic", check_deterministic(plugin, sample_rate));
+    report.check("in-place", check_in_place(plugin, sample_rate));
+    report.check("silence", check_finite(plugin, sample_rate, &signal::silence(BLOCK_SIZE * BLOCKS)));
+    report.check("full-scale sine",
+                 check_finite(plugin, sample_rate, &signal::sine(BLOCK_SIZE * BLOCKS, 997.0, sample_rate, 1.0)));
+    report.check("full-scale nyquist", check_finite(plugin, sample_rate, &signal::nyquist(BLOCK_SIZE * BLOCKS)));
+    report.check("control outputs", check_control_outputs(plugin, sample_rate));
+    report.check("hard realtime", check_hard_realtime(plugin, sample_rate));
+    report
+}
+
+fn check_callbacks(plugin: PluginRef) -> Result<(), String> {
+    let desc = plugin.0;
+    let mut missing = Vec::new();
+    if desc.instantiate.is_none() {
+        missing.push("instantiate");
+    }
+    if desc.connect_port.is_none() {
+        missing.push("connect_port");
+    }
+    if desc.run.is_none() {
+        missing.push("run");
+    }
+    if desc.cleanup.is_none() {
+        missing.push("cleanup");
+    }
+    if !missing.is_empty() {
+        return Err(format!("required callbacks are NULL: {}", missing.join(", ")));
+    }
+    if desc.run_adding.is_some() != desc.set_run_adding_gain.is_some() {
+        return Err("run_adding and set_run_adding_gain must be provided together".to_string());
+    }
+    Ok(())
+}
+
+fn check_ports(plugin: PluginRef) -> Result<(), String> {
+    let desc = plugin.0;
+    if desc.label.is_null() || desc.name.is_null() || desc.maker.is_null() || desc.copyright.is_null() {
+        return Err("label, name, maker and copyright must not be NULL".to_string());
+    }
+    if plugin.label().contains(char::is_whitespace) {
+        return Err(format!("label {:?} contains whitespace", plugin.label()));
+    }
+    if desc.port_count > 0 &&
+       (desc.port_descriptors.is_null() || desc.port_names.is_null() || desc.port_range_hints.is_null()) {
+        return Err("port arrays must not be NULL".to_string());
+    }
+    for (i, port) in plugin.ports().iter().enumerate() {
+        if port.port.desc == PortDescriptor::Invalid {
+            return Err(format!("port {} ({:?}) must be exactly one of input/output and audio/control",
+                               i, port.name));
+        }
+    }
+    Ok(())
+}
+
+fn check_hint(port: &PortInfo) -> Result<(), String> {
+    let bits = port.hint_descriptor;
+    let lower = port.port.lower_bound;
+    let upper = port.port.upper_bound;
+    if let (Some(lower), Some(upper)) = (lower, upper) && lower > upper {
+        return Err(format!("lower bound {} is above upper bound {}", lower, upper));
+    }
+    if bits & ladspa_h::HINT_DEFAULT_MASK != ladspa_h::HINT_DEFAULT_NONE && port.port.default.is_none() {
+        return Err(format!("unknown default {:#x}", bits & ladspa_h::HINT_DEFAULT_MASK));
+    }
+    let needs = match port.port.default {
+        Some(DefaultValue::Minimum) => (true, false),
+        Some(DefaultValue::Maximum) => (false, true),
+        Some(DefaultValue::Low) | Some(DefaultValue::Middle) | Some(DefaultValue::High) => (true, true),
+        _ => (false, false),
+    };
+    if (needs.0 && lower.is_none()) || (needs.1 && upper.is_none()) {
+        return Err(format!("default {:?} requires bounds that are not set", port.port.default.unwrap()));
+    }
+    let hint = port.port.hint.unwrap_or(ControlHint::empty());
+    if hint.contains(ControlHint::HINT_TOGGLED) {
+        let other = hint - ControlHint::HINT_TOGGLED;
+        let default_ok = matches!(port.port.default, None | Some(DefaultValue::Value0) | Some(DefaultValue::Value1));
+        if !other.is_empty() || lower.is_some() || upper.is_some() || !default_ok {
+            return Err("HINT_TOGGLED may only be combined with a default of 0 or 1".to_string());
+        }
+    }
+    if hint.contains(ControlHint::HINT_LOGARITHMIC) && needs != (false, false) &&
+       (lower.is_some_and(|x| x <= 0.0) || upper.is_some_and(|x| x <= 0.0)) {
+        return Err("a logarithmic default requires positive bounds".to_string());
+    }
+    Ok(())
+}
+
+fn check_hints(plugin: PluginRef) -> Result<(), String> {
+    for (i, port) in plugin.ports().iter().enumerate() {
+        let kind = port.port.desc;
+        if kind != PortDescriptor::ControlInput && kind != PortDescriptor::ControlOutput {
+            continue;
+        }
+        check_hint(port).map_err(|e| format!("port {} ({:?}): {}", i, port.name, e))?;
+    }
+    Ok(())
+}
+
+fn new_instance(plugin: PluginRef, sample_rate: u64) -> Result<Instance, String> {
+    let mut instance = plugin.instantiate(sample_rate).ok_or("instantiate returned NULL")?;
+    instance.activate();
+    Ok(instance)
+}
+
+// A run of 0 samples must neither write the outputs nor change what later runs produce.
+fn check_zero_length(plugin: PluginRef, sample_rate: u64) -> Result<(), String> {
+    const UNTOUCHED: Data = 1234.5;
+    let length = BLOCK_SIZE * BLOCKS;
+    let inputs = noise_inputs(plugin, 1, length);
+    let expected = new_instance(plugin, sample_rate)?.render(&inputs, length, [BLOCK_SIZE; BLOCKS]);
+
+    let mut instance = new_instance(plugin, sample_rate)?;
+    let audio_inputs = plugin.ports_of(PortDescriptor::AudioInput);
+    let audio_outputs = plugin.ports_of(PortDescriptor::AudioOutput);
+    let mut outputs = vec![vec![UNTOUCHED; BLOCK_SIZE]; audio_outputs.len()];
+    unsafe {
+        for (&port, input) in audio_inputs.iter().zip(inputs.iter()) {
+            instance.connect(port, input.as_ptr() as *mut Data);
+        }
+        for (&port, output) in audio_outputs.iter().zip(outputs.iter_mut()) {
+            instance.connect(port, output.as_mut_ptr());
+        }
+        instance.run_raw(0);
+    }
+    for (channel, output) in outputs.iter().enumerate() {
+        if let Some(i) = output.iter().position(|&x| x != UNTOUCHED) {
+            return Err(format!("output {} was written at sample {}", channel, i));
+        }
+    }
+    let actual = instance.render(&inputs, length, [BLOCK_SIZE; BLOCKS]);
+    compare(&expected, &actual, "the following runs")
+}
+
+fn check_deterministic(plugin: PluginRef, sample_rate: u64) -> Result<(), String> {
+    let length = BLOCK_SIZE * BLOCKS;
+    let inputs = noise_inputs(plugin, 1, length);
+    let first = new_instance(plugin, sample_rate)?.render(&inputs, length, [BLOCK_SIZE; BLOCKS]);
+    let second = new_instance(plugin, sample_rate)?.render(&inputs, length, [BLOCK_SIZE; BLOCKS]);
+    compare(&first, &second, "second instance")
+}
+
+fn compare(expected: &[Vec<Data>], actual: &[Vec<Data>], what: &str) -> Result<(), String> {
+    match first_difference(expected, actual, 0.0) {
+        Some(difference) => Err(format!("{}: {}", what, difference)),
+        None => Ok(()),
+    }
+}
+
+fn check_in_place(plugin: PluginRef, sample_rate: u64) -> Result<(), String> {
+    if plugin.properties().contains(Properties::PROP_INPLACE_BROKEN) {
+        return Ok(());
+    }
+    let length = BLOCK_SIZE * BLOCKS;
+    let inputs = noise_inputs(plugin, 1, length);
+    let outputs = plugin.ports_of(PortDescriptor::AudioOutput);
+    let shared = inputs.len().min(outputs.len());
+    if shared == 0 {
+        return Ok(());
+    }
+    let expected = new_instance(plugin, sample_rate)?.render(&inputs, length, [BLOCK_SIZE; BLOCKS]);
+
+    // Connect the first inputs to the same buffers as the outputs, holding the input data.
+    let mut instance = new_instance(plugin, sample_rate)?;
+    let mut buffers: Vec<Vec<Data>> = (0..outputs.len())
+        .map(|i| if i < shared { inputs[i].clone() } else { vec![0.0; length] })
+        .collect();
+    let audio_inputs = plugin.ports_of(PortDescriptor::AudioInput);
+    for pos in (0..length).step_by(BLOCK_SIZE) {
+        unsafe {
+            for (i, &port) in audio_inputs.iter().enumerate() {
+                let location = if i < shared {
+                    buffers[i][pos..].as_mut_ptr()
+                } else {
+                    inputs[i][pos..].as_ptr() as *mut Data
+                };
+                instance.connect(port, location);
+            }
+            for (i, &port) in outputs.iter().enumerate() {
+                instance.connect(port, buffers[i][pos..].as_mut_ptr());
+            }
+            instance.run_raw(BLOCK_SIZE);
+        }
+    }
+    compare(&expected, &buffers, "in-place processing")
+        .map_err(|e| format!("{} (set PROP_INPLACE_BROKEN if this is intended)", e))
+}
+
+fn check_finite(plugin: PluginRef, sample_rate: u64, input: &[Data]) -> Result<(), String> {
+    let inputs = vec![input.to_vec(); plugin.ports_of(PortDescriptor::AudioInput).len()];
+    let mut instance = new_instance(plugin, sample_rate)?;
+    let outputs = instance.render(&inputs, input.len(), [BLOCK_SIZE; BLOCKS]);
+    for (channel, output) in outputs.iter().enumerate() {
+        for (i, &x) in output.iter().enumerate() {
+            if !x.is_finite() {
+                return Err(format!("output {} is {} at sample {}", channel, x, i));
+            }
+            if x.is_subnormal() {
+                return Err(format!("output {} is denormal ({:e}) at sample {}", channel, x, i));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_control_outputs(plugin: PluginRef, sample_rate: u64) -> Result<(), String> {
+    let control_outputs = plugin.ports_of(PortDescriptor::ControlOutput);
+    if control_outputs.is_empty() {
+        return Ok(());
+    }
+    let mut instance = new_instance(plugin, sample_rate)?;
+    let inputs = noise_inputs(plugin, 1, BLOCK_SIZE);
+    for block in 0..BLOCKS {
+        for &port in control_outputs.iter() {
+            instance.write_control(port, Data::NAN);
+        }
+        instance.render(&inputs, BLOCK_SIZE, [BLOCK_SIZE]);
+        for &port in control_outputs.iter() {
+            let value = instance.control(port);
+            if value.is_nan() {
+                return Err(format!("port {} ({:?}) was not written in block {}",
+                                   port, instance.ports()[port].name, block));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_hard_realtime(plugin: PluginRef, sample_rate: u64) -> Result<(), String> {
+    if !plugin.properties().contains(Properties::PROP_HARD_REALTIME_CAPABLE) || !realtime::installed() {
+        return Ok(());
+    }
+    let length = BLOCK_SIZE * BLOCKS;
+    let inputs = noise_inputs(plugin, 1, length);
+    let mut instance = new_instance(plugin, sample_rate)?;
+    realtime::take_violations();
+    instance.render(&inputs, length, [BLOCK_SIZE; BLOCKS]);
+    match realtime::take_violations().first() {
+        Some(violation) => Err(format!("PROP_HARD_REALTIME_CAPABLE is declared, but found {}", violation)),
+        None => Ok(()),
+    }
+}
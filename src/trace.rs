@@ -0,0 +1,392 @@
+/*!
+ * Tracing of the calls hosts make into the exported functions, for finding out what a particular
+ * host actually does.
+ *
+ * Setting ```LADSPA_RS_TRACE``` to a file path makes every instance record each call it receives
+ * in that file, along with the values of its control inputs at every ```run```. Every instance
+ * has its own lock-free ring buffer of events, which a background thread drains every few
+ * milliseconds, so the audio thread never waits for I/O or a lock; when a ring is full, events
+ * are dropped and a ```# dropped``` line says how many. The thread runs while instances exist:
+ * the ```cleanup``` of the last one writes the remaining events and stops it, so the trace is
+ * complete and no thread is left running code from a library that the host then unloads.
+ *
+ * Each line holds the time in nanoseconds since tracing started, the instance id, the call and
+ * its arguments:
+ *
+ * ```text
+ * 1042 1 instantiate delay_5829 44100
+ * 2211 1 connect_port 0 0x7f3a5c000b40
+ * 9120 1 run 256
+ * 9135 1 control 4 0.3
+ * ```
+ *
+ * ```summarize``` turns a trace into a report per instance.
+ */
+
+use std::cell::UnsafeCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::Data;
+
+/// The environment variable naming the trace file.
+pub const TRACE_VAR: &str = "LADSPA_RS_TRACE";
+
+// The number of events an instance can buffer before events are dropped.
+const RING_SIZE: usize = 1 << 12;
+
+// How often the writer thread drains the rings.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Copy, Clone)]
+pub(crate) enum Call {
+    Instantiate { label: &'static str, sample_rate: u64 },
+    ConnectPort { port: u64, location: usize },
+    Activate,
+    Deactivate,
+    Run { call: &'static str, sample_count: u64 },
+    Control { port: usize, value: Data },
+    SetRunAddingGain { gain: Data },
+    Cleanup,
+}
+
+#[derive(Copy, Clone)]
+struct Event {
+    time: u64,
+    instance: usize,
+    call: Call,
+}
+
+// A single-producer, single-consumer ring of events. The producer is the instance, whose calls
+// never overlap; the consumer is the writer thread.
+struct Ring {
+    slots: Box<[UnsafeCell<MaybeUninit<Event>>]>,
+    // The number of events ever pushed and popped.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn new() -> Ring {
+        Ring {
+            slots: (0..RING_SIZE).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    // Only called by the producer.
+    fn push(&self, event: Event) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        if head - self.tail.load(Ordering::Acquire) == self.slots.len() {
+            return false;
+        }
+        unsafe { (*self.slots[head % self.slots.len()].get()).write(event) };
+        self.head.store(head + 1, Ordering::Release);
+        true
+    }
+
+    // Only called by the consumer.
+    fn pop(&self) -> Option<Event> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let event = unsafe { (*self.slots[tail % self.slots.len()].get()).assume_init() };
+        self.tail.store(tail + 1, Ordering::Release);
+        Some(event)
+    }
+}
+
+// The trace file and the number of dropped events written to it so far.
+type Output = (BufWriter<File>, u64);
+
+// The writer thread, which runs while there are recorders. It hands the output back when it
+// stops, for the thread of the next recorder.
+struct Writer {
+    recorders: usize,
+    thread: Option<JoinHandle<Output>>,
+    output: Option<Output>,
+}
+
+struct Tracer {
+    start: Instant,
+    rings: Mutex<Vec<Arc<Ring>>>,
+    writer: Mutex<Writer>,
+    stop: AtomicBool,
+}
+
+static TRACER: OnceLock<Option<Tracer>> = OnceLock::new();
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+fn tracer() -> Option<&'static Tracer> {
+    TRACER.get_or_init(|| {
+        let path = env::var_os(TRACE_VAR)?;
+        let file = match File::create(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("LADSPA trace: cannot create {:?}: {}", path, e);
+                return None;
+            }
+        };
+        Some(Tracer {
+            start: Instant::now(),
+            rings: Mutex::new(Vec::new()),
+            writer: Mutex::new(Writer { recorders: 0, thread: None, output: Some((BufWriter::new(file), 0)) }),
+            stop: AtomicBool::new(false),
+        })
+    }).as_ref()
+}
+
+impl Tracer {
+    // Counts a new recorder, starting the writer thread for the first one.
+    fn attach(&'static self) {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.recorders += 1;
+        if writer.thread.is_none() && let Some(output) = writer.output.take() {
+            self.stop.store(false, Ordering::Relaxed);
+            match thread::Builder::new().name("ladspa-trace".to_string()).spawn(move || self.write_events(output)) {
+                Ok(thread) => writer.thread = Some(thread),
+                Err(e) => eprintln!("LADSPA trace: cannot start the writer thread: {}", e),
+            }
+        }
+    }
+
+    // Uncounts a recorder. After the last one, the writer thread writes what is left and stops.
+    fn detach(&self) {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writer.recorders -= 1;
+        if writer.recorders == 0 && let Some(thread) = writer.thread.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            thread.thread().unpark();
+            writer.output = thread.join().ok();
+        }
+    }
+
+    fn write_events(&self, (mut out, mut dropped): Output) -> Output {
+        let mut events = Vec::new();
+        loop {
+            thread::park_timeout(POLL_INTERVAL);
+            // Checked before draining, so that the last drain sees every event.
+            let stop = self.stop.load(Ordering::Relaxed);
+            {
+                let mut rings = self.rings.lock().unwrap_or_else(|e| e.into_inner());
+                for ring in rings.iter() {
+                    events.extend(std::iter::from_fn(|| ring.pop()));
+                }
+                // Forget the rings of instances that have been cleaned up, now that they are empty.
+                rings.retain(|ring| Arc::strong_count(ring) > 1);
+            }
+            events.sort_by_key(|event| event.time);
+            for event in events.drain(..) {
+                let _ = write_event(&mut out, &event);
+            }
+            let total = DROPPED.load(Ordering::Relaxed);
+            if total != dropped {
+                let _ = writeln!(out, "# dropped {}", total - dropped);
+                dropped = total;
+            }
+            let _ = out.flush();
+            if stop {
+                return (out, dropped);
+            }
+        }
+    }
+}
+
+/**
+ * Records the calls of one instance. Ids are unique within the process. Creating a recorder
+ * registers its ring with the writer thread and dropping the last one waits for that thread, so
+ * do both outside realtime callbacks; recording neither blocks nor allocates.
+ */
+pub(crate) struct Recorder {
+    id: usize,
+    ring: Option<Arc<Ring>>,
+}
+
+impl Recorder {
+    pub(crate) fn new() -> Recorder {
+        let ring = tracer().map(|tracer| {
+            let ring = Arc::new(Ring::new());
+            tracer.rings.lock().unwrap_or_else(|e| e.into_inner()).push(ring.clone());
+            tracer.attach();
+            ring
+        });
+        Recorder { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), ring }
+    }
+
+    /// Whether calls are being traced.
+    pub(crate) fn enabled(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    pub(crate) fn record(&self, call: Call) {
+        if let (Some(ring), Some(tracer)) = (self.ring.as_ref(), tracer()) {
+            let time = tracer.start.elapsed().as_nanos() as u64;
+            if !ring.push(Event { time, instance: self.id, call }) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Release the ring first, so that the last drain forgets it.
+        if self.ring.take().is_some() && let Some(tracer) = tracer() {
+            tracer.detach();
+        }
+    }
+}
+
+fn write_event(out: &mut impl Write, event: &Event) -> std::io::Result<()> {
+    write!(out, "{} {} ", event.time, event.instance)?;
+    match event.call {
+        Call::Instantiate { label, sample_rate } => writeln!(out, "instantiate {} {}", label, sample_rate),
+        Call::ConnectPort { port, location } => writeln!(out, "connect_port {} {:#x}", port, location),
+        Call::Activate => writeln!(out, "activate"),
+        Call::Deactivate => writeln!(out, "deactivate"),
+        Call::Run { call, sample_count } => writeln!(out, "{} {}", call, sample_count),
+        Call::Control { port, value } => writeln!(out, "control {} {:?}", port, value),
+        Call::SetRunAddingGain { gain } => writeln!(out, "set_run_adding_gain {:?}", gain),
+        Call::Cleanup => writeln!(out, "cleanup"),
+    }
+}
+
+#[derive(Default)]
+struct ControlSummary {
+    min: Data,
+    max: Data,
+    last: Option<Data>,
+    changes: usize,
+}
+
+#[derive(Default)]
+struct InstanceSummary {
+    label: String,
+    sample_rate: String,
+    first: u64,
+    last: u64,
+    calls: BTreeMap<String, usize>,
+    blocks: usize,
+    samples: u64,
+    min_block: u64,
+    max_block: u64,
+    null_connections: usize,
+    runs_inactive: usize,
+    active: bool,
+    controls: BTreeMap<usize, ControlSummary>,
+}
+
+/**
+ * Summarises a trace written with ```LADSPA_RS_TRACE```: for every instance, the calls it
+ * received, the block sizes it ran with, NULL connections, runs while inactive and the range of
+ * every control input.
+ */
+pub fn summarize(trace: &str) -> String {
+    let mut instances: BTreeMap<usize, InstanceSummary> = BTreeMap::new();
+    let mut dropped = 0;
+    let mut unparsed = 0;
+    for line in trace.lines() {
+        if let Some(n) = line.strip_prefix("# dropped ") {
+            dropped += n.trim().parse::<u64>().unwrap_or(0);
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (time, id) = match (fields.first().and_then(|x| x.parse::<u64>().ok()),
+                                fields.get(1).and_then(|x| x.parse::<usize>().ok())) {
+            (Some(time), Some(id)) if fields.len() > 2 => (time, id),
+            _ => {
+                unparsed += 1;
+                continue;
+            }
+        };
+        let call = fields[2];
+        let arg = |i: usize| fields.get(3 + i).copied().unwrap_or("");
+        let instance = instances.entry(id).or_insert_with(|| InstanceSummary { first: time, ..Default::default() });
+        instance.last = time;
+        if call != "control" {
+            *instance.calls.entry(call.to_string()).or_insert(0) += 1;
+        }
+        match call {
+            "instantiate" => {
+                instance.label = arg(0).to_string();
+                instance.sample_rate = arg(1).to_string();
+            }
+            "connect_port" if arg(1) == "0x0" => instance.null_connections += 1,
+            "activate" => instance.active = true,
+            "deactivate" => instance.active = false,
+            "run" | "run_adding" | "run_synth" => {
+                let n = arg(0).parse::<u64>().unwrap_or(0);
+                if instance.blocks == 0 || n < instance.min_block {
+                    instance.min_block = n;
+                }
+                instance.max_block = instance.max_block.max(n);
+                instance.blocks += 1;
+                instance.samples += n;
+                if !instance.active {
+                    instance.runs_inactive += 1;
+                }
+            }
+            "control" => {
+                if let (Ok(port), Ok(value)) = (arg(0).parse::<usize>(), arg(1).parse::<Data>()) {
+                    let control = instance.controls.entry(port).or_default();
+                    match control.last {
+                        None => {
+                            control.min = value;
+                            control.max = value;
+                        }
+                        Some(last) if last.to_bits() != value.to_bits() => control.changes += 1,
+                        Some(_) => {}
+                    }
+                    control.min = control.min.min(value);
+                    control.max = control.max.max(value);
+                    control.last = Some(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut summary = String::new();
+    for (id, instance) in instances.iter() {
+        writeln!(summary, "instance {}: {} at {} Hz, {:.3} s",
+                 id, instance.label, instance.sample_rate,
+                 (instance.last - instance.first) as f64 / 1e9).unwrap();
+        let calls: Vec<String> = instance.calls.iter().map(|(call, n)| format!("{} {}", call, n)).collect();
+        writeln!(summary, "  calls: {}", calls.join(", ")).unwrap();
+        if instance.blocks > 0 {
+            writeln!(summary, "  blocks: {}, {} samples, {} to {} per block, {:.1} on average",
+                     instance.blocks, instance.samples, instance.min_block, instance.max_block,
+                     instance.samples as f64 / instance.blocks as f64).unwrap();
+        }
+        if instance.null_connections > 0 {
+            writeln!(summary, "  NULL connections: {}", instance.null_connections).unwrap();
+        }
+        if instance.runs_inactive > 0 {
+            writeln!(summary, "  runs while inactive: {}", instance.runs_inactive).unwrap();
+        }
+        for (port, control) in instance.controls.iter() {
+            writeln!(summary, "  control {}: {:?} to {:?}, {} changes",
+                     port, control.min, control.max, control.changes).unwrap();
+        }
+    }
+    if dropped > 0 {
+        writeln!(summary, "dropped events: {}", dropped).unwrap();
+    }
+    if unparsed > 0 {
+        writeln!(summary, "unparsed lines: {}", unparsed).unwrap();
+    }
+    summary
+}